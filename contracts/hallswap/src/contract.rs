@@ -1,27 +1,43 @@
+use std::collections::BTreeMap;
+
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    from_json, to_json_binary, Addr, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env,
-    MessageInfo, Response, StdResult, Uint128, WasmMsg,
+    from_json, to_json_binary, Addr, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Empty, Env,
+    MessageInfo, Order, Reply, Response, StdError, StdResult, Storage, SubMsg, SubMsgResult,
+    Uint128, WasmMsg,
 };
 use cw2::set_contract_version;
 use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw_storage_plus::{Bound, Map};
+use math::{stableswap, xyk};
 use models::asset::Asset;
 use models::asset_info::AssetInfo;
+use querier::msg::{AstroportPairType, PairInfo};
 use querier::querier::{
-    query_astrovault_hybrid_simulation, query_astrovault_pool_info,
-    query_astrovault_stable_simulation, query_balance, query_contract_info, query_helix_simulation,
-    query_simulation,
+    query_astroport_precision, query_astroport_stable_amp, query_astrovault_hybrid_simulation,
+    query_astrovault_pool_info, query_astrovault_stable_simulation, query_balance,
+    query_contract_info, query_helix_simulation, query_nft_owner, query_pool, query_simulation,
+    query_token_balance,
 };
 
 use crate::error::ContractError;
 use crate::msg::{
-    AstrovaultHybridExecuteMsg, AstrovaultStableExecuteMsg, AstrovaultXykExecuteMsg,
-    Cw20AstrovaultXykExecuteMsg, ExecuteMsg, HelixExecuteMsg, InstantiateMsg, Interface,
-    PairCw20HookMsg, PairExecuteMsg, PairType, QueryMsg, QuerySimulationResult, RouteInfo,
-    RouteInfoV2, SwapInterface, SwapOperation,
+    AccruedFee, AdjustedHop, AstrovaultHybridExecuteMsg, AstrovaultStableExecuteMsg,
+    AstrovaultXykExecuteMsg, BurnedFee, Cw20AstrovaultXykExecuteMsg, Deadline,
+    EffectiveFeeResponse, ExecuteMsg, HelixExecuteMsg, InstantiateMsg, Interface,
+    MinimumReceiveMode, PairCw20HookMsg, PairExecuteMsg, PairType, PostSwapOutput, QueryMsg,
+    QuerySimulationResult, Referral, RouteDiagnostic, RouteInfo, RouteInfoV2, RouteInfoV3,
+    RouteIssue, SplitOperation, SwapInterface, SwapOperation, SwapOutput, UserTierResponse,
+    ValidateRoutesResponse,
+};
+use crate::state::{
+    Config, FeeConversion, FeeDiscount, FeeRecipient, FeeableReturn, HoldingCondition, Integrator,
+    PartialRoute, VolumeBucket, VolumeTier, VolumeTiers, ACCRUED_FEES, ASSET_FEES, BURNED_FEES,
+    CONFIG, DIRECT_RETURN, FEEABLE_RETURNS, FEES_COLLECTED, FEE_CONVERSION, FEE_CONVERSION_ROUTES,
+    FEE_DISCOUNTS, FEE_EXEMPTIONS, INTEGRATORS, INTEGRATOR_FEES, INTEGRATOR_VOLUMES, LEGACY_CONFIG,
+    PAIR_FEES, PARTIAL_ROUTES, REFERRAL_FEES_COLLECTED, RESERVED_FEES, USER_VOLUMES, VOLUME_TIERS,
 };
-use crate::state::{Config, CONFIG, FEES_COLLECTED};
 
 const CONTRACT_NAME: &str = "crates.io:hallswap";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -29,6 +45,19 @@ const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 // Use max allowed values to bypass all slippage calculations on the pool contract
 const BELIEF_PRICE: Decimal = Decimal::MAX;
 const MAX_SLIPPAGE: Decimal = Decimal::raw(500_000_000_000_000_000u128); // 0.5 = 50%
+/// Highest number of buckets the rolling window of the volume tiers can be split into
+const MAX_VOLUME_BUCKETS: u64 = 60;
+/// Highest number of fee discounts, each one costing a query per swap
+const MAX_FEE_DISCOUNTS: usize = 10;
+/// Highest number of fee recipients, each one costing a transfer per claimed asset
+const MAX_FEE_RECIPIENTS: usize = 10;
+/// Reply id of the last hops paying the receiver directly, apart from the route indexes of
+/// partial swaps
+const DIRECT_RETURN_REPLY_ID: u64 = u64::MAX;
+
+// Pagination of the listing queries
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -37,20 +66,19 @@ pub fn instantiate(
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    let fee_recipients = msg_fee_recipients(&msg)?;
     let config = Config {
         owner: match msg.owner {
             Some(owner) => owner,
             None => info.sender.clone(),
         },
-        fee_address: match msg.fee_address {
-            Some(fee_address) => fee_address,
-            None => info.sender.clone(),
-        },
+        fee_recipients: fee_recipients.unwrap_or(vec![FeeRecipient {
+            address: info.sender.clone(),
+            weight: 1,
+        }]),
         fee_bps: msg.fee_bps.unwrap_or(0),
-        fee_assets: match msg.fee_assets {
-            Some(fee_assets) => fee_assets,
-            None => vec![],
-        },
+        fee_assets: msg.fee_assets.unwrap_or_default(),
+        max_referral_bps: msg.max_referral_bps.unwrap_or(0),
     };
 
     CONFIG.save(deps.storage, &config)?;
@@ -65,17 +93,20 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
+    assert_deadline(&env, msg.deadline())?;
     match msg {
-        ExecuteMsg::Receive(cw20_msg) => receive_cw20(deps, env, cw20_msg),
+        ExecuteMsg::Receive(cw20_msg) => receive_cw20(deps, env, info, cw20_msg),
         ExecuteMsg::ExecuteRoutes {
             offer_asset_info,
             routes,
             minimum_receive,
             to,
+            ..
         } => swap_deprec(
             deps,
             env,
             info.sender,
+            native_assets(&info.funds),
             offer_asset_info,
             routes,
             minimum_receive,
@@ -85,23 +116,139 @@ pub fn execute(
             routes,
             minimum_receive,
             to,
-        } => swap(deps, env, info.sender, routes, minimum_receive, to),
-        ExecuteMsg::ExecuteSwapOp { operation, amount } => {
-            swap_pool(deps, env, info.sender, operation, amount)
+            allow_partial,
+            max_slippage_bps,
+            reference_quote,
+            minimum_receive_mode,
+            referral,
+            integrator,
+            ..
+        } => swap(
+            deps,
+            env,
+            info.sender,
+            native_assets(&info.funds),
+            routes,
+            minimum_receive,
+            to,
+            allow_partial.unwrap_or(false),
+            max_slippage_bps,
+            reference_quote,
+            minimum_receive_mode.unwrap_or(MinimumReceiveMode::RouterOutput {}),
+            referral,
+            integrator,
+        ),
+        ExecuteMsg::ExecuteRoutesV3 {
+            route,
+            minimum_receive,
+            to,
+            minimum_receive_mode,
+            ..
+        } => swap_v3(
+            deps,
+            env,
+            info.sender,
+            native_assets(&info.funds),
+            route,
+            minimum_receive,
+            to,
+            minimum_receive_mode.unwrap_or(MinimumReceiveMode::RouterOutput {}),
+        ),
+        ExecuteMsg::ExecuteRoutesMultiOutput {
+            routes,
+            outputs,
+            minimum_receive_mode,
+            ..
+        } => swap_multi_output(
+            deps,
+            env,
+            info.sender,
+            native_assets(&info.funds),
+            routes,
+            outputs,
+            minimum_receive_mode.unwrap_or(MinimumReceiveMode::RouterOutput {}),
+        ),
+        ExecuteMsg::ExecuteArbitrage {
+            routes,
+            minimum_profit,
+            to,
+            ..
+        } => arbitrage(
+            deps,
+            env,
+            info.sender,
+            native_assets(&info.funds),
+            routes,
+            minimum_profit,
+            to,
+        ),
+        ExecuteMsg::ExecuteSwapOp {
+            operation,
+            amount,
+            to,
+        } => swap_pool(deps, env, info.sender, operation, amount, to),
+        ExecuteMsg::ExecuteRoute { route, amount, to } => {
+            execute_route(env, info.sender, route, amount, to)
+        }
+        ExecuteMsg::ExecuteSettlePartialRoutes {
+            refund_to,
+            referral,
+            integrator,
+        } => settle_partial_routes(deps, env, info.sender, refund_to, referral, integrator),
+        ExecuteMsg::ExecuteSettleFeeConversion {
+            asset_info,
+            prev_balance,
+            minimum_receive,
+            burn,
+        } => settle_fee_conversion(
+            deps,
+            env,
+            info.sender,
+            asset_info,
+            prev_balance,
+            minimum_receive,
+            burn,
+        ),
+        ExecuteMsg::ExecuteSplitOp { operations, amount } => {
+            split_swap_pool(deps, env, info.sender, operations, amount)
         }
+        ExecuteMsg::ExecuteSnapshotReturn {
+            return_asset_info,
+            fee_bps,
+        } => snapshot_return(deps, env, info.sender, return_asset_info, fee_bps),
         ExecuteMsg::ExecutePostSwap {
-            offer_asset_info,
+            offer_assets,
+            outputs,
+            referral,
+            integrator,
+            fee_exempt,
+        } => post_swap(
+            deps,
+            env,
+            info.sender,
+            offer_assets,
+            outputs,
+            referral,
+            integrator,
+            fee_exempt,
+        ),
+        ExecuteMsg::ExecutePostArbitrage {
+            asset_info,
             offer_amount,
-            return_asset_info,
+            prev_balance,
+            minimum_profit,
             to,
-        } => post_swap(
+            fee_bps,
+        } => post_arbitrage(
             deps,
             env,
             info.sender,
-            offer_asset_info,
+            asset_info,
             offer_amount,
-            return_asset_info,
+            prev_balance,
+            minimum_profit,
             to,
+            fee_bps,
         ),
         ExecuteMsg::AssertMinimumReceive {
             receiver,
@@ -118,13 +265,134 @@ pub fn execute(
             minimum_receive,
         ),
         ExecuteMsg::UpdateConfig(config) => update_config(deps, info, config),
+        ExecuteMsg::RegisterIntegrator {
+            id,
+            address,
+            fee_share,
+        } => register_integrator(deps, info, id, address, fee_share),
+        ExecuteMsg::ClaimIntegratorFees { id } => claim_integrator_fees(deps, info, id),
+        ExecuteMsg::SetAssetFee {
+            asset_info,
+            fee_bps,
+        } => set_asset_fee(deps, info, asset_info, fee_bps),
+        ExecuteMsg::SetPairFee {
+            offer_asset_info,
+            return_asset_info,
+            fee_bps,
+        } => set_pair_fee(deps, info, offer_asset_info, return_asset_info, fee_bps),
+        ExecuteMsg::SetVolumeTiers { volume_tiers } => set_volume_tiers(deps, info, volume_tiers),
+        ExecuteMsg::SetFeeDiscounts { discounts } => set_fee_discounts(deps, info, discounts),
+        ExecuteMsg::UpdateFeeExemptions { add, remove } => {
+            update_fee_exemptions(deps, info, add, remove)
+        }
+        ExecuteMsg::ClaimFees { assets, recipient } => claim_fees(deps, info, assets, recipient),
+        ExecuteMsg::SetFeeConversion { fee_conversion } => {
+            set_fee_conversion(deps, info, fee_conversion)
+        }
+        ExecuteMsg::SetFeeConversionRoute { asset_info, route } => {
+            set_fee_conversion_route(deps, info, asset_info, route)
+        }
+        ExecuteMsg::ConvertFees {
+            assets,
+            minimum_receive,
+        } => convert_fees(deps, env, info, assets, minimum_receive),
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
     match msg {
-        QueryMsg::Simulation { routes } => Ok(to_json_binary(&simulation(deps, routes)?)?),
+        QueryMsg::Simulation {
+            routes,
+            referral,
+            sender,
+        } => {
+            let adjustment = fee_adjustment(deps, &env, sender.as_ref())?;
+            Ok(to_json_binary(&simulation(
+                deps,
+                routes,
+                referral.as_ref(),
+                &adjustment,
+            )?)?)
+        }
+        QueryMsg::SimulationArbitrage { routes, sender } => {
+            let adjustment = fee_adjustment(deps, &env, sender.as_ref())?;
+            Ok(to_json_binary(&simulation_arbitrage(
+                deps,
+                routes,
+                &adjustment,
+            )?)?)
+        }
+        QueryMsg::SimulationV3 { route, sender } => {
+            let adjustment = fee_adjustment(deps, &env, sender.as_ref())?;
+            Ok(to_json_binary(&simulation_v3(deps, route, &adjustment)?)?)
+        }
+        QueryMsg::ValidateRoutes { routes } => Ok(to_json_binary(&validate_routes(deps, routes))?),
+        QueryMsg::IntegratorFees { id } => Ok(to_json_binary(&integrator_assets(
+            deps,
+            &INTEGRATOR_FEES,
+            &id,
+        )?)?),
+        QueryMsg::IntegratorVolume { id } => Ok(to_json_binary(&integrator_assets(
+            deps,
+            &INTEGRATOR_VOLUMES,
+            &id,
+        )?)?),
+        QueryMsg::EffectiveFee {
+            offer_asset_info,
+            return_asset_info,
+        } => {
+            let config = CONFIG.load(deps.storage)?;
+            let fee_bps = effective_fee_bps(
+                deps.storage,
+                &config,
+                &offer_asset_info,
+                &return_asset_info,
+                &FeeAdjustment::default(),
+            )?;
+            Ok(to_json_binary(&EffectiveFeeResponse { fee_bps })?)
+        }
+        QueryMsg::UserTier { address } => Ok(to_json_binary(&user_tier(deps, &env, &address)?)?),
+        QueryMsg::AccruedFees { start_after, limit } => {
+            Ok(to_json_binary(&accrued_fees(deps, start_after, limit)?)?)
+        }
+        QueryMsg::FeeExemptions { start_after, limit } => {
+            Ok(to_json_binary(&fee_exemptions(deps, start_after, limit)?)?)
+        }
+        QueryMsg::BurnedFees { start_after, limit } => {
+            Ok(to_json_binary(&burned_fees(deps, start_after, limit)?)?)
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.result {
+        // A route of a partial swap failed, its offer amount is refunded once all routes ran
+        SubMsgResult::Err(reason) => {
+            let mut partial_route = PARTIAL_ROUTES.load(deps.storage, msg.id)?;
+            partial_route.failed = true;
+            PARTIAL_ROUTES.save(deps.storage, msg.id, &partial_route)?;
+            Ok(Response::new()
+                .add_attribute("failed_route", msg.id.to_string())
+                .add_attribute("failed_reason", reason))
+        }
+        // The last hop of a route paid the receiver, which `post_swap` cannot see
+        SubMsgResult::Ok(response) if msg.id == DIRECT_RETURN_REPLY_ID => {
+            let return_amount: Uint128 = response
+                .events
+                .iter()
+                .filter(|event| event.ty == "wasm")
+                .flat_map(|event| &event.attributes)
+                .find(|attribute| attribute.key == "return_amount")
+                .ok_or_else(|| StdError::generic_err("pool did not report its return amount"))?
+                .value
+                .parse()?;
+            let direct_return = DIRECT_RETURN.may_load(deps.storage)?.unwrap_or_default();
+            DIRECT_RETURN.save(deps.storage, &direct_return.checked_add(return_amount)?)?;
+            Ok(Response::default())
+        }
+        SubMsgResult::Ok(_) => Ok(Response::default()),
     }
 }
 
@@ -134,20 +402,23 @@ pub fn migrate(deps: DepsMut, env: Env, msg: InstantiateMsg) -> Result<Response,
     let fallback_owner = deps
         .api
         .addr_validate(&contract_info.admin.unwrap_or(contract_info.creator))?;
+    let fee_recipients = msg_fee_recipients(&msg)?;
     let config = Config {
         owner: match msg.owner {
             Some(owner) => owner,
             None => fallback_owner.clone(),
         },
-        fee_address: match msg.fee_address {
-            Some(fee_address) => fee_address,
-            None => fallback_owner.clone(),
+        // Configs stored with a single `fee_address` keep it as their only recipient
+        fee_recipients: match fee_recipients {
+            Some(fee_recipients) => fee_recipients,
+            None => stored_fee_recipients(deps.storage).unwrap_or(vec![FeeRecipient {
+                address: fallback_owner.clone(),
+                weight: 1,
+            }]),
         },
         fee_bps: msg.fee_bps.unwrap_or(0),
-        fee_assets: match msg.fee_assets {
-            Some(fee_assets) => fee_assets,
-            None => vec![],
-        },
+        fee_assets: msg.fee_assets.unwrap_or_default(),
+        max_referral_bps: msg.max_referral_bps.unwrap_or(0),
     };
 
     CONFIG.save(deps.storage, &config)?;
@@ -155,582 +426,3569 @@ pub fn migrate(deps: DepsMut, env: Env, msg: InstantiateMsg) -> Result<Response,
     Ok(Response::default())
 }
 
-/// See `swap` function for where fees are charged
+/// See `swap` function for where fees are charged.
 fn simulation(
     deps: Deps,
     routes: Vec<RouteInfoV2>,
+    referral: Option<&Referral>,
+    adjustment: &FeeAdjustment,
 ) -> Result<QuerySimulationResult, ContractError> {
+    Ok(simulate_routes(deps, routes, referral, adjustment)?.result)
+}
+
+/// Simulation of a swap, along with the assets every route holds before and after each hop
+struct RoutesSimulation {
+    result: QuerySimulationResult,
+    /// Offer asset of every route, fee included, followed by the return asset of each hop
+    route_assets: Vec<Vec<Asset>>,
+}
+
+/// Routes are simulated in the order `swap` executes them, so that pools used by several hops
+/// are quoted with the state left by the previous ones, see `simulate_shared_operation`.
+fn simulate_routes(
+    deps: Deps,
+    routes: Vec<RouteInfoV2>,
+    referral: Option<&Referral>,
+    adjustment: &FeeAdjustment,
+) -> Result<RoutesSimulation, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    let (offer_asset_info, return_asset_info) = get_offer_return_asset(&routes)?;
-    let mut fee_asset_info = offer_asset_info.clone();
-    let mut fee_asset_amount = Uint128::zero();
+    assert_referral(&config, referral)?;
+    let (_, return_asset_info) = get_offer_return_assets(&routes)?;
+    let mut fee_assets: Vec<Asset> = vec![];
+    let mut referral_fee_assets: Vec<Asset> = vec![];
     let mut return_asset_amount = Uint128::zero();
+    let mut feeable_returns: Vec<FeeableReturn> = vec![];
 
-    // Execute every route
-    for route_info in routes {
+    let shared_pools = get_shared_pools(&routes);
+    let mut pool_states: BTreeMap<String, SharedPoolState> = BTreeMap::new();
+    let mut adjusted_hops: Vec<AdjustedHop> = vec![];
+    let mut route_assets: Vec<Vec<Asset>> = vec![vec![]; routes.len()];
+
+    // Execute every route, uncharged ones first like `swap` does
+    let (charged_routes, uncharged_routes) = partition_routes(
+        deps.storage,
+        &config,
+        routes,
+        &return_asset_info,
+        adjustment,
+    )?;
+    for (route_index, route_info, fee_bps) in uncharged_routes.into_iter().chain(charged_routes) {
         let (route, mut offer_amount) = (route_info.route, route_info.offer_amount);
+        let offer_asset_info = route[0].offer_asset.clone();
+        route_assets[route_index].push(Asset {
+            info: offer_asset_info.clone(),
+            amount: offer_amount,
+        });
 
         // Case 1: Charge starting offer asset
-        if config.fee_bps > 0 && config.fee_assets.contains(&offer_asset_info.id()) {
-            let fee_amount = calc_fee(offer_amount, config.fee_bps)?;
-            fee_asset_amount = fee_asset_amount.checked_add(fee_amount)?;
-            offer_amount = offer_amount.checked_sub(fee_amount)?;
+        let is_charged = is_fee_offer_asset(&config, fee_bps, &offer_asset_info);
+        if is_charged {
+            let fee_amount = calc_fee(offer_amount, fee_bps)?;
+            let referral_fee_amount = calc_referral_fee(offer_amount, referral)?;
+            add_asset(&mut fee_assets, &offer_asset_info, fee_amount)?;
+            add_asset(
+                &mut referral_fee_assets,
+                &offer_asset_info,
+                referral_fee_amount,
+            )?;
+            offer_amount = offer_amount
+                .checked_sub(fee_amount)?
+                .checked_sub(referral_fee_amount)?;
         }
 
         // Execute the swap, sending all return asset back to this contract
         let mut mut_offer_asset = Asset {
-            info: offer_asset_info.clone(),
+            info: offer_asset_info,
             amount: offer_amount,
         };
-        for swap_operation in &route {
-            let pair_info = swap_operation.pair_info(&deps.querier)?;
-            let offer_asset_index = u32::try_from(
-                pair_info
-                    .asset_infos
-                    .iter()
-                    .position(|info| info.id() == swap_operation.offer_asset.id())
-                    .ok_or(ContractError::InvalidRoute {})?,
-            )
-            .unwrap();
-            let return_asset_index = u32::try_from(
-                pair_info
-                    .asset_infos
-                    .iter()
-                    .position(|info| info.id() == swap_operation.return_asset.id())
-                    .ok_or(ContractError::InvalidRoute {})?,
-            )
-            .unwrap();
-
-            let return_amount = match swap_operation.interface()? {
-                SwapInterface::Astroport {} | SwapInterface::OraiDexV2 {} => query_simulation(
-                    &deps.querier,
-                    &swap_operation.contract_addr,
-                    mut_offer_asset.clone(),
-                )?,
-                SwapInterface::Helix { market_id } => query_helix_simulation(
-                    &deps.querier,
-                    &swap_operation.contract_addr,
-                    mut_offer_asset.clone(),
-                    market_id,
-                )?,
-                SwapInterface::Astrovault {
-                    pair_type: PairType::Xyk {},
-                } => query_simulation(
-                    &deps.querier,
-                    &swap_operation.contract_addr,
-                    mut_offer_asset.clone(),
-                )?,
-                SwapInterface::Astrovault {
-                    pair_type: PairType::Stable {},
-                } => query_astrovault_stable_simulation(
-                    &deps.querier,
-                    &swap_operation.contract_addr,
-                    mut_offer_asset.amount,
-                    offer_asset_index,
-                    return_asset_index,
-                )?,
-                SwapInterface::Astrovault {
-                    pair_type: PairType::Hybrid {},
-                } => query_astrovault_hybrid_simulation(
-                    &deps.querier,
-                    &swap_operation.contract_addr,
-                    mut_offer_asset.amount,
-                    offer_asset_index,
-                )?,
-            };
-            if Uint128::is_zero(&return_amount) {
-                return Err(ContractError::InvalidRoute {});
+        for (hop_index, swap_operation) in route.iter().enumerate() {
+            let offer_asset = mut_offer_asset.clone();
+            let naive_return_asset = simulate_operation(
+                deps,
+                swap_operation,
+                offer_asset.clone(),
+                route_index,
+                hop_index,
+            )?;
+            mut_offer_asset = naive_return_asset.clone();
+            if shared_pools.contains(&swap_operation.contract_addr) {
+                mut_offer_asset = simulate_shared_operation(
+                    deps,
+                    &mut pool_states,
+                    swap_operation,
+                    offer_asset,
+                    naive_return_asset.clone(),
+                    route_index,
+                    hop_index,
+                )?;
             }
-
-            let return_asset_info = pair_info
-                .asset_infos
-                .iter()
-                .find(|info| info.id() == swap_operation.return_asset.id())
-                .ok_or(ContractError::InvalidRoute {})?;
-
-            mut_offer_asset = Asset {
-                info: return_asset_info.clone(),
-                amount: return_amount,
-            };
+            if mut_offer_asset.amount != naive_return_asset.amount {
+                adjusted_hops.push(AdjustedHop {
+                    route_index: route_index as u32,
+                    hop_index: hop_index as u32,
+                    pool: swap_operation.contract_addr.clone(),
+                    naive_return_amount: naive_return_asset.amount,
+                    sequential_return_amount: mut_offer_asset.amount,
+                });
+            }
+            route_assets[route_index].push(mut_offer_asset.clone());
         }
         return_asset_amount = return_asset_amount.checked_add(mut_offer_asset.amount)?;
+        if !is_charged {
+            add_feeable_return(&mut feeable_returns, fee_bps, mut_offer_asset.amount)?;
+        }
     }
 
-    if config.fee_bps > 0 && fee_asset_amount.is_zero() {
-        let fee_amount = calc_fee(return_asset_amount, config.fee_bps)?;
-        fee_asset_amount = fee_amount;
-        fee_asset_info = return_asset_info.clone();
-        return_asset_amount = return_asset_amount.checked_sub(fee_amount)?;
+    // Case 2: Mirrors `post_swap`, which charges the return of every group of uncharged routes
+    // sharing a fee with that fee
+    let (feeable_amount, fee_amount) = feeable_return_fee(&feeable_returns)?;
+    add_asset(&mut fee_assets, &return_asset_info, fee_amount)?;
+    return_asset_amount = return_asset_amount.checked_sub(fee_amount)?;
+    let referral_fee_amount = calc_referral_fee(feeable_amount, referral)?;
+    add_asset(
+        &mut referral_fee_assets,
+        &return_asset_info,
+        referral_fee_amount,
+    )?;
+    return_asset_amount = return_asset_amount.checked_sub(referral_fee_amount)?;
+
+    Ok(RoutesSimulation {
+        result: simulation_result(
+            return_asset_info,
+            return_asset_amount,
+            fee_assets,
+            referral_fee_assets,
+            adjusted_hops,
+        ),
+        route_assets,
+    })
+}
+
+/// State of a pool used by more than one hop of a simulation
+enum SharedPoolState {
+    /// Pool quoted locally from reserves queried once
+    Local {
+        curve: LocalCurve,
+        initial_reserves: Vec<Asset>,
+        reserves: Vec<Asset>,
+    },
+    /// Pool without a known invariant or queryable reserves, quoted from the amounts offered to
+    /// and returned by it so far per asset
+    Offered {
+        offered: Vec<Asset>,
+        returned: Vec<Asset>,
+    },
+}
+
+/// Invariant of a pool quoted locally
+enum LocalCurve {
+    Xyk,
+    /// Amplification and decimals of the reserves, in the order the pool returns them
+    Stable {
+        amp: u64,
+        precisions: Vec<u8>,
+    },
+}
+
+impl LocalCurve {
+    /// Returns the amount of the reserve at `return_index` offering `offer_amount` of the one at
+    /// `offer_index` gives, before the pool's fee.
+    fn quote(
+        &self,
+        reserves: &[Asset],
+        offer_index: usize,
+        return_index: usize,
+        offer_amount: Uint128,
+    ) -> Result<Uint128, ContractError> {
+        let return_amount = match self {
+            LocalCurve::Xyk => {
+                xyk::compute_swap(
+                    reserves[offer_index].amount,
+                    reserves[return_index].amount,
+                    offer_amount,
+                    Decimal::zero(),
+                )?
+                .return_amount
+            }
+            LocalCurve::Stable { amp, precisions } => {
+                let balances: Vec<Uint128> = reserves.iter().map(|asset| asset.amount).collect();
+                stableswap::compute_swap(
+                    *amp,
+                    &balances,
+                    precisions,
+                    offer_index,
+                    return_index,
+                    offer_amount,
+                    Decimal::zero(),
+                )?
+                .return_amount
+            }
+        };
+        Ok(return_amount)
     }
+}
 
-    Ok(QuerySimulationResult {
-        return_asset: Asset {
-            info: return_asset_info,
-            amount: return_asset_amount,
-        },
-        fee_asset: if fee_asset_amount.is_zero() {
-            None
+/// Returns the pools used by more than one hop across all routes.
+fn get_shared_pools(routes: &[RouteInfoV2]) -> Vec<Addr> {
+    let mut seen_pools: Vec<&Addr> = vec![];
+    let mut shared_pools: Vec<Addr> = vec![];
+    for swap_operation in routes.iter().flat_map(|route_info| &route_info.route) {
+        let pool = &swap_operation.contract_addr;
+        if seen_pools.contains(&pool) {
+            if !shared_pools.contains(pool) {
+                shared_pools.push(pool.clone());
+            }
         } else {
-            Some(Asset {
-                info: fee_asset_info,
-                amount: fee_asset_amount,
-            })
+            seen_pools.push(pool);
+        }
+    }
+    shared_pools
+}
+
+/// Returns how a pool used by several hops is quoted: locally for constant product pools and
+/// Astroport stable pools whose amplification and precisions can be queried, from the amounts
+/// offered so far otherwise.
+///
+/// Astroport pairs that do not report a pair type are constant product ones.
+fn shared_pool_state(
+    deps: Deps,
+    swap_operation: &SwapOperation,
+    interface: SwapInterface,
+) -> SharedPoolState {
+    let pool = &swap_operation.contract_addr;
+    let is_xyk = match interface {
+        SwapInterface::Astroport {} => match swap_operation.pair_info(&deps.querier) {
+            Ok(PairInfo {
+                pair_type: None | Some(AstroportPairType::Xyk {}),
+                ..
+            }) => Some(true),
+            Ok(PairInfo {
+                pair_type: Some(AstroportPairType::Stable {}),
+                ..
+            }) => Some(false),
+            _ => None,
         },
+        SwapInterface::OraiDexV2 {}
+        | SwapInterface::Astrovault {
+            pair_type: PairType::Xyk {},
+        } => Some(true),
+        _ => None,
+    };
+    let local_state = is_xyk.and_then(|is_xyk| {
+        let reserves = query_pool(&deps.querier, pool).ok()?.assets;
+        let curve = if is_xyk {
+            LocalCurve::Xyk
+        } else {
+            let amp = query_astroport_stable_amp(&deps.querier, pool).ok()?;
+            let precisions = reserves
+                .iter()
+                .map(|reserve| query_astroport_precision(&deps.querier, pool, &reserve.info))
+                .collect::<Result<_, _>>()
+                .ok()?;
+            LocalCurve::Stable {
+                amp: amp.to_uint_floor().u128().try_into().ok()?,
+                precisions,
+            }
+        };
+        Some(SharedPoolState::Local {
+            curve,
+            initial_reserves: reserves.clone(),
+            reserves,
+        })
+    });
+    local_state.unwrap_or(SharedPoolState::Offered {
+        offered: vec![],
+        returned: vec![],
     })
 }
 
-fn receive_cw20(
-    deps: DepsMut,
-    env: Env,
-    cw20_msg: Cw20ReceiveMsg,
-) -> Result<Response, ContractError> {
-    match from_json(&cw20_msg.msg)? {
-        ExecuteMsg::ExecuteRoutes {
-            offer_asset_info,
-            routes,
-            minimum_receive,
-            to,
-        } => swap_deprec(
-            deps,
-            env,
-            Addr::unchecked(cw20_msg.sender),
-            offer_asset_info,
-            routes,
-            minimum_receive,
-            to,
-        ),
-        ExecuteMsg::ExecuteRoutesV2 {
-            routes,
-            minimum_receive,
-            to,
-        } => swap(
-            deps,
-            env,
-            Addr::unchecked(cw20_msg.sender),
-            routes,
-            minimum_receive,
-            to,
-        ),
-        _ => Err(ContractError::InvalidCw20HookMessage {}),
+/// Quotes a hop on a pool that earlier hops of the same simulation already swapped on, given
+/// its naive quote against the current pool state.
+///
+/// Pools quoted locally keep their reserves updated after every use. The naive quote divided
+/// by the local quote against the initial reserves gives the share left after the pool's fee,
+/// which is then applied to the local quote against the updated reserves. Other pools are
+/// quoted as the difference between simulating the net amount offered so far in the same
+/// direction with and without this hop, which holds for any invariant that only depends on
+/// reserves. When earlier hops took at least as much of the offered asset out of the pool as
+/// they put in, the pool pays more for it than at its current state, so the naive quote is
+/// kept as a lower bound.
+fn simulate_shared_operation(
+    deps: Deps,
+    pool_states: &mut BTreeMap<String, SharedPoolState>,
+    swap_operation: &SwapOperation,
+    offer_asset: Asset,
+    naive_return_asset: Asset,
+    route_index: usize,
+    hop_index: usize,
+) -> Result<Asset, ContractError> {
+    let pool = swap_operation.contract_addr.to_string();
+    if !pool_states.contains_key(&pool) {
+        let interface = decode_interface(swap_operation, route_index, hop_index)?;
+        let state = shared_pool_state(deps, swap_operation, interface);
+        pool_states.insert(pool.clone(), state);
     }
-}
 
-fn swap_deprec(
-    deps: DepsMut,
-    env: Env,
-    sender: Addr,
-    offer_asset_info: AssetInfo,
-    routes: Vec<RouteInfo>,
-    minimum_receive: Uint128,
-    to: Option<Addr>,
-) -> Result<Response, ContractError> {
-    let mut routes_v2: Vec<RouteInfoV2> = vec![];
-    for route_info in routes {
-        let mut mut_offer_asset_info = offer_asset_info.clone();
+    let (offer_info, return_info) = (&offer_asset.info, &naive_return_asset.info);
+    let return_amount = match pool_states.get_mut(&pool).unwrap() {
+        SharedPoolState::Local {
+            curve,
+            initial_reserves,
+            reserves,
+        } => {
+            let find_index = |info: &AssetInfo| {
+                reserves
+                    .iter()
+                    .position(|reserve| reserve.id() == info.id())
+                    .ok_or(ContractError::AssetNotInPair {
+                        route_index: route_index as u32,
+                        hop_index: hop_index as u32,
+                        pool: swap_operation.contract_addr.clone(),
+                        asset: info.id(),
+                    })
+            };
+            let (offer_index, return_index) = (find_index(offer_info)?, find_index(return_info)?);
+            let initial_return_amount = curve.quote(
+                initial_reserves,
+                offer_index,
+                return_index,
+                offer_asset.amount,
+            )?;
+            let return_amount = if initial_return_amount.is_zero() {
+                naive_return_asset.amount
+            } else {
+                curve
+                    .quote(reserves, offer_index, return_index, offer_asset.amount)?
+                    .multiply_ratio(naive_return_asset.amount, initial_return_amount)
+            };
 
-        let mut new_route: Vec<SwapOperation> = vec![];
-        for contract_info in route_info.route {
-            let pair_info = contract_info.pair_info(&deps.querier)?;
-            let return_asset_info = pair_info
-                .asset_infos
-                .iter()
-                .find(|info| info.id() != mut_offer_asset_info.id())
-                .ok_or(ContractError::InvalidRoute {})?;
-            new_route.push(SwapOperation {
-                contract_addr: contract_info.contract_addr,
-                offer_asset: mut_offer_asset_info.clone(),
-                return_asset: return_asset_info.clone(),
-                interface: contract_info.interface.map(Interface::Struct),
-            });
-            mut_offer_asset_info = return_asset_info.clone();
+            // The fee stays in the pool, only the return amount leaves it
+            reserves[offer_index].amount = reserves[offer_index]
+                .amount
+                .checked_add(offer_asset.amount)?;
+            reserves[return_index].amount =
+                reserves[return_index].amount.checked_sub(return_amount)?;
+            return_amount
+        }
+        SharedPoolState::Offered { offered, returned } => {
+            let amount_of = |assets: &[Asset]| {
+                assets
+                    .iter()
+                    .find(|asset| asset.id() == offer_info.id())
+                    .map_or(Uint128::zero(), |asset| asset.amount)
+            };
+            let (offered_amount, returned_amount) = (amount_of(offered), amount_of(returned));
+            let return_amount = if offered_amount <= returned_amount {
+                naive_return_asset.amount
+            } else {
+                let simulate_offered = |amount: Uint128| {
+                    simulate_operation(
+                        deps,
+                        swap_operation,
+                        Asset {
+                            info: offer_info.clone(),
+                            amount,
+                        },
+                        route_index,
+                        hop_index,
+                    )
+                };
+                let net_offered_amount = offered_amount - returned_amount;
+                let total_offered_amount = net_offered_amount.checked_add(offer_asset.amount)?;
+                simulate_offered(total_offered_amount)?
+                    .amount
+                    .saturating_sub(simulate_offered(net_offered_amount)?.amount)
+            };
+            add_asset(offered, offer_info, offer_asset.amount)?;
+            add_asset(returned, return_info, return_amount)?;
+            return_amount
         }
+    };
 
-        routes_v2.push(RouteInfoV2 {
-            route: new_route,
-            offer_amount: route_info.offer_amount,
-        });
-    }
-    swap(deps, env, sender, routes_v2, minimum_receive, to)
+    Ok(Asset {
+        info: naive_return_asset.info,
+        amount: return_amount,
+    })
 }
 
-/// Two cases where fees are charged depending on where and if we find a valid fee asset
-/// Case 1: valid fee offer asset -> charge at `swap`
-/// Case 2: return asset -> charge at `post_swap`
-fn swap(
-    deps: DepsMut,
-    env: Env,
-    sender: Addr,
+/// See `arbitrage` function for how fees are charged on the profit
+fn simulation_arbitrage(
+    deps: Deps,
     routes: Vec<RouteInfoV2>,
-    minimum_receive: Uint128,
-    to: Option<Addr>,
-) -> Result<Response, ContractError> {
+    adjustment: &FeeAdjustment,
+) -> Result<QuerySimulationResult, ContractError> {
     let config = CONFIG.load(deps.storage)?;
+    let asset_info = get_cyclic_asset(&routes)?;
+    let mut offer_amount = Uint128::zero();
+    let mut return_amount = Uint128::zero();
 
-    let mut msgs: Vec<CosmosMsg> = vec![];
-    let mut total_offer_amount = Uint128::zero();
-    let mut total_fee_amount = Uint128::zero();
-
-    let (offer_asset_info, return_asset_info) = get_offer_return_asset(&routes)?;
+    for (route_index, route_info) in routes.into_iter().enumerate() {
+        offer_amount = offer_amount.checked_add(route_info.offer_amount)?;
+        let mut mut_offer_asset = Asset {
+            info: asset_info.clone(),
+            amount: route_info.offer_amount,
+        };
+        for (hop_index, swap_operation) in route_info.route.iter().enumerate() {
+            mut_offer_asset = simulate_operation(
+                deps,
+                swap_operation,
+                mut_offer_asset,
+                route_index,
+                hop_index,
+            )?;
+        }
+        return_amount = return_amount.checked_add(mut_offer_asset.amount)?;
+    }
 
-    // Execute every route
-    for route_info in routes {
-        let (route, mut offer_amount) = (route_info.route, route_info.offer_amount);
-        total_offer_amount = total_offer_amount.checked_add(offer_amount)?;
+    let mut fee_assets: Vec<Asset> = vec![];
+    let profit = return_amount.saturating_sub(offer_amount);
+    let fee_bps = effective_fee_bps(deps.storage, &config, &asset_info, &asset_info, adjustment)?;
+    let fee_amount = calc_fee(profit, fee_bps)?;
+    add_asset(&mut fee_assets, &asset_info, fee_amount)?;
 
-        // Case 1: Charge starting offer asset
-        if config.fee_bps > 0 && config.fee_assets.contains(&offer_asset_info.id()) {
-            let fee_amount = calc_fee(offer_amount, config.fee_bps)?;
-            total_fee_amount = total_fee_amount.checked_add(fee_amount)?;
-            offer_amount = offer_amount.checked_sub(fee_amount)?;
-        }
+    Ok(simulation_result(
+        asset_info,
+        return_amount.checked_sub(fee_amount)?,
+        fee_assets,
+        vec![],
+        vec![],
+    ))
+}
 
-        // Execute the swap, sending all return asset back to this contract
-        for (idx, swap_operation) in route.iter().enumerate() {
-            msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+/// See `swap_v3` function for how a route graph is split, and `swap` for where fees are charged
+fn simulation_v3(
+    deps: Deps,
+    route: RouteInfoV3,
+    adjustment: &FeeAdjustment,
+) -> Result<QuerySimulationResult, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let execution_order = route.execution_order()?;
+    let (offer_asset_info, return_asset_info) = (route.offer_asset()?, route.return_asset()?);
+    let fee_bps = effective_fee_bps(
+        deps.storage,
+        &config,
+        &offer_asset_info,
+        &return_asset_info,
+        adjustment,
+    )?;
+    let mut fee_assets: Vec<Asset> = vec![];
+
+    // Case 1: Charge starting offer asset
+    let mut node_amounts = vec![Uint128::zero(); route.nodes.len()];
+    node_amounts[0] = route.offer_amount;
+    if is_fee_offer_asset(&config, fee_bps, &offer_asset_info) {
+        let fee_amount = calc_fee(route.offer_amount, fee_bps)?;
+        add_asset(&mut fee_assets, &offer_asset_info, fee_amount)?;
+        node_amounts[0] = node_amounts[0].checked_sub(fee_amount)?;
+    }
+
+    // Split every node the same way `split_swap_pool` does
+    let mut return_asset_amount = Uint128::zero();
+    for node in execution_order {
+        let operations = route.outgoing(node);
+        let total_amount = node_amounts[node];
+        if operations.is_empty() {
+            return_asset_amount = total_amount;
+            continue;
+        }
+        let mut remaining_amount = total_amount;
+        for (idx, split) in operations.iter().enumerate() {
+            let offer_amount = if idx + 1 == operations.len() {
+                remaining_amount
+            } else {
+                total_amount.mul_floor(split.fraction)
+            };
+            remaining_amount = remaining_amount.checked_sub(offer_amount)?;
+            if offer_amount.is_zero() {
+                continue;
+            }
+            let return_asset = simulate_operation(
+                deps,
+                &split.operation,
+                Asset {
+                    info: split.operation.offer_asset.clone(),
+                    amount: offer_amount,
+                },
+                node,
+                idx,
+            )?;
+            let to = route
+                .nodes
+                .iter()
+                .position(|info| info.id() == return_asset.id())
+                .ok_or(ContractError::InvalidRoute {})?;
+            node_amounts[to] = node_amounts[to].checked_add(return_asset.amount)?;
+        }
+    }
+
+    if fee_bps > 0 && fee_assets.is_empty() {
+        let fee_amount = calc_fee(return_asset_amount, fee_bps)?;
+        add_asset(&mut fee_assets, &return_asset_info, fee_amount)?;
+        return_asset_amount = return_asset_amount.checked_sub(fee_amount)?;
+    }
+
+    Ok(simulation_result(
+        return_asset_info,
+        return_asset_amount,
+        fee_assets,
+        vec![],
+        vec![],
+    ))
+}
+
+/// Builds the simulation response from the return amount left after all `fee_assets`.
+fn simulation_result(
+    return_asset_info: AssetInfo,
+    return_amount: Uint128,
+    fee_assets: Vec<Asset>,
+    referral_fee_assets: Vec<Asset>,
+    adjusted_hops: Vec<AdjustedHop>,
+) -> QuerySimulationResult {
+    QuerySimulationResult {
+        return_asset: Asset {
+            info: return_asset_info,
+            amount: return_amount,
+        },
+        fee_asset: fee_assets.first().cloned(),
+        fee_assets,
+        referral_fee_assets,
+        adjusted_hops,
+    }
+}
+
+/// Simulates a single swap operation against the pool, returning the asset it would return.
+/// `route_index` and `hop_index` locate the operation in errors.
+fn simulate_operation(
+    deps: Deps,
+    swap_operation: &SwapOperation,
+    offer_asset: Asset,
+    route_index: usize,
+    hop_index: usize,
+) -> Result<Asset, ContractError> {
+    let interface = decode_interface(swap_operation, route_index, hop_index)?;
+    let (route_index, hop_index) = (route_index as u32, hop_index as u32);
+    let pool = swap_operation.contract_addr.clone();
+    let pair_info =
+        swap_operation
+            .pair_info(&deps.querier)
+            .map_err(|err| ContractError::PairNotFound {
+                route_index,
+                hop_index,
+                pool: pool.clone(),
+                reason: err.to_string(),
+            })?;
+    let asset_index = |asset_info: &AssetInfo| {
+        pair_info
+            .asset_infos
+            .iter()
+            .position(|info| info.id() == asset_info.id())
+            .map(|index| index as u32)
+            .ok_or(ContractError::AssetNotInPair {
+                route_index,
+                hop_index,
+                pool: pool.clone(),
+                asset: asset_info.id(),
+            })
+    };
+    let offer_asset_index = asset_index(&swap_operation.offer_asset)?;
+    let return_asset_index = asset_index(&swap_operation.return_asset)?;
+
+    let return_amount = match interface {
+        SwapInterface::Astroport {} | SwapInterface::OraiDexV2 {} => {
+            query_simulation(&deps.querier, &pool, offer_asset.clone())
+        }
+        SwapInterface::Helix { market_id } => {
+            query_helix_simulation(&deps.querier, &pool, offer_asset.clone(), market_id)
+        }
+        SwapInterface::Astrovault {
+            pair_type: PairType::Xyk {},
+        } => query_simulation(&deps.querier, &pool, offer_asset.clone()),
+        SwapInterface::Astrovault {
+            pair_type: PairType::Stable {},
+        } => query_astrovault_stable_simulation(
+            &deps.querier,
+            &pool,
+            offer_asset.amount,
+            offer_asset_index,
+            return_asset_index,
+        ),
+        SwapInterface::Astrovault {
+            pair_type: PairType::Hybrid {},
+        } => query_astrovault_hybrid_simulation(
+            &deps.querier,
+            &pool,
+            offer_asset.amount,
+            offer_asset_index,
+        ),
+    }
+    .map_err(|source| ContractError::SimulationFailed {
+        route_index,
+        hop_index,
+        source,
+    })?;
+    // A pool that answers but returns nothing, e.g. for lack of liquidity
+    if Uint128::is_zero(&return_amount) {
+        return Err(ContractError::ZeroSimulationOutput {
+            route_index,
+            hop_index,
+            pool,
+        });
+    }
+
+    Ok(Asset {
+        info: pair_info.asset_infos[return_asset_index as usize].clone(),
+        amount: return_amount,
+    })
+}
+
+/// Decodes the interface of a swap operation, locating it by `route_index` and `hop_index`
+/// when it fails.
+fn decode_interface(
+    swap_operation: &SwapOperation,
+    route_index: usize,
+    hop_index: usize,
+) -> Result<SwapInterface, ContractError> {
+    swap_operation
+        .interface()
+        .map_err(|err| ContractError::InterfaceDecode {
+            route_index: route_index as u32,
+            hop_index: hop_index as u32,
+            pool: swap_operation.contract_addr.clone(),
+            reason: err.to_string(),
+        })
+}
+
+/// Decodes the interface of every hop upfront, so that an invalid one fails before any
+/// message is dispatched.
+fn assert_route_interfaces(routes: &[RouteInfoV2]) -> Result<(), ContractError> {
+    for (route_index, route_info) in routes.iter().enumerate() {
+        for (hop_index, swap_operation) in route_info.route.iter().enumerate() {
+            decode_interface(swap_operation, route_index, hop_index)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks every hop of `routes` instead of failing at the first problem. Routes may start from
+/// different offer assets, see `swap`.
+fn validate_routes(deps: Deps, routes: Vec<RouteInfoV2>) -> ValidateRoutesResponse {
+    let mut errors: Vec<RouteDiagnostic> = vec![];
+    let mut report = |route_index: Option<usize>, hop_index: Option<usize>, issue: RouteIssue| {
+        errors.push(RouteDiagnostic {
+            route_index: route_index.map(|idx| idx as u32),
+            hop_index: hop_index.map(|idx| idx as u32),
+            issue,
+        })
+    };
+
+    let expected_return_asset = routes
+        .first()
+        .and_then(|route_info| route_info.route.last())
+        .map(|swap_operation| swap_operation.return_asset.id());
+    // Assets the router sweeps in full, which later hops must not offer
+    let mut swept_assets: Vec<&AssetInfo> = routes
+        .iter()
+        .filter_map(|route_info| route_info.route.first())
+        .map(|swap_operation| &swap_operation.offer_asset)
+        .collect();
+    swept_assets.extend(
+        routes
+            .first()
+            .and_then(|route_info| route_info.route.last())
+            .map(|swap_operation| &swap_operation.return_asset),
+    );
+    if routes.is_empty() {
+        report(None, None, RouteIssue::NoRoutes {});
+    }
+
+    for (route_index, route_info) in routes.iter().enumerate() {
+        let (first, last) = match (route_info.route.first(), route_info.route.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => {
+                report(Some(route_index), None, RouteIssue::EmptyRoute {});
+                continue;
+            }
+        };
+
+        for (hop_index, swap_operation) in route_info.route.iter().enumerate() {
+            let (offer_asset_id, return_asset_id) = (
+                swap_operation.offer_asset.id(),
+                swap_operation.return_asset.id(),
+            );
+            if offer_asset_id == return_asset_id {
+                report(
+                    Some(route_index),
+                    Some(hop_index),
+                    RouteIssue::SameOfferAndReturnAsset {
+                        asset: offer_asset_id.clone(),
+                    },
+                );
+            }
+            if hop_index > 0 {
+                let expected = route_info.route[hop_index - 1].return_asset.id();
+                if expected != offer_asset_id {
+                    report(
+                        Some(route_index),
+                        Some(hop_index),
+                        RouteIssue::Disconnected {
+                            expected,
+                            found: offer_asset_id.clone(),
+                        },
+                    );
+                }
+                if offers_any(swap_operation, &swept_assets) {
+                    report(
+                        Some(route_index),
+                        Some(hop_index),
+                        RouteIssue::OffersSweptAsset {
+                            asset: offer_asset_id.clone(),
+                        },
+                    );
+                }
+            }
+
+            if let Err(err) = swap_operation.interface() {
+                report(
+                    Some(route_index),
+                    Some(hop_index),
+                    RouteIssue::InterfaceDecode {
+                        reason: err.to_string(),
+                    },
+                );
+                continue;
+            }
+            let pair_info = match swap_operation.pair_info(&deps.querier) {
+                Ok(pair_info) => pair_info,
+                Err(err) => {
+                    report(
+                        Some(route_index),
+                        Some(hop_index),
+                        RouteIssue::PairNotFound {
+                            pool: swap_operation.contract_addr.clone(),
+                            reason: err.to_string(),
+                        },
+                    );
+                    continue;
+                }
+            };
+            let is_in_pair =
+                |id: &String| pair_info.asset_infos.iter().any(|info| &info.id() == id);
+            if !is_in_pair(&offer_asset_id) {
+                report(
+                    Some(route_index),
+                    Some(hop_index),
+                    RouteIssue::OfferAssetNotInPair {
+                        pool: swap_operation.contract_addr.clone(),
+                        asset: offer_asset_id,
+                    },
+                );
+            }
+            if !is_in_pair(&return_asset_id) {
+                report(
+                    Some(route_index),
+                    Some(hop_index),
+                    RouteIssue::ReturnAssetNotInPair {
+                        pool: swap_operation.contract_addr.clone(),
+                        asset: return_asset_id,
+                    },
+                );
+            }
+        }
+
+        let last_hop = Some(route_info.route.len() - 1);
+        if let Some(expected) = &expected_return_asset {
+            if expected != &last.return_asset.id() {
+                report(
+                    Some(route_index),
+                    last_hop,
+                    RouteIssue::ReturnAssetMismatch {
+                        expected: expected.clone(),
+                        found: last.return_asset.id(),
+                    },
+                );
+            }
+            if expected == &first.offer_asset.id() {
+                report(
+                    Some(route_index),
+                    Some(0),
+                    RouteIssue::OfferIsReturnAsset {
+                        asset: expected.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    ValidateRoutesResponse {
+        valid: errors.is_empty(),
+        errors,
+    }
+}
+
+fn receive_cw20(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let sent_assets = vec![Asset {
+        info: AssetInfo::Token {
+            contract_addr: info.sender,
+        },
+        amount: cw20_msg.amount,
+    }];
+    let msg: ExecuteMsg = from_json(&cw20_msg.msg)?;
+    assert_deadline(&env, msg.deadline())?;
+    match msg {
+        ExecuteMsg::ExecuteRoutes {
+            offer_asset_info,
+            routes,
+            minimum_receive,
+            to,
+            ..
+        } => swap_deprec(
+            deps,
+            env,
+            Addr::unchecked(cw20_msg.sender),
+            sent_assets,
+            offer_asset_info,
+            routes,
+            minimum_receive,
+            to,
+        ),
+        ExecuteMsg::ExecuteRoutesV2 {
+            routes,
+            minimum_receive,
+            to,
+            allow_partial,
+            max_slippage_bps,
+            reference_quote,
+            minimum_receive_mode,
+            referral,
+            integrator,
+            ..
+        } => swap(
+            deps,
+            env,
+            Addr::unchecked(cw20_msg.sender),
+            sent_assets,
+            routes,
+            minimum_receive,
+            to,
+            allow_partial.unwrap_or(false),
+            max_slippage_bps,
+            reference_quote,
+            minimum_receive_mode.unwrap_or(MinimumReceiveMode::RouterOutput {}),
+            referral,
+            integrator,
+        ),
+        ExecuteMsg::ExecuteRoutesV3 {
+            route,
+            minimum_receive,
+            to,
+            minimum_receive_mode,
+            ..
+        } => swap_v3(
+            deps,
+            env,
+            Addr::unchecked(cw20_msg.sender),
+            sent_assets,
+            route,
+            minimum_receive,
+            to,
+            minimum_receive_mode.unwrap_or(MinimumReceiveMode::RouterOutput {}),
+        ),
+        ExecuteMsg::ExecuteRoutesMultiOutput {
+            routes,
+            outputs,
+            minimum_receive_mode,
+            ..
+        } => swap_multi_output(
+            deps,
+            env,
+            Addr::unchecked(cw20_msg.sender),
+            sent_assets,
+            routes,
+            outputs,
+            minimum_receive_mode.unwrap_or(MinimumReceiveMode::RouterOutput {}),
+        ),
+        ExecuteMsg::ExecuteArbitrage {
+            routes,
+            minimum_profit,
+            to,
+            ..
+        } => arbitrage(
+            deps,
+            env,
+            Addr::unchecked(cw20_msg.sender),
+            sent_assets,
+            routes,
+            minimum_profit,
+            to,
+        ),
+        _ => Err(ContractError::InvalidCw20HookMessage {}),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn swap_deprec(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    sent_assets: Vec<Asset>,
+    offer_asset_info: AssetInfo,
+    routes: Vec<RouteInfo>,
+    minimum_receive: Uint128,
+    to: Option<Addr>,
+) -> Result<Response, ContractError> {
+    let mut routes_v2: Vec<RouteInfoV2> = vec![];
+    for route_info in routes {
+        let mut mut_offer_asset_info = offer_asset_info.clone();
+
+        let mut new_route: Vec<SwapOperation> = vec![];
+        for contract_info in route_info.route {
+            let pair_info = contract_info.pair_info(&deps.querier)?;
+            let return_asset_info = pair_info
+                .asset_infos
+                .iter()
+                .find(|info| info.id() != mut_offer_asset_info.id())
+                .ok_or(ContractError::InvalidRoute {})?;
+            new_route.push(SwapOperation {
+                contract_addr: contract_info.contract_addr,
+                offer_asset: mut_offer_asset_info.clone(),
+                return_asset: return_asset_info.clone(),
+                interface: contract_info.interface.map(Interface::Struct),
+            });
+            mut_offer_asset_info = return_asset_info.clone();
+        }
+
+        routes_v2.push(RouteInfoV2 {
+            route: new_route,
+            offer_amount: route_info.offer_amount,
+        });
+    }
+    swap(
+        deps,
+        env,
+        sender,
+        sent_assets,
+        routes_v2,
+        minimum_receive,
+        to,
+        false,
+        None,
+        None,
+        MinimumReceiveMode::RouterOutput {},
+        None,
+        None,
+    )
+}
+
+/// Two cases where fees are charged depending on where and if we find a valid fee asset
+/// Case 1: valid fee offer asset -> charge at `swap`
+/// Case 2: return asset -> charge at `post_swap`
+/// Routes may start from different offer assets as long as they all end at the same return
+/// asset, in which case each offer asset is charged according to its own case.
+/// With `allow_partial`, every route runs as a sub message so that a failing route is refunded
+/// instead of reverting the whole swap, see `settle_partial_routes`.
+/// The routes are simulated once, for both `max_slippage_bps` and the volume of the sender.
+#[allow(clippy::too_many_arguments)]
+fn swap(
+    mut deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    sent_assets: Vec<Asset>,
+    routes: Vec<RouteInfoV2>,
+    minimum_receive: Uint128,
+    to: Option<Addr>,
+    allow_partial: bool,
+    max_slippage_bps: Option<u16>,
+    reference_quote: Option<Uint128>,
+    minimum_receive_mode: MinimumReceiveMode,
+    referral: Option<Referral>,
+    integrator: Option<String>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_referral(&config, referral.as_ref())?;
+
+    let (offer_assets, return_asset_info) = get_offer_return_assets(&routes)?;
+    let (sent_assets, pull_msgs) = pull_cw20_assets(&env, &sender, sent_assets, &offer_assets)?;
+    assert_sent_assets(&sent_assets, &offer_assets)?;
+    assert_route_interfaces(&routes)?;
+    if let Some((id, _)) = load_integrator(deps.storage, integrator.as_ref())? {
+        for offer_asset in &offer_assets {
+            accrue_asset(deps.storage, &INTEGRATOR_VOLUMES, &id, offer_asset)?;
+        }
+    }
+    let adjustment = fee_adjustment(deps.as_ref(), &env, Some(&sender))?;
+    let simulation = if max_slippage_bps.is_some() {
+        Some(simulate_routes(
+            deps.as_ref(),
+            routes.clone(),
+            referral.as_ref(),
+            &adjustment,
+        )?)
+    } else if VOLUME_TIERS.may_load(deps.storage)?.is_some() {
+        // A swap that fails to simulate is not counted, rather than failing
+        simulate_routes(
+            deps.as_ref(),
+            routes.clone(),
+            referral.as_ref(),
+            &adjustment,
+        )
+        .ok()
+    } else {
+        None
+    };
+    let minimum_receive = slippage_minimum_receive(
+        minimum_receive,
+        max_slippage_bps,
+        reference_quote,
+        simulation.as_ref(),
+    )?;
+    track_user_volume(deps.branch(), &env, &sender, |deps, volume_tiers| {
+        let Some(simulation) = &simulation else {
+            return Ok(Uint128::zero());
+        };
+        let mut volume = Uint128::zero();
+        for assets in &simulation.route_assets {
+            volume = volume.checked_add(route_assets_quote_value(deps, volume_tiers, assets))?;
+        }
+        Ok(volume)
+    })?;
+
+    // Routes charged at `post_swap` run first, so that their return amount can be told apart
+    // from the one of routes already charged at `swap`
+    let (charged_routes, uncharged_routes) = partition_routes(
+        deps.storage,
+        &config,
+        routes,
+        &return_asset_info,
+        &adjustment,
+    )?;
+    let return_fee_bps = max_fee_bps(&uncharged_routes);
+    let uncharged_fees: Vec<u16> = uncharged_routes
+        .iter()
+        .map(|(_, _, fee_bps)| *fee_bps)
+        .collect();
+
+    let mut msgs: Vec<SubMsg> = pull_msgs.into_iter().map(SubMsg::new).collect();
+    let mut fees: Vec<Asset> = vec![];
+    let mut referral_fees: Vec<Asset> = vec![];
+
+    // Execute every route, sending the return asset back to this contract, or directly to the
+    // receiver when no fee is left to charge at `post_swap`
+    let receiver = to.clone().unwrap_or(sender.clone());
+    let charges_return = return_fee_bps > 0 || referral.is_some();
+    let uncharged_count = uncharged_routes.len();
+    for (idx, (_, route_info, fee_bps)) in uncharged_routes
+        .into_iter()
+        .chain(charged_routes)
+        .enumerate()
+    {
+        // Case 1: Charge starting offer asset
+        let offer_asset_info = route_info.route[0].offer_asset.clone();
+        let (fee_amount, referral_fee_amount) = if idx >= uncharged_count {
+            (
+                calc_fee(route_info.offer_amount, fee_bps)?,
+                calc_referral_fee(route_info.offer_amount, referral.as_ref())?,
+            )
+        } else {
+            (Uint128::zero(), Uint128::zero())
+        };
+        let offer_amount = route_info
+            .offer_amount
+            .checked_sub(fee_amount)?
+            .checked_sub(referral_fee_amount)?;
+        let route_to = (!charges_return || idx >= uncharged_count).then_some(&receiver);
+
+        if allow_partial {
+            // Fees are only paid for the routes that succeed, see `settle_partial_routes`
+            PARTIAL_ROUTES.save(
+                deps.storage,
+                idx as u64,
+                &PartialRoute {
+                    offer_asset: Asset {
+                        info: offer_asset_info,
+                        amount: route_info.offer_amount,
+                    },
+                    fee_amount,
+                    referral_fee_amount,
+                    failed: false,
+                },
+            )?;
+            msgs.push(SubMsg::reply_on_error(
+                WasmMsg::Execute {
+                    contract_addr: env.contract.address.to_string(),
+                    funds: vec![],
+                    msg: to_json_binary(&ExecuteMsg::ExecuteRoute {
+                        route: route_info.route,
+                        amount: offer_amount,
+                        to: route_to.cloned(),
+                    })?,
+                },
+                idx as u64,
+            ));
+        } else {
+            add_asset(&mut fees, &offer_asset_info, fee_amount)?;
+            add_asset(&mut referral_fees, &offer_asset_info, referral_fee_amount)?;
+            msgs.extend(
+                route_msgs(&env, &route_info.route, offer_amount, route_to)?
+                    .into_iter()
+                    .map(SubMsg::new),
+            );
+        }
+
+        // Case 2: Record the return of the last uncharged route of every fee
+        if charges_return && idx < uncharged_count && uncharged_fees.get(idx + 1) != Some(&fee_bps)
+        {
+            msgs.push(SubMsg::new(WasmMsg::Execute {
+                contract_addr: env.contract.address.to_string(),
+                funds: vec![],
+                msg: to_json_binary(&ExecuteMsg::ExecuteSnapshotReturn {
+                    return_asset_info: return_asset_info.clone(),
+                    fee_bps,
+                })?,
+            }));
+        }
+    }
+    if allow_partial {
+        msgs.push(SubMsg::new(WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            funds: vec![],
+            msg: to_json_binary(&ExecuteMsg::ExecuteSettlePartialRoutes {
+                refund_to: sender.clone(),
+                referral: referral.as_ref().map(|referral| referral.address.clone()),
+                integrator: integrator.clone(),
+            })?,
+        }));
+    }
+
+    msgs.extend(
+        post_swap_msgs(
+            deps,
+            &env,
+            sender,
+            offer_assets,
+            fees,
+            adjustment.exempt,
+            referral.map(|referral| (referral, referral_fees)),
+            integrator,
+            vec![(
+                SwapOutput {
+                    asset_info: return_asset_info,
+                    minimum_receive,
+                    to,
+                },
+                return_fee_bps,
+            )],
+            minimum_receive_mode,
+        )?
+        .into_iter()
+        .map(SubMsg::new),
+    );
+
+    Ok(Response::new().add_submessages(msgs))
+}
+
+/// Returns the messages that execute every hop of `route`, offering `offer_amount` to the first
+/// pool and this contract's whole balance of the intermediate assets to the next ones. The last
+/// hop pays `to` directly when its pool supports it.
+fn route_msgs(
+    env: &Env,
+    route: &[SwapOperation],
+    offer_amount: Uint128,
+    to: Option<&Addr>,
+) -> StdResult<Vec<CosmosMsg>> {
+    route
+        .iter()
+        .enumerate()
+        .map(|(idx, swap_operation)| {
+            Ok(CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: env.contract.address.to_string(),
                 funds: vec![],
                 msg: to_json_binary(&ExecuteMsg::ExecuteSwapOp {
                     operation: swap_operation.clone(),
                     amount: if idx == 0 { Some(offer_amount) } else { None },
+                    to: if idx + 1 == route.len() {
+                        to.cloned()
+                    } else {
+                        None
+                    },
                 })?,
-            }));
+            }))
+        })
+        .collect()
+}
+
+/// Same as `swap`, but executes a route graph node by node in topological order so that
+/// every node splits only after all of its incoming edges have been swapped.
+#[allow(clippy::too_many_arguments)]
+fn swap_v3(
+    mut deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    sent_assets: Vec<Asset>,
+    route: RouteInfoV3,
+    minimum_receive: Uint128,
+    to: Option<Addr>,
+    minimum_receive_mode: MinimumReceiveMode,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    let execution_order = route.execution_order()?;
+    let (offer_asset_info, return_asset_info) = (route.offer_asset()?, route.return_asset()?);
+    let offer_asset = Asset {
+        info: offer_asset_info.clone(),
+        amount: route.offer_amount,
+    };
+    assert_sent_assets(&sent_assets, std::slice::from_ref(&offer_asset))?;
+    let adjustment = fee_adjustment(deps.as_ref(), &env, Some(&sender))?;
+    track_user_volume(deps.branch(), &env, &sender, |deps, volume_tiers| {
+        route_v3_quote_value(deps, volume_tiers, &route)
+    })?;
+    let fee_bps = effective_fee_bps(
+        deps.storage,
+        &config,
+        &offer_asset_info,
+        &return_asset_info,
+        &adjustment,
+    )?;
+
+    // Case 1: Charge starting offer asset
+    let mut offer_amount = route.offer_amount;
+    let mut fees: Vec<Asset> = vec![];
+    if is_fee_offer_asset(&config, fee_bps, &offer_asset_info) {
+        let fee_amount = calc_fee(offer_amount, fee_bps)?;
+        add_asset(&mut fees, &offer_asset_info, fee_amount)?;
+        offer_amount = offer_amount.checked_sub(fee_amount)?;
+    }
+
+    // Split every node across its outgoing edges, sending all return asset back to this contract
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    for node in execution_order {
+        let operations = route.outgoing(node);
+        if operations.is_empty() {
+            continue;
+        }
+        for (idx, split) in operations.iter().enumerate() {
+            decode_interface(&split.operation, node, idx)?;
+        }
+        msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            funds: vec![],
+            msg: to_json_binary(&ExecuteMsg::ExecuteSplitOp {
+                operations,
+                amount: if node == 0 { Some(offer_amount) } else { None },
+            })?,
+        }));
+    }
+
+    msgs.extend(post_swap_msgs(
+        deps,
+        &env,
+        sender,
+        vec![offer_asset],
+        fees,
+        adjustment.exempt,
+        None,
+        None,
+        vec![(
+            SwapOutput {
+                asset_info: return_asset_info,
+                minimum_receive,
+                to,
+            },
+            fee_bps,
+        )],
+        minimum_receive_mode,
+    )?);
+
+    Ok(Response::new().add_messages(msgs))
+}
+
+/// Swaps a single offer asset into several return assets, each with its own minimum receive
+/// and receiver. Every output is charged the same way a single-output `swap` would, so each
+/// output can be quoted by simulating its own routes.
+fn swap_multi_output(
+    mut deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    sent_assets: Vec<Asset>,
+    routes: Vec<RouteInfoV2>,
+    outputs: Vec<SwapOutput>,
+    minimum_receive_mode: MinimumReceiveMode,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    let offer_assets = get_offer_assets_multi_output(&routes, &outputs)?;
+    assert_sent_assets(&sent_assets, &offer_assets)?;
+    assert_route_interfaces(&routes)?;
+    let adjustment = fee_adjustment(deps.as_ref(), &env, Some(&sender))?;
+    track_user_volume(deps.branch(), &env, &sender, |deps, volume_tiers| {
+        routes_quote_value(deps, volume_tiers, &routes)
+    })?;
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    let mut fees: Vec<Asset> = vec![];
+
+    // Execute every route, sending all return assets back to this contract
+    for route_info in routes {
+        let (route, mut offer_amount) = (route_info.route, route_info.offer_amount);
+
+        // Case 1: Charge starting offer asset
+        let return_asset_info = &route[route.len() - 1].return_asset;
+        let fee_bps = effective_fee_bps(
+            deps.storage,
+            &config,
+            &route[0].offer_asset,
+            return_asset_info,
+            &adjustment,
+        )?;
+        if is_fee_offer_asset(&config, fee_bps, &route[0].offer_asset) {
+            let fee_amount = calc_fee(offer_amount, fee_bps)?;
+            add_asset(&mut fees, &route[0].offer_asset, fee_amount)?;
+            offer_amount = offer_amount.checked_sub(fee_amount)?;
+        }
+        msgs.extend(route_msgs(&env, &route, offer_amount, None)?);
+    }
+    let outputs_fee_bps = outputs
+        .into_iter()
+        .map(|output| {
+            let fee_bps = effective_fee_bps(
+                deps.storage,
+                &config,
+                &offer_assets[0].info,
+                &output.asset_info,
+                &adjustment,
+            )?;
+            Ok((output, fee_bps))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    msgs.extend(post_swap_msgs(
+        deps,
+        &env,
+        sender,
+        offer_assets,
+        fees,
+        adjustment.exempt,
+        None,
+        None,
+        outputs_fee_bps,
+        minimum_receive_mode,
+    )?);
+
+    Ok(Response::new().add_messages(msgs))
+}
+
+/// Executes routes that start and end at the same asset. Instead of a minimum receive, the
+/// net profit measured as this contract's balance delta must reach `minimum_profit`, and fees
+/// are only charged on the profit.
+fn arbitrage(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    sent_assets: Vec<Asset>,
+    routes: Vec<RouteInfoV2>,
+    minimum_profit: Uint128,
+    to: Option<Addr>,
+) -> Result<Response, ContractError> {
+    let asset_info = get_cyclic_asset(&routes)?;
+    assert_route_interfaces(&routes)?;
+    let mut offer_amount = Uint128::zero();
+    for route_info in &routes {
+        offer_amount = offer_amount.checked_add(route_info.offer_amount)?;
+    }
+    assert_sent_assets(
+        &sent_assets,
+        &[Asset {
+            info: asset_info.clone(),
+            amount: offer_amount,
+        }],
+    )?;
+
+    // Snapshot the balance, which already holds the offer amount sent along
+    let prev_balance = available_balance(deps.as_ref(), &env, &asset_info)?;
+    let config = CONFIG.load(deps.storage)?;
+    let adjustment = fee_adjustment(deps.as_ref(), &env, Some(&sender))?;
+    let fee_bps = effective_fee_bps(deps.storage, &config, &asset_info, &asset_info, &adjustment)?;
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    for route_info in &routes {
+        msgs.extend(route_msgs(
+            &env,
+            &route_info.route,
+            route_info.offer_amount,
+            None,
+        )?);
+    }
+    msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_json_binary(&ExecuteMsg::ExecutePostArbitrage {
+            asset_info,
+            offer_amount,
+            prev_balance,
+            minimum_profit,
+            to: to.unwrap_or(sender),
+            fee_bps,
+        })?,
+    }));
+
+    Ok(Response::new().add_messages(msgs))
+}
+
+/// Accrues Case 1 fees for the fee collector, then returns the messages that send every return
+/// asset back to its user/to and assert the minimum received of each.
+#[allow(clippy::too_many_arguments)]
+fn post_swap_msgs(
+    deps: DepsMut,
+    env: &Env,
+    sender: Addr,
+    offer_assets: Vec<Asset>,
+    fees: Vec<Asset>,
+    fee_exempt: bool,
+    referral: Option<(Referral, Vec<Asset>)>,
+    integrator: Option<String>,
+    outputs: Vec<(SwapOutput, u16)>,
+    minimum_receive_mode: MinimumReceiveMode,
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    let mut msgs: Vec<CosmosMsg> = vec![];
+
+    // Exempt senders pay no fee, recorded as a zero fee on every offer asset
+    let fees = if fee_exempt {
+        offer_assets
+            .iter()
+            .map(|offer_asset| Asset {
+                info: offer_asset.info.clone(),
+                amount: Uint128::zero(),
+            })
+            .collect()
+    } else {
+        fees
+    };
+
+    // Accrue Case 1 fees for the fee collector and the integrator, and send the referral's
+    let registered_integrator = load_integrator(deps.storage, integrator.as_ref())?;
+    for fee in &fees {
+        let integrator_fee_amount =
+            accrue_integrator_fee(deps.storage, registered_integrator.as_ref(), fee)?;
+        accrue_protocol_fee(
+            deps.storage,
+            &fee.info,
+            fee.amount.checked_sub(integrator_fee_amount)?,
+        )?;
+    }
+    if fees.is_empty() {
+        FEES_COLLECTED.remove(deps.storage);
+    } else {
+        FEES_COLLECTED.save(deps.storage, &fees)?;
+    }
+    let (referral, referral_fees) = referral.unzip();
+    let referral_fees = referral_fees.unwrap_or_default();
+    if let Some(referral) = &referral {
+        for referral_fee in &referral_fees {
+            msgs.push(referral_fee.to_send_msg(referral.address.to_string()));
+        }
+    }
+    if referral_fees.is_empty() {
+        REFERRAL_FEES_COLLECTED.remove(deps.storage);
+    } else {
+        REFERRAL_FEES_COLLECTED.save(deps.storage, &referral_fees)?;
+    }
+    FEEABLE_RETURNS.remove(deps.storage);
+    DIRECT_RETURN.remove(deps.storage);
+
+    // Send the return assets back to the user/to and emit all event logs. The minimum receive is
+    // asserted there on the amount received, and also here on the receiver's balance if asked
+    let mut post_swap_outputs: Vec<PostSwapOutput> = vec![];
+    let mut assert_msgs: Vec<CosmosMsg> = vec![];
+    for (output, fee_bps) in outputs {
+        let receiver = output.to.clone().unwrap_or(sender.clone());
+        post_swap_outputs.push(PostSwapOutput {
+            asset_info: output.asset_info.clone(),
+            to: receiver.clone(),
+            fee_bps,
+            minimum_receive: output.minimum_receive,
+        });
+        match minimum_receive_mode {
+            MinimumReceiveMode::RouterOutput {} => {}
+            MinimumReceiveMode::ReceiverBalance {} => {
+                let receiver_balance = query_balance(&deps.querier, &receiver, &output.asset_info)?;
+                assert_msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: env.contract.address.to_string(),
+                    funds: vec![],
+                    msg: to_json_binary(&ExecuteMsg::AssertMinimumReceive {
+                        receiver,
+                        asset_info: output.asset_info,
+                        prev_balance: receiver_balance,
+                        minimum_receive: output.minimum_receive,
+                    })?,
+                }));
+            }
+        }
+    }
+    msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_json_binary(&ExecuteMsg::ExecutePostSwap {
+            offer_assets,
+            outputs: post_swap_outputs,
+            referral,
+            integrator,
+            fee_exempt,
+        })?,
+    }));
+    msgs.extend(assert_msgs);
+
+    Ok(msgs)
+}
+
+fn swap_pool(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    operation: SwapOperation,
+    amount: Option<Uint128>,
+    to: Option<Addr>,
+) -> Result<Response, ContractError> {
+    // This is an internal function that's not meant to be executed by users
+    if env.contract.address != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let offer_amount = amount.unwrap_or(available_balance(
+        deps.as_ref(),
+        &env,
+        &operation.offer_asset,
+    )?);
+    let offer_asset = Asset {
+        info: operation.offer_asset.clone(),
+        amount: offer_amount,
+    };
+
+    let pays_to = to.is_some()
+        && matches!(
+            operation.interface()?,
+            SwapInterface::Astroport {} | SwapInterface::OraiDexV2 {}
+        );
+    let msg = swap_msg(deps.as_ref(), &operation, offer_asset, to)?;
+    if pays_to {
+        return Ok(
+            Response::new().add_submessage(SubMsg::reply_on_success(msg, DIRECT_RETURN_REPLY_ID))
+        );
+    }
+    Ok(Response::new().add_message(msg))
+}
+
+/// Executes every hop of a route, so that the whole route can run as a single sub message.
+fn execute_route(
+    env: Env,
+    sender: Addr,
+    route: Vec<SwapOperation>,
+    amount: Uint128,
+    to: Option<Addr>,
+) -> Result<Response, ContractError> {
+    // This is an internal function that's not meant to be executed by users
+    if env.contract.address != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    Ok(Response::new().add_messages(route_msgs(&env, &route, amount, to.as_ref())?))
+}
+
+/// Accrues, or burns if `burn` is set, the target asset returned by the conversion routes of
+/// `convert_fees`, asserting it reaches `minimum_receive`.
+fn settle_fee_conversion(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    asset_info: AssetInfo,
+    prev_balance: Uint128,
+    minimum_receive: Uint128,
+    burn: bool,
+) -> Result<Response, ContractError> {
+    // This is an internal function that's not meant to be executed by users
+    if env.contract.address != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let return_amount =
+        available_balance(deps.as_ref(), &env, &asset_info)?.checked_sub(prev_balance)?;
+    if return_amount < minimum_receive {
+        return Err(ContractError::AssertionMinimumReceive {
+            receive: minimum_receive,
+            amount: return_amount,
+        });
+    }
+    if !burn {
+        accrue_protocol_fee(deps.storage, &asset_info, return_amount)?;
+        return Ok(Response::new()
+            .add_attribute("action", "settle_fee_conversion")
+            .add_attribute("return_asset", asset_info.id())
+            .add_attribute("return_amount", return_amount));
+    }
+
+    let mut response = Response::new()
+        .add_attribute("action", "settle_fee_conversion")
+        .add_attribute("burned_asset", asset_info.id())
+        .add_attribute("burned_amount", return_amount);
+    if !return_amount.is_zero() {
+        BURNED_FEES.update(deps.storage, &asset_info.id(), |burned| -> StdResult<_> {
+            Ok(burned.unwrap_or_default().checked_add(return_amount)?)
+        })?;
+        response = response.add_message(asset_info.to_burn_msg(return_amount));
+    }
+    Ok(response)
+}
+
+/// Refunds the offer amount of every route that failed during a partial swap, including its
+/// fees, and accrues the Case 1 fees of the routes that succeeded for the fee collector and the
+/// integrator, sending the referral's.
+fn settle_partial_routes(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    refund_to: Addr,
+    referral: Option<Addr>,
+    integrator: Option<String>,
+) -> Result<Response, ContractError> {
+    // This is an internal function that's not meant to be executed by users
+    if env.contract.address != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let partial_routes = PARTIAL_ROUTES
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut refunds: Vec<Asset> = vec![];
+    let mut fees: Vec<Asset> = vec![];
+    let mut referral_fees: Vec<Asset> = vec![];
+    for (idx, partial_route) in partial_routes {
+        let offer_asset = partial_route.offer_asset;
+        if partial_route.failed {
+            add_asset(&mut refunds, &offer_asset.info, offer_asset.amount)?;
+        } else {
+            add_asset(&mut fees, &offer_asset.info, partial_route.fee_amount)?;
+            add_asset(
+                &mut referral_fees,
+                &offer_asset.info,
+                partial_route.referral_fee_amount,
+            )?;
+        }
+        PARTIAL_ROUTES.remove(deps.storage, idx);
+    }
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    let mut refund: Vec<(String, String)> = vec![];
+    for asset in &refunds {
+        msgs.push(asset.to_send_msg(refund_to.to_string()));
+        refund.push(("refund_asset".to_owned(), asset.id()));
+        refund.push(("refund_amount".to_owned(), asset.amount.to_string()));
+    }
+    let integrator = load_integrator(deps.storage, integrator.as_ref())?;
+    for fee in &fees {
+        let integrator_fee_amount = accrue_integrator_fee(deps.storage, integrator.as_ref(), fee)?;
+        accrue_protocol_fee(
+            deps.storage,
+            &fee.info,
+            fee.amount.checked_sub(integrator_fee_amount)?,
+        )?;
+    }
+    if !fees.is_empty() {
+        FEES_COLLECTED.save(deps.storage, &fees)?;
+    }
+    if let Some(referral) = referral {
+        for referral_fee in &referral_fees {
+            msgs.push(referral_fee.to_send_msg(referral.to_string()));
+        }
+    }
+    if !referral_fees.is_empty() {
+        REFERRAL_FEES_COLLECTED.save(deps.storage, &referral_fees)?;
+    }
+
+    Ok(Response::new().add_messages(msgs).add_attributes(refund))
+}
+
+/// Splits the router's balance of the shared offer asset across `operations` by their
+/// fraction. The last operation receives the remainder so no rounding dust is left behind.
+fn split_swap_pool(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    operations: Vec<SplitOperation>,
+    amount: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    // This is an internal function that's not meant to be executed by users
+    if env.contract.address != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let offer_asset_info = operations
+        .first()
+        .map(|split| split.operation.offer_asset.clone())
+        .ok_or(ContractError::InvalidRoute {})?;
+    let total_amount = match amount {
+        Some(amount) => amount,
+        None => available_balance(deps.as_ref(), &env, &offer_asset_info)?,
+    };
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    let mut remaining_amount = total_amount;
+    for (idx, split) in operations.iter().enumerate() {
+        let offer_amount = if idx + 1 == operations.len() {
+            remaining_amount
+        } else {
+            total_amount.mul_floor(split.fraction)
+        };
+        remaining_amount = remaining_amount.checked_sub(offer_amount)?;
+        if offer_amount.is_zero() {
+            continue;
+        }
+        msgs.push(swap_msg(
+            deps.as_ref(),
+            &split.operation,
+            Asset {
+                info: offer_asset_info.clone(),
+                amount: offer_amount,
+            },
+            None,
+        )?);
+    }
+
+    Ok(Response::new().add_messages(msgs))
+}
+
+/// Builds the message that swaps `offer_asset` on the pool of `operation`, leaving the
+/// return asset with this contract unless Astroport and OraiDEX pools are told to pay `to`.
+fn swap_msg(
+    deps: Deps,
+    operation: &SwapOperation,
+    offer_asset: Asset,
+    to: Option<Addr>,
+) -> Result<CosmosMsg, ContractError> {
+    let (offer_asset_info, return_asset_info, swap_addr) = (
+        operation.offer_asset.clone(),
+        operation.return_asset.clone(),
+        operation.contract_addr.clone(),
+    );
+
+    Ok(match operation.interface()? {
+        SwapInterface::Astroport {} | SwapInterface::OraiDexV2 {} => match &offer_asset.info {
+            AssetInfo::NativeToken { denom } => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: swap_addr.to_string(),
+                funds: vec![Coin {
+                    denom: denom.to_string(),
+                    amount: offer_asset.amount,
+                }],
+                msg: to_json_binary(&PairExecuteMsg::Swap {
+                    offer_asset,
+                    belief_price: Some(BELIEF_PRICE),
+                    max_spread: Some(MAX_SLIPPAGE),
+                    to: to.map(|to| to.to_string()),
+                })?,
+            }),
+            AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                funds: vec![],
+                msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                    contract: swap_addr.to_string(),
+                    amount: offer_asset.amount,
+                    msg: to_json_binary(&PairCw20HookMsg::Swap {
+                        belief_price: Some(BELIEF_PRICE),
+                        max_spread: Some(MAX_SLIPPAGE),
+                        to: to.map(|to| to.to_string()),
+                    })?,
+                })?,
+            }),
+        },
+        SwapInterface::Helix { market_id } => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: swap_addr.to_string(),
+            funds: vec![Coin {
+                denom: offer_asset_info.id(), // assume to be definitely a denom as Helix only supports native assets
+                amount: offer_asset.amount,
+            }],
+            msg: to_json_binary(&HelixExecuteMsg::Swap {
+                market_id,
+                minimum_receive: None,
+                to: None,
+            })?,
+        }),
+        SwapInterface::Astrovault {
+            pair_type: PairType::Xyk {},
+        } => match &offer_asset.info {
+            AssetInfo::NativeToken { denom } => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: swap_addr.to_string(),
+                funds: vec![Coin {
+                    denom: denom.to_string(),
+                    amount: offer_asset.amount,
+                }],
+                msg: to_json_binary(&AstrovaultXykExecuteMsg::Swap { offer_asset })?,
+            }),
+            AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                funds: vec![],
+                msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                    contract: swap_addr.to_string(),
+                    amount: offer_asset.amount,
+                    msg: to_json_binary(&Cw20AstrovaultXykExecuteMsg::Swap {})?,
+                })?,
+            }),
+        },
+        SwapInterface::Astrovault {
+            pair_type: PairType::Hybrid {},
+        } => match &offer_asset.info {
+            AssetInfo::NativeToken { denom } => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: swap_addr.to_string(),
+                funds: vec![Coin {
+                    denom: denom.to_string(),
+                    amount: offer_asset.amount,
+                }],
+                msg: to_json_binary(&AstrovaultHybridExecuteMsg::Swap {})?,
+            }),
+            AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                funds: vec![],
+                msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                    contract: swap_addr.to_string(),
+                    amount: offer_asset.amount,
+                    msg: to_json_binary(&AstrovaultHybridExecuteMsg::Swap {})?,
+                })?,
+            }),
+        },
+        SwapInterface::Astrovault {
+            pair_type: PairType::Stable {},
+        } => {
+            let pool_info = query_astrovault_pool_info(&deps.querier, &swap_addr)?;
+            let swap_to_asset_index = pool_info
+                .asset_infos
+                .iter()
+                .position(|info| info.id() == return_asset_info.id())
+                .ok_or(ContractError::InvalidRoute {})?
+                as u32;
+            let expected_return = Uint128::zero();
+            match &offer_asset.info {
+                AssetInfo::NativeToken { denom } => CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: swap_addr.to_string(),
+                    funds: vec![Coin {
+                        denom: denom.to_string(),
+                        amount: offer_asset.amount,
+                    }],
+                    msg: to_json_binary(&AstrovaultStableExecuteMsg::Swap {
+                        swap_to_asset_index,
+                        expected_return,
+                    })?,
+                }),
+                AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: contract_addr.to_string(),
+                    funds: vec![],
+                    msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                        contract: swap_addr.to_string(),
+                        amount: offer_asset.amount,
+                        msg: to_json_binary(&AstrovaultStableExecuteMsg::Swap {
+                            swap_to_asset_index,
+                            expected_return,
+                        })?,
+                    })?,
+                }),
+            }
+        }
+    })
+}
+
+/// Records the return asset received since the previous snapshot, once every route charged at
+/// `post_swap` with `fee_bps` has been executed, so that Case 2 fees charge each route its own
+/// fee and skip the return of offer assets charged in `swap`.
+fn snapshot_return(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    return_asset_info: AssetInfo,
+    fee_bps: u16,
+) -> Result<Response, ContractError> {
+    // This is an internal function that's not meant to be executed by users
+    if env.contract.address != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut feeable_returns = FEEABLE_RETURNS.may_load(deps.storage)?.unwrap_or_default();
+    let (snapshotted_amount, _) = feeable_return_fee(&feeable_returns)?;
+    let return_amount = available_balance(deps.as_ref(), &env, &return_asset_info)?;
+    feeable_returns.push(FeeableReturn {
+        fee_bps,
+        amount: return_amount.checked_sub(snapshotted_amount)?,
+    });
+    FEEABLE_RETURNS.save(deps.storage, &feeable_returns)?;
+    Ok(Response::default())
+}
+
+/// Sends the correct return amounts back to the users/to and emits all event logs.
+#[allow(clippy::too_many_arguments)]
+fn post_swap(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    offer_assets: Vec<Asset>,
+    outputs: Vec<PostSwapOutput>,
+    referral: Option<Referral>,
+    integrator: Option<String>,
+    fee_exempt: bool,
+) -> Result<Response, ContractError> {
+    // This is an internal function that's not meant to be executed by users
+    if env.contract.address != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut offer: Vec<(String, String)> = vec![];
+    for offer_asset in offer_assets {
+        offer.push(("offer_asset".to_owned(), offer_asset.id()));
+        offer.push(("offer_amount".to_owned(), offer_asset.amount.to_string()));
+    }
+
+    let mut returns: Vec<(String, String)> = vec![];
+    let mut fee: Vec<(String, String)> = vec![];
+    let mut msgs: Vec<CosmosMsg> = vec![];
+
+    // Case 1 which we charged in `swap` function, where the integrator's share was accrued
+    let integrator = load_integrator(deps.storage, integrator.as_ref())?;
+    let mut integrator_fee: Vec<(String, String)> = vec![];
+    if let Some((id, _)) = &integrator {
+        integrator_fee.push(("integrator".to_owned(), id.clone()));
+    }
+    let fees_collected = FEES_COLLECTED.may_load(deps.storage)?.unwrap_or_default();
+    for fees_collected in &fees_collected {
+        fee.push(("fee_asset".to_owned(), fees_collected.id()));
+        fee.push(("fee_amount".to_owned(), fees_collected.amount.to_string()));
+        if let Some((_, registered_integrator)) = &integrator {
+            let integrator_fee_amount = fees_collected
+                .amount
+                .mul_floor(registered_integrator.fee_share);
+            integrator_fee.push(("integrator_fee_asset".to_owned(), fees_collected.id()));
+            integrator_fee.push((
+                "integrator_fee_amount".to_owned(),
+                integrator_fee_amount.to_string(),
+            ));
+        }
+    }
+    let mut referral_fee: Vec<(String, String)> = vec![];
+    if let Some(referral) = &referral {
+        referral_fee.push(("referral".to_owned(), referral.address.to_string()));
+    }
+    let referral_fees_collected = REFERRAL_FEES_COLLECTED
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    for referral_fees_collected in &referral_fees_collected {
+        referral_fee.push((
+            "referral_fee_asset".to_owned(),
+            referral_fees_collected.id(),
+        ));
+        referral_fee.push((
+            "referral_fee_amount".to_owned(),
+            referral_fees_collected.amount.to_string(),
+        ));
+    }
+    let feeable_returns = FEEABLE_RETURNS.may_load(deps.storage)?;
+    // Only `swap` pays the receiver from the last hops, and it has a single output
+    let mut direct_return = DIRECT_RETURN.may_load(deps.storage)?.unwrap_or_default();
+
+    for output in outputs {
+        let return_asset_id = output.asset_info.id();
+        let mut return_amount = available_balance(deps.as_ref(), &env, &output.asset_info)?;
+
+        // Case 2 which we charged at the end, `post_swap` function, on the return of every
+        // group of routes sharing a fee when they were recorded. The zero fee of exempt senders
+        // is already recorded as collected
+        let (feeable_return, fee_amount) = match &feeable_returns {
+            Some(feeable_returns) => feeable_return_fee(feeable_returns)?,
+            None if fee_exempt || fees_collected.is_empty() => {
+                (return_amount, calc_fee(return_amount, output.fee_bps)?)
+            }
+            None => (Uint128::zero(), Uint128::zero()),
+        };
+        if !fee_exempt {
+            return_amount = return_amount.checked_sub(fee_amount)?;
+            if !fee_amount.is_zero() {
+                let integrator_fee_amount = accrue_integrator_fee(
+                    deps.storage,
+                    integrator.as_ref(),
+                    &Asset {
+                        info: output.asset_info.clone(),
+                        amount: fee_amount,
+                    },
+                )?;
+                accrue_protocol_fee(
+                    deps.storage,
+                    &output.asset_info,
+                    fee_amount.checked_sub(integrator_fee_amount)?,
+                )?;
+                fee.push(("fee_asset".to_owned(), return_asset_id.clone()));
+                fee.push(("fee_amount".to_owned(), fee_amount.to_string()));
+                if integrator.is_some() {
+                    integrator_fee
+                        .push(("integrator_fee_asset".to_owned(), return_asset_id.clone()));
+                    integrator_fee.push((
+                        "integrator_fee_amount".to_owned(),
+                        integrator_fee_amount.to_string(),
+                    ));
+                }
+            }
+        }
+        if let Some(referral) = &referral {
+            let referral_fee_amount = calc_fee(feeable_return, referral.fee_bps)?;
+            return_amount = return_amount.checked_sub(referral_fee_amount)?;
+            if !referral_fee_amount.is_zero() {
+                msgs.push(
+                    output
+                        .asset_info
+                        .to_send_msg(referral.address.to_string(), referral_fee_amount),
+                );
+                referral_fee.push(("referral_fee_asset".to_owned(), return_asset_id.clone()));
+                referral_fee.push((
+                    "referral_fee_amount".to_owned(),
+                    referral_fee_amount.to_string(),
+                ));
+            }
+        }
+
+        if !return_amount.is_zero() {
+            msgs.push(
+                output
+                    .asset_info
+                    .to_send_msg(output.to.to_string(), return_amount),
+            );
+        }
+
+        // Add what the last hops paid the receiver directly
+        return_amount = return_amount.checked_add(std::mem::take(&mut direct_return))?;
+        if return_amount < output.minimum_receive {
+            return Err(ContractError::AssertionMinimumReceive {
+                receive: output.minimum_receive,
+                amount: return_amount,
+            });
+        }
+        returns.push(("return_asset".to_owned(), return_asset_id));
+        returns.push(("return_amount".to_owned(), return_amount.to_string()));
+        returns.push(("receiver".to_owned(), output.to.to_string()));
+    }
+
+    FEES_COLLECTED.remove(deps.storage);
+    REFERRAL_FEES_COLLECTED.remove(deps.storage);
+    FEEABLE_RETURNS.remove(deps.storage);
+    DIRECT_RETURN.remove(deps.storage);
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("hallswap", "v1")
+        .add_attributes(offer)
+        .add_attributes(returns)
+        .add_attributes(fee)
+        .add_attributes(integrator_fee)
+        .add_attributes(referral_fee))
+}
+
+/// Charges fees on the profit of an arbitrage, asserts the minimum profit and sends the offer
+/// amount and the net profit back to the user/to.
+#[allow(clippy::too_many_arguments)]
+fn post_arbitrage(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    asset_info: AssetInfo,
+    offer_amount: Uint128,
+    prev_balance: Uint128,
+    minimum_profit: Uint128,
+    to: Addr,
+    fee_bps: u16,
+) -> Result<Response, ContractError> {
+    // This is an internal function that's not meant to be executed by users
+    if env.contract.address != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let current_balance = available_balance(deps.as_ref(), &env, &asset_info)?;
+    let profit = current_balance.checked_sub(prev_balance).map_err(|_| {
+        ContractError::AssertionMinimumProfit {
+            minimum_profit,
+            profit: Uint128::zero(),
+        }
+    })?;
+    let fee_amount = calc_fee(profit, fee_bps)?;
+    let net_profit = profit.checked_sub(fee_amount)?;
+    if net_profit < minimum_profit {
+        return Err(ContractError::AssertionMinimumProfit {
+            minimum_profit,
+            profit: net_profit,
+        });
+    }
+
+    let mut fee: Vec<(String, String)> = vec![];
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    if !fee_amount.is_zero() {
+        accrue_protocol_fee(deps.storage, &asset_info, fee_amount)?;
+        fee.push(("fee_asset".to_owned(), asset_info.id()));
+        fee.push(("fee_amount".to_owned(), fee_amount.to_string()));
+    }
+    let return_amount = offer_amount.checked_add(net_profit)?;
+    msgs.push(asset_info.to_send_msg(to.to_string(), return_amount));
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("hallswap", "v1")
+        .add_attribute("offer_asset", asset_info.id())
+        .add_attribute("offer_amount", offer_amount)
+        .add_attribute("return_asset", asset_info.id())
+        .add_attribute("return_amount", return_amount)
+        .add_attribute("profit", net_profit)
+        .add_attribute("receiver", to)
+        .add_attributes(fee))
+}
+
+/// Asserts that `receiver` will receive at least `min_output` of `asset_info`.
+fn assert_minimum_receive(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    receiver: Addr,
+    asset_info: AssetInfo,
+    prev_balance: Uint128,
+    minimum_receive: Uint128,
+) -> Result<Response, ContractError> {
+    // This is an internal function that's not meant to be executed by users
+    if env.contract.address != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let current_balance = query_balance(&deps.querier, &receiver, &asset_info)?;
+    let swap_amount = current_balance.checked_sub(prev_balance)?;
+    if swap_amount < minimum_receive {
+        Err(ContractError::AssertionMinimumReceive {
+            receive: minimum_receive,
+            amount: swap_amount,
+        })
+    } else {
+        Ok(Response::default())
+    }
+}
+
+fn update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if let Some(fee_recipients) = msg_fee_recipients(&msg)? {
+        config.fee_recipients = fee_recipients;
+    }
+    if let Some(owner) = msg.owner {
+        config.owner = owner;
+    }
+    if let Some(fee_bps) = msg.fee_bps {
+        config.fee_bps = fee_bps;
+    }
+    if let Some(fee_assets) = msg.fee_assets {
+        config.fee_assets = fee_assets;
+    }
+    if let Some(max_referral_bps) = msg.max_referral_bps {
+        config.max_referral_bps = max_referral_bps;
+    }
+
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new().add_attribute("action", "update_config"))
+}
+
+fn register_integrator(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: String,
+    address: Addr,
+    fee_share: Decimal,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if fee_share > Decimal::one() {
+        return Err(ContractError::InvalidIntegratorFeeShare { fee_share });
+    }
+
+    INTEGRATORS.save(deps.storage, &id, &Integrator { address, fee_share })?;
+    Ok(Response::new()
+        .add_attribute("action", "register_integrator")
+        .add_attribute("integrator", id))
+}
+
+fn set_asset_fee(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_info: AssetInfo,
+    fee_bps: Option<u16>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let asset_id = asset_info.id();
+    match fee_bps {
+        Some(fee_bps) => {
+            assert_fee_bps(fee_bps)?;
+            ASSET_FEES.save(deps.storage, &asset_id, &fee_bps)?;
+        }
+        None => ASSET_FEES.remove(deps.storage, &asset_id),
+    }
+    Ok(Response::new()
+        .add_attribute("action", "set_asset_fee")
+        .add_attribute("asset", asset_id)
+        .add_attribute(
+            "fee_bps",
+            fee_bps.map_or("none".to_string(), |fee| fee.to_string()),
+        ))
+}
+
+fn set_pair_fee(
+    deps: DepsMut,
+    info: MessageInfo,
+    offer_asset_info: AssetInfo,
+    return_asset_info: AssetInfo,
+    fee_bps: Option<u16>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let (offer_id, return_id) = (offer_asset_info.id(), return_asset_info.id());
+    match fee_bps {
+        Some(fee_bps) => {
+            assert_fee_bps(fee_bps)?;
+            PAIR_FEES.save(deps.storage, (&offer_id, &return_id), &fee_bps)?;
+        }
+        None => PAIR_FEES.remove(deps.storage, (&offer_id, &return_id)),
+    }
+    Ok(Response::new()
+        .add_attribute("action", "set_pair_fee")
+        .add_attribute("offer_asset", offer_id)
+        .add_attribute("return_asset", return_id)
+        .add_attribute(
+            "fee_bps",
+            fee_bps.map_or("none".to_string(), |fee| fee.to_string()),
+        ))
+}
+
+/// Sets or disables the volume tiers. Volumes already recorded in the former quote asset keep
+/// counting until they leave the window.
+fn set_volume_tiers(
+    deps: DepsMut,
+    info: MessageInfo,
+    volume_tiers: Option<VolumeTiers>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    match volume_tiers {
+        Some(volume_tiers) => {
+            assert_volume_tiers(&volume_tiers)?;
+            VOLUME_TIERS.save(deps.storage, &volume_tiers)?;
+        }
+        None => VOLUME_TIERS.remove(deps.storage),
+    }
+    Ok(Response::new().add_attribute("action", "set_volume_tiers"))
+}
+
+fn update_fee_exemptions(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<Addr>,
+    remove: Vec<Addr>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut attributes: Vec<(String, String)> = vec![];
+    for address in add {
+        FEE_EXEMPTIONS.save(deps.storage, &address, &Empty {})?;
+        attributes.push(("add".to_owned(), address.to_string()));
+    }
+    for address in remove {
+        FEE_EXEMPTIONS.remove(deps.storage, &address);
+        attributes.push(("remove".to_owned(), address.to_string()));
+    }
+    Ok(Response::new()
+        .add_attribute("action", "update_fee_exemptions")
+        .add_attributes(attributes))
+}
+
+fn set_fee_discounts(
+    deps: DepsMut,
+    info: MessageInfo,
+    discounts: Vec<FeeDiscount>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    assert_fee_discounts(&discounts)?;
+    FEE_DISCOUNTS.save(deps.storage, &discounts)?;
+    Ok(Response::new().add_attribute("action", "set_fee_discounts"))
+}
+
+/// Sends every fee accrued by the integrator of `id` to its address, releasing them from the
+/// balance reserved in this contract.
+fn claim_integrator_fees(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    let (id, integrator) = load_integrator(deps.storage, Some(&id))?
+        .ok_or(ContractError::IntegratorNotFound { id })?;
+
+    if info.sender != integrator.address {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    let mut claimed: Vec<(String, String)> = vec![];
+    for accrued_fee in integrator_assets(deps.as_ref(), &INTEGRATOR_FEES, &id)? {
+        let asset_id = accrued_fee.id();
+        INTEGRATOR_FEES.remove(deps.storage, (&id, &asset_id));
+        release_fee(deps.storage, &asset_id, accrued_fee.amount)?;
+        claimed.push(("claimed_asset".to_owned(), asset_id));
+        claimed.push(("claimed_amount".to_owned(), accrued_fee.amount.to_string()));
+        msgs.push(accrued_fee.to_send_msg(integrator.address.to_string()));
+    }
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "claim_integrator_fees")
+        .add_attribute("integrator", id)
+        .add_attributes(claimed))
+}
+
+/// Splits the protocol fees accrued in `assets` across the fee recipients by weight, the
+/// rounding dust going to the first recipient. Owner or fee recipients only.
+fn claim_fees(
+    deps: DepsMut,
+    info: MessageInfo,
+    assets: Vec<AssetInfo>,
+    recipient: Option<Addr>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    assert_fee_manager(&config, &info.sender)?;
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    let mut claimed: Vec<(String, String)> = vec![];
+    for asset_info in assets {
+        let asset_id = asset_info.id();
+        let Some(amount) = ACCRUED_FEES.may_load(deps.storage, &asset_id)? else {
+            continue;
+        };
+        ACCRUED_FEES.remove(deps.storage, &asset_id);
+        release_fee(deps.storage, &asset_id, amount)?;
+        claimed.push(("claimed_asset".to_owned(), asset_id));
+        claimed.push(("claimed_amount".to_owned(), amount.to_string()));
+        for (address, share) in split_fee(&config.fee_recipients, amount)? {
+            // The share of the sender goes to its recipient, if any
+            let address = match &recipient {
+                Some(recipient) if address == info.sender => recipient.clone(),
+                _ => address,
+            };
+            if !share.is_zero() {
+                msgs.push(asset_info.to_send_msg(address.to_string(), share));
+            }
         }
     }
 
-    // Send to fee collector for Case 1
-    if !total_fee_amount.is_zero() {
-        msgs.push(offer_asset_info.to_send_msg(config.fee_address.to_string(), total_fee_amount));
-        FEES_COLLECTED.save(
-            deps.storage,
-            &Asset {
-                info: offer_asset_info.clone(),
-                amount: total_fee_amount,
-            },
-        )?;
-    } else {
-        FEES_COLLECTED.remove(deps.storage);
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "claim_fees")
+        .add_attributes(claimed))
+}
+
+fn set_fee_conversion(
+    deps: DepsMut,
+    info: MessageInfo,
+    fee_conversion: Option<FeeConversion>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
     }
 
-    // Send the return asset back to the user/to and emit all event logs
-    let receiver = to.unwrap_or(sender.clone());
-    msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: env.contract.address.to_string(),
-        funds: vec![],
-        msg: to_json_binary(&ExecuteMsg::ExecutePostSwap {
-            offer_asset_info,
-            offer_amount: total_offer_amount,
-            return_asset_info: return_asset_info.clone(),
-            to: receiver.clone(),
-        })?,
-    }));
+    match fee_conversion {
+        Some(fee_conversion) => {
+            if fee_conversion.max_slippage_bps > 10000 {
+                return Err(ContractError::InvalidSlippageTolerance {
+                    max_slippage_bps: fee_conversion.max_slippage_bps,
+                });
+            }
+            FEE_CONVERSION.save(deps.storage, &fee_conversion)?;
+        }
+        None => FEE_CONVERSION.remove(deps.storage),
+    }
+    Ok(Response::new().add_attribute("action", "set_fee_conversion"))
+}
 
-    // Assert minimum received by the user
-    let receiver_balance = query_balance(&deps.querier, &receiver, &return_asset_info)?;
-    msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: env.contract.address.to_string(),
-        funds: vec![],
-        msg: to_json_binary(&ExecuteMsg::AssertMinimumReceive {
-            receiver,
-            asset_info: return_asset_info,
-            prev_balance: receiver_balance,
-            minimum_receive,
-        })?,
-    }));
+fn set_fee_conversion_route(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_info: AssetInfo,
+    route: Option<Vec<SwapOperation>>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
 
-    Ok(Response::new().add_messages(msgs))
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let asset_id = asset_info.id();
+    match route {
+        Some(route) => {
+            let fee_conversion = FEE_CONVERSION
+                .may_load(deps.storage)?
+                .ok_or(ContractError::FeeConversionDisabled {})?;
+            assert_conversion_route(&route, &asset_id, &fee_conversion.target_asset)?;
+            FEE_CONVERSION_ROUTES.save(deps.storage, &asset_id, &route)?;
+        }
+        None => FEE_CONVERSION_ROUTES.remove(deps.storage, &asset_id),
+    }
+    Ok(Response::new()
+        .add_attribute("action", "set_fee_conversion_route")
+        .add_attribute("asset", asset_id))
 }
 
-fn swap_pool(
+/// Swaps the accrued fees of `assets` into the target asset through their conversion routes,
+/// executed like the routes of `swap`. The return is asserted against the simulation of every
+/// route minus the configured slippage, and against `minimum_receive`, then accrued in the
+/// target asset, or burned together with the fees already accrued in it when the conversion
+/// burns. Anyone can convert, but since a simulation in the same block can be moved by whoever
+/// sandwiches the conversion, senders other than the fee managers must bound the return with
+/// `minimum_receive`.
+fn convert_fees(
     deps: DepsMut,
     env: Env,
-    sender: Addr,
-    operation: SwapOperation,
-    amount: Option<Uint128>,
+    info: MessageInfo,
+    assets: Option<Vec<AssetInfo>>,
+    minimum_receive: Option<Uint128>,
 ) -> Result<Response, ContractError> {
-    // This is an internal function that's not meant to be executed by users
-    if env.contract.address != sender {
-        return Err(ContractError::Unauthorized {});
+    let config = CONFIG.load(deps.storage)?;
+    if minimum_receive.is_none() && !is_fee_manager(&config, &info.sender) {
+        return Err(ContractError::MinimumReceiveRequired {});
     }
 
-    let (offer_asset_info, return_asset_info, swap_addr) = (
-        operation.clone().offer_asset,
-        operation.clone().return_asset,
-        operation.clone().contract_addr,
-    );
+    let fee_conversion = FEE_CONVERSION
+        .may_load(deps.storage)?
+        .ok_or(ContractError::FeeConversionDisabled {})?;
+    let target_asset = fee_conversion.target_asset;
+
+    let routes = match assets {
+        Some(assets) => assets
+            .iter()
+            .map(|asset_info| {
+                let asset_id = asset_info.id();
+                match FEE_CONVERSION_ROUTES.may_load(deps.storage, &asset_id)? {
+                    Some(route) => Ok((asset_id, route)),
+                    None => Err(ContractError::FeeConversionRouteNotFound { asset: asset_id }),
+                }
+            })
+            .collect::<Result<Vec<_>, ContractError>>()?,
+        None => FEE_CONVERSION_ROUTES
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?,
+    };
+
+    for (asset_id, route) in &routes {
+        assert_conversion_route(route, asset_id, &target_asset)?;
+    }
+    // The later hops swap the whole balance of their offer asset, which would include the fees
+    // released for another route
+    let asset_ids: Vec<&String> = routes.iter().map(|(asset_id, _)| asset_id).collect();
+    if routes.iter().any(|(_, route)| {
+        route[1..]
+            .iter()
+            .any(|swap_operation| asset_ids.contains(&&swap_operation.offer_asset.id()))
+    }) {
+        return Err(ContractError::InvalidRoute {});
+    }
 
+    let prev_balance = available_balance(deps.as_ref(), &env, &target_asset)?;
+    let caller_minimum_receive = minimum_receive;
+    let mut minimum_receive = Uint128::zero();
     let mut msgs: Vec<CosmosMsg> = vec![];
+    let mut converted: Vec<(String, String)> = vec![];
+    if fee_conversion.burn {
+        // Released after `prev_balance` so the settlement burns them with the return
+        let target_id = target_asset.id();
+        if let Some(amount) = ACCRUED_FEES.may_load(deps.storage, &target_id)? {
+            ACCRUED_FEES.remove(deps.storage, &target_id);
+            release_fee(deps.storage, &target_id, amount)?;
+            minimum_receive = amount;
+            converted.push(("converted_asset".to_owned(), target_id));
+            converted.push(("converted_amount".to_owned(), amount.to_string()));
+        }
+    }
+    for (route_index, (asset_id, route)) in routes.into_iter().enumerate() {
+        let Some(amount) = ACCRUED_FEES.may_load(deps.storage, &asset_id)? else {
+            continue;
+        };
 
-    let offer_amount = amount.unwrap_or(query_balance(
-        &deps.querier,
-        &env.contract.address,
-        &offer_asset_info,
-    )?);
-    let offer_asset = Asset {
-        info: offer_asset_info.clone(),
-        amount: offer_amount,
-    };
+        let mut simulated = Asset {
+            info: route[0].offer_asset.clone(),
+            amount,
+        };
+        for (hop_index, swap_operation) in route.iter().enumerate() {
+            simulated = simulate_operation(
+                deps.as_ref(),
+                swap_operation,
+                simulated,
+                route_index,
+                hop_index,
+            )?;
+        }
+        minimum_receive = minimum_receive.checked_add(
+            simulated
+                .amount
+                .multiply_ratio(10000u16 - fee_conversion.max_slippage_bps, 10000u16),
+        )?;
 
-    msgs.push(match operation.interface()? {
-        SwapInterface::Astroport {} | SwapInterface::OraiDexV2 {} => match &offer_asset.info {
-            AssetInfo::NativeToken { denom } => CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: swap_addr.to_string(),
-                funds: vec![Coin {
-                    denom: denom.to_string(),
-                    amount: offer_asset.amount,
-                }],
-                msg: to_json_binary(&PairExecuteMsg::Swap {
-                    offer_asset,
-                    belief_price: Some(BELIEF_PRICE),
-                    max_spread: Some(MAX_SLIPPAGE),
-                    to: None,
-                })?,
-            }),
-            AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: contract_addr.to_string(),
-                funds: vec![],
-                msg: to_json_binary(&Cw20ExecuteMsg::Send {
-                    contract: swap_addr.to_string(),
-                    amount: offer_asset.amount,
-                    msg: to_json_binary(&PairCw20HookMsg::Swap {
-                        belief_price: Some(BELIEF_PRICE),
-                        max_spread: Some(MAX_SLIPPAGE),
-                        to: None,
-                    })?,
-                })?,
-            }),
-        },
-        SwapInterface::Helix { market_id } => CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: swap_addr.to_string(),
-            funds: vec![Coin {
-                denom: offer_asset_info.id(), // assume to be definitely a denom as Helix only supports native assets
-                amount: offer_asset.amount,
-            }],
-            msg: to_json_binary(&HelixExecuteMsg::Swap {
-                market_id,
-                minimum_receive: None,
-                to: None,
+        // The converted fees become available to the routes
+        ACCRUED_FEES.remove(deps.storage, &asset_id);
+        release_fee(deps.storage, &asset_id, amount)?;
+        msgs.extend(route_msgs(&env, &route, amount, None)?);
+        converted.push(("converted_asset".to_owned(), asset_id));
+        converted.push(("converted_amount".to_owned(), amount.to_string()));
+    }
+    if !converted.is_empty() {
+        msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            funds: vec![],
+            msg: to_json_binary(&ExecuteMsg::ExecuteSettleFeeConversion {
+                asset_info: target_asset,
+                prev_balance,
+                minimum_receive: minimum_receive.max(caller_minimum_receive.unwrap_or_default()),
+                burn: fee_conversion.burn,
             })?,
-        }),
-        SwapInterface::Astrovault {
-            pair_type: PairType::Xyk {},
-        } => match &offer_asset.info {
-            AssetInfo::NativeToken { denom } => CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: swap_addr.to_string(),
-                funds: vec![Coin {
-                    denom: denom.to_string(),
-                    amount: offer_asset.amount,
-                }],
-                msg: to_json_binary(&AstrovaultXykExecuteMsg::Swap { offer_asset })?,
-            }),
-            AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: contract_addr.to_string(),
-                funds: vec![],
-                msg: to_json_binary(&Cw20ExecuteMsg::Send {
-                    contract: swap_addr.to_string(),
-                    amount: offer_asset.amount,
-                    msg: to_json_binary(&Cw20AstrovaultXykExecuteMsg::Swap {})?,
-                })?,
-            }),
-        },
-        SwapInterface::Astrovault {
-            pair_type: PairType::Hybrid {},
-        } => match &offer_asset.info {
-            AssetInfo::NativeToken { denom } => CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: swap_addr.to_string(),
-                funds: vec![Coin {
-                    denom: denom.to_string(),
-                    amount: offer_asset.amount,
-                }],
-                msg: to_json_binary(&AstrovaultHybridExecuteMsg::Swap {})?,
-            }),
-            AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: contract_addr.to_string(),
-                funds: vec![],
-                msg: to_json_binary(&Cw20ExecuteMsg::Send {
-                    contract: swap_addr.to_string(),
-                    amount: offer_asset.amount,
-                    msg: to_json_binary(&AstrovaultHybridExecuteMsg::Swap {})?,
-                })?,
-            }),
-        },
-        SwapInterface::Astrovault {
-            pair_type: PairType::Stable {},
-        } => {
-            let pool_info = query_astrovault_pool_info(&deps.querier, &swap_addr)?;
-            let swap_to_asset_index = pool_info
-                .asset_infos
-                .iter()
-                .position(|info| info.id() == return_asset_info.id())
-                .ok_or(ContractError::InvalidRoute {})?
-                as u32;
-            let expected_return = Uint128::zero();
-            match &offer_asset.info {
-                AssetInfo::NativeToken { denom } => CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: swap_addr.to_string(),
-                    funds: vec![Coin {
-                        denom: denom.to_string(),
-                        amount: offer_asset.amount,
-                    }],
-                    msg: to_json_binary(&AstrovaultStableExecuteMsg::Swap {
-                        swap_to_asset_index,
-                        expected_return,
-                    })?,
-                }),
-                AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: contract_addr.to_string(),
-                    funds: vec![],
-                    msg: to_json_binary(&Cw20ExecuteMsg::Send {
-                        contract: swap_addr.to_string(),
-                        amount: offer_asset.amount,
-                        msg: to_json_binary(&AstrovaultStableExecuteMsg::Swap {
-                            swap_to_asset_index,
-                            expected_return,
-                        })?,
-                    })?,
-                }),
+        }));
+    }
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "convert_fees")
+        .add_attributes(converted))
+}
+
+fn integrator_assets(
+    deps: Deps,
+    map: &Map<(&str, &str), Asset>,
+    id: &str,
+) -> StdResult<Vec<Asset>> {
+    map.prefix(id)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, asset)| asset))
+        .collect()
+}
+
+/// Returns the volume of `address` over the rolling window and the tiers around it, unset when
+/// volume tiers are disabled.
+fn user_tier(
+    deps: Deps,
+    env: &Env,
+    address: &Addr,
+) -> Result<Option<UserTierResponse>, ContractError> {
+    let Some(volume_tiers) = VOLUME_TIERS.may_load(deps.storage)? else {
+        return Ok(None);
+    };
+    let volume = total_volume(&window_buckets(deps.storage, env, &volume_tiers, address)?)?;
+    let (tier, next_tier) = reached_tiers(&volume_tiers, volume);
+    Ok(Some(UserTierResponse {
+        volume,
+        tier: tier.cloned(),
+        next_tier: next_tier.cloned(),
+    }))
+}
+
+fn accrued_fees(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<AccruedFee>, ContractError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+    Ok(ACCRUED_FEES
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(asset, amount)| AccruedFee { asset, amount }))
+        .collect::<StdResult<Vec<_>>>()?)
+}
+
+fn burned_fees(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<BurnedFee>, ContractError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+    Ok(BURNED_FEES
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(asset, amount)| BurnedFee { asset, amount }))
+        .collect::<StdResult<Vec<_>>>()?)
+}
+
+fn fee_exemptions(
+    deps: Deps,
+    start_after: Option<Addr>,
+    limit: Option<u32>,
+) -> Result<Vec<Addr>, ContractError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_ref().map(Bound::exclusive);
+    Ok(FEE_EXEMPTIONS
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?)
+}
+
+/// Returns the minimum receive to enforce on a swap, raised to the return of `simulation` and
+/// to `reference_quote` minus `max_slippage_bps` when a tolerance is given, in which case the
+/// swap is always simulated.
+fn slippage_minimum_receive(
+    minimum_receive: Uint128,
+    max_slippage_bps: Option<u16>,
+    reference_quote: Option<Uint128>,
+    simulation: Option<&RoutesSimulation>,
+) -> Result<Uint128, ContractError> {
+    let max_slippage_bps = match max_slippage_bps {
+        Some(max_slippage_bps) if max_slippage_bps > 10000 => {
+            return Err(ContractError::InvalidSlippageTolerance { max_slippage_bps })
+        }
+        Some(max_slippage_bps) => max_slippage_bps,
+        None => return Ok(minimum_receive),
+    };
+    let apply_slippage =
+        |amount: Uint128| amount.multiply_ratio(10000u16 - max_slippage_bps, 10000u16);
+
+    let simulated_return = simulation.map_or(Uint128::zero(), |simulation| {
+        simulation.result.return_asset.amount
+    });
+    let mut minimum_receive = minimum_receive.max(apply_slippage(simulated_return));
+    if let Some(reference_quote) = reference_quote {
+        minimum_receive = minimum_receive.max(apply_slippage(reference_quote));
+    }
+    Ok(minimum_receive)
+}
+
+fn assert_referral(config: &Config, referral: Option<&Referral>) -> Result<(), ContractError> {
+    match referral {
+        Some(referral) if referral.fee_bps > config.max_referral_bps => {
+            Err(ContractError::InvalidReferralFee {
+                fee_bps: referral.fee_bps,
+                max_referral_bps: config.max_referral_bps,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Asserts that the current block has not passed `deadline`.
+fn assert_deadline(env: &Env, deadline: Option<&Deadline>) -> Result<(), ContractError> {
+    if let Some(deadline) = deadline {
+        let is_expired = deadline
+            .timestamp
+            .is_some_and(|timestamp| env.block.time > timestamp)
+            || deadline
+                .height
+                .is_some_and(|height| env.block.height > height);
+        if is_expired {
+            return Err(ContractError::Expired {
+                deadline: deadline.clone(),
+                height: env.block.height,
+                time: env.block.time,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn calc_referral_fee(
+    amount: Uint128,
+    referral: Option<&Referral>,
+) -> Result<Uint128, ContractError> {
+    match referral {
+        Some(referral) => calc_fee(amount, referral.fee_bps),
+        None => Ok(Uint128::zero()),
+    }
+}
+
+/// Returns the basis points value of `amount`.
+fn calc_fee(amount: Uint128, fee_bps: u16) -> Result<Uint128, ContractError> {
+    Ok(amount
+        .checked_mul(Uint128::from(fee_bps))
+        .map_err(|_| ContractError::FeeOverflow { amount, fee_bps })?
+        .checked_div(Uint128::from(10000u16))?)
+}
+
+/// Returns the total amount offered per offer asset and the return asset shared by all routes.
+fn get_offer_return_assets(
+    routes: &[RouteInfoV2],
+) -> Result<(Vec<Asset>, AssetInfo), ContractError> {
+    let return_asset_info = if let Some(route_info) = routes.first() {
+        if let Some(swap_operation) = route_info.route.last() {
+            Ok(swap_operation.return_asset.clone())
+        } else {
+            Err(ContractError::InvalidRoute {})
+        }
+    } else {
+        Err(ContractError::InvalidRoute {})
+    }?;
+
+    let mut offer_assets: Vec<Asset> = vec![];
+    for route_info in routes {
+        let (first, last) = match (route_info.route.first(), route_info.route.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(ContractError::InvalidRoute {}),
+        };
+        if last.return_asset.id() != return_asset_info.id()
+            || first.offer_asset.id() == return_asset_info.id()
+        {
+            return Err(ContractError::InvalidRoute {});
+        }
+        add_asset(
+            &mut offer_assets,
+            &first.offer_asset,
+            route_info.offer_amount,
+        )?;
+    }
+
+    let mut swept_assets: Vec<&AssetInfo> = offer_assets
+        .iter()
+        .map(|offer_asset| &offer_asset.info)
+        .collect();
+    swept_assets.push(&return_asset_info);
+    assert_intermediate_assets(routes, &swept_assets)?;
+    Ok((offer_assets, return_asset_info))
+}
+
+/// Asserts that no hop after the first of a route offers one of `assets`. Those hops swap the
+/// router's whole balance of their offer asset, which would take what the other routes offer or
+/// already returned.
+fn assert_intermediate_assets(
+    routes: &[RouteInfoV2],
+    assets: &[&AssetInfo],
+) -> Result<(), ContractError> {
+    for route_info in routes {
+        for swap_operation in route_info.route.iter().skip(1) {
+            if offers_any(swap_operation, assets) {
+                return Err(ContractError::InvalidRoute {});
             }
         }
-    });
+    }
+    Ok(())
+}
 
-    Ok(Response::new().add_messages(msgs))
+fn offers_any(swap_operation: &SwapOperation, assets: &[&AssetInfo]) -> bool {
+    assets
+        .iter()
+        .any(|asset_info| asset_info.id() == swap_operation.offer_asset.id())
 }
 
-/// Sends the correct return amount back to the user/to and emits all event logs.
-fn post_swap(
-    deps: DepsMut,
-    env: Env,
-    sender: Addr,
-    offer_asset_info: AssetInfo,
-    offer_amount: Uint128,
-    return_asset_info: AssetInfo,
-    to: Addr,
-) -> Result<Response, ContractError> {
-    // This is an internal function that's not meant to be executed by users
-    if env.contract.address != sender {
-        return Err(ContractError::Unauthorized {});
+/// Returns the total offered of the single offer asset shared by all routes, checking that
+/// every route ends at one of `outputs`, that every output is reached by a route and that no
+/// later hop swaps an output away.
+fn get_offer_assets_multi_output(
+    routes: &[RouteInfoV2],
+    outputs: &[SwapOutput],
+) -> Result<Vec<Asset>, ContractError> {
+    let mut offer_assets: Vec<Asset> = vec![];
+    for route_info in routes {
+        let (first, last) = match (route_info.route.first(), route_info.route.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(ContractError::InvalidRoute {}),
+        };
+        if !outputs
+            .iter()
+            .any(|output| output.asset_info.id() == last.return_asset.id())
+        {
+            return Err(ContractError::InvalidRoute {});
+        }
+        add_asset(
+            &mut offer_assets,
+            &first.offer_asset,
+            route_info.offer_amount,
+        )?;
+    }
+    if offer_assets.len() != 1 {
+        return Err(ContractError::InvalidRoute {});
     }
 
-    let config = CONFIG.load(deps.storage)?;
+    for (idx, output) in outputs.iter().enumerate() {
+        let is_duplicate = outputs[..idx]
+            .iter()
+            .any(|other| other.asset_info.id() == output.asset_info.id());
+        let is_reached = routes.iter().any(|route_info| {
+            route_info.route[route_info.route.len() - 1]
+                .return_asset
+                .id()
+                == output.asset_info.id()
+        });
+        if is_duplicate || !is_reached || output.asset_info.id() == offer_assets[0].id() {
+            return Err(ContractError::InvalidRoute {});
+        }
+    }
 
-    let offer_asset_id = offer_asset_info.id();
-    let return_asset_id = return_asset_info.id();
-    let mut return_amount =
-        query_balance(&deps.querier, &env.contract.address, &return_asset_info)?;
+    let mut swept_assets: Vec<&AssetInfo> = vec![&offer_assets[0].info];
+    swept_assets.extend(outputs.iter().map(|output| &output.asset_info));
+    assert_intermediate_assets(routes, &swept_assets)?;
+    Ok(offer_assets)
+}
 
-    let mut fee: Vec<(String, String)> = vec![];
+/// Returns the asset that every route of an arbitrage starts and ends at.
+fn get_cyclic_asset(routes: &[RouteInfoV2]) -> Result<AssetInfo, ContractError> {
+    let asset_info = routes
+        .first()
+        .and_then(|route_info| route_info.route.first())
+        .map(|swap_operation| swap_operation.offer_asset.clone())
+        .ok_or(ContractError::InvalidRoute {})?;
+    for route_info in routes {
+        let (first, last) = match (route_info.route.first(), route_info.route.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(ContractError::InvalidRoute {}),
+        };
+        if first.offer_asset.id() != asset_info.id() || last.return_asset.id() != asset_info.id() {
+            return Err(ContractError::InvalidRoute {});
+        }
+    }
+    Ok(asset_info)
+}
+
+/// Asserts that the assets sent along with the swap match what the routes offer.
+fn assert_sent_assets(sent_assets: &[Asset], offer_assets: &[Asset]) -> Result<(), ContractError> {
+    let find_amount = |assets: &[Asset], id: &String| {
+        assets
+            .iter()
+            .find(|asset| &asset.id() == id)
+            .map_or(Uint128::zero(), |asset| asset.amount)
+    };
+    for asset in sent_assets.iter().chain(offer_assets) {
+        let (expected, received) = (
+            find_amount(offer_assets, &asset.id()),
+            find_amount(sent_assets, &asset.id()),
+        );
+        if expected != received {
+            return Err(ContractError::InvalidFunds {
+                asset: asset.id(),
+                expected,
+                received,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the messages pulling from `sender` the cw20 offer assets that were not sent along with
+/// the swap, through `TransferFrom` on the allowance it gave to this contract, and the sent
+/// assets including them. Native coins and the cw20 of a `Receive` hook can so be offered in the
+/// same basket.
+fn pull_cw20_assets(
+    env: &Env,
+    sender: &Addr,
+    mut sent_assets: Vec<Asset>,
+    offer_assets: &[Asset],
+) -> StdResult<(Vec<Asset>, Vec<CosmosMsg>)> {
     let mut msgs: Vec<CosmosMsg> = vec![];
+    for offer_asset in offer_assets {
+        let AssetInfo::Token { contract_addr } = &offer_asset.info else {
+            continue;
+        };
+        if sent_assets
+            .iter()
+            .any(|asset| asset.id() == offer_asset.id())
+        {
+            continue;
+        }
+        msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            funds: vec![],
+            msg: to_json_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: sender.to_string(),
+                recipient: env.contract.address.to_string(),
+                amount: offer_asset.amount,
+            })?,
+        }));
+        sent_assets.push(offer_asset.clone());
+    }
+    Ok((sent_assets, msgs))
+}
 
-    let fees_collected = FEES_COLLECTED.may_load(deps.storage)?;
-    if let Some(fees_collected) = fees_collected {
-        // Case 1 which we charged in `swap` function
-        fee.push(("fee_asset".to_owned(), fees_collected.info.id()));
-        fee.push(("fee_amount".to_owned(), fees_collected.amount.to_string()));
-    } else if config.fee_bps > 0 {
-        // Case 2 which we charged at the end, `post_swap` function
-        let fee_amount = calc_fee(return_amount, config.fee_bps)?;
-        return_amount = return_amount.checked_sub(fee_amount)?;
-        if !fee_amount.is_zero() {
-            msgs.push(return_asset_info.to_send_msg(config.fee_address.to_string(), fee_amount));
-            fee.push(("fee_asset".to_owned(), return_asset_id.clone()));
-            fee.push(("fee_amount".to_owned(), fee_amount.to_string()));
+/// Returns the native coins sent along with a message as assets.
+fn native_assets(funds: &[Coin]) -> Vec<Asset> {
+    funds
+        .iter()
+        .map(|coin| Asset {
+            info: AssetInfo::NativeToken {
+                denom: coin.denom.clone(),
+            },
+            amount: coin.amount,
+        })
+        .collect()
+}
+
+/// Adds `amount` of `info` to `assets`, merging it with an existing entry of the same asset.
+fn add_asset(assets: &mut Vec<Asset>, info: &AssetInfo, amount: Uint128) -> StdResult<()> {
+    if amount.is_zero() {
+        return Ok(());
+    }
+    if let Some(asset) = assets.iter_mut().find(|asset| asset.id() == info.id()) {
+        asset.amount = asset.amount.checked_add(amount)?;
+    } else {
+        assets.push(Asset {
+            info: info.clone(),
+            amount,
+        });
+    }
+    Ok(())
+}
+
+/// Returns whether fees on `offer_asset_info` are charged at `swap` (Case 1).
+fn is_fee_offer_asset(config: &Config, fee_bps: u16, offer_asset_info: &AssetInfo) -> bool {
+    fee_bps > 0 && config.fee_assets.contains(&offer_asset_info.id())
+}
+
+fn assert_fee_bps(fee_bps: u16) -> Result<(), ContractError> {
+    if fee_bps > 10000 {
+        return Err(ContractError::InvalidFeeBps { fee_bps });
+    }
+    Ok(())
+}
+
+/// Returns the fee of swaps from `offer_asset_info` to `return_asset_info`: the pair override,
+/// else the offer asset override, else the return asset override, else the global fee. The
+/// fee is then lowered by the sender's `adjustment`.
+fn effective_fee_bps(
+    storage: &dyn Storage,
+    config: &Config,
+    offer_asset_info: &AssetInfo,
+    return_asset_info: &AssetInfo,
+    adjustment: &FeeAdjustment,
+) -> StdResult<u16> {
+    if adjustment.exempt {
+        return Ok(0);
+    }
+    let (offer_id, return_id) = (offer_asset_info.id(), return_asset_info.id());
+    let fee_bps = if let Some(fee_bps) = PAIR_FEES.may_load(storage, (&offer_id, &return_id))? {
+        fee_bps
+    } else if let Some(fee_bps) = ASSET_FEES.may_load(storage, &offer_id)? {
+        fee_bps
+    } else if let Some(fee_bps) = ASSET_FEES.may_load(storage, &return_id)? {
+        fee_bps
+    } else {
+        config.fee_bps
+    };
+    let fee_bps = adjustment.cap.map_or(fee_bps, |cap| fee_bps.min(cap));
+    // The discounted fee cannot exceed `fee_bps`, so it always fits in a u16
+    Ok(Uint128::from(fee_bps)
+        .mul_floor(Decimal::one() - adjustment.discount)
+        .u128() as u16)
+}
+
+/// A route with its index in the message and its effective fee
+type FeeRoute = (usize, RouteInfoV2, u16);
+
+/// Splits `routes` into the ones charged on their offer asset and the others, keeping the
+/// original index and the effective fee of every route.
+fn partition_routes(
+    storage: &dyn Storage,
+    config: &Config,
+    routes: Vec<RouteInfoV2>,
+    return_asset_info: &AssetInfo,
+    adjustment: &FeeAdjustment,
+) -> StdResult<(Vec<FeeRoute>, Vec<FeeRoute>)> {
+    let mut charged = vec![];
+    let mut uncharged: Vec<FeeRoute> = vec![];
+    for (idx, route_info) in routes.into_iter().enumerate() {
+        let offer_asset_info = &route_info.route[0].offer_asset;
+        let fee_bps = effective_fee_bps(
+            storage,
+            config,
+            offer_asset_info,
+            return_asset_info,
+            adjustment,
+        )?;
+        if is_fee_offer_asset(config, fee_bps, offer_asset_info) {
+            charged.push((idx, route_info, fee_bps));
+        } else {
+            uncharged.push((idx, route_info, fee_bps));
         }
     }
+    // Uncharged routes run grouped by fee, see `snapshot_return`
+    uncharged.sort_by_key(|(_, _, fee_bps)| *fee_bps);
+    Ok((charged, uncharged))
+}
 
-    msgs.push(return_asset_info.to_send_msg(to.to_string(), return_amount));
-    FEES_COLLECTED.remove(deps.storage);
-    Ok(Response::new()
-        .add_messages(msgs)
-        .add_attribute("hallswap", "v1")
-        .add_attribute("offer_asset", offer_asset_id)
-        .add_attribute("offer_amount", offer_amount)
-        .add_attribute("return_asset", return_asset_id)
-        .add_attribute("return_amount", return_amount)
-        .add_attribute("receiver", to)
-        .add_attributes(fee))
+/// Adds `amount` returned by a route charged `fee_bps` at `post_swap` to `feeable_returns`.
+fn add_feeable_return(
+    feeable_returns: &mut Vec<FeeableReturn>,
+    fee_bps: u16,
+    amount: Uint128,
+) -> StdResult<()> {
+    match feeable_returns
+        .iter_mut()
+        .find(|feeable_return| feeable_return.fee_bps == fee_bps)
+    {
+        Some(feeable_return) => {
+            feeable_return.amount = feeable_return.amount.checked_add(amount)?
+        }
+        None => feeable_returns.push(FeeableReturn { fee_bps, amount }),
+    }
+    Ok(())
 }
 
-/// Asserts that `receiver` will receive at least `min_output` of `asset_info`.
-fn assert_minimum_receive(
-    deps: DepsMut,
-    env: Env,
-    sender: Addr,
-    receiver: Addr,
-    asset_info: AssetInfo,
-    prev_balance: Uint128,
-    minimum_receive: Uint128,
-) -> Result<Response, ContractError> {
-    // This is an internal function that's not meant to be executed by users
-    if env.contract.address != sender {
+/// Returns the total of `feeable_returns` and the Case 2 fee on it, each return charged its
+/// own fee.
+fn feeable_return_fee(
+    feeable_returns: &[FeeableReturn],
+) -> Result<(Uint128, Uint128), ContractError> {
+    let mut total = (Uint128::zero(), Uint128::zero());
+    for feeable_return in feeable_returns {
+        total = (
+            total.0.checked_add(feeable_return.amount)?,
+            total
+                .1
+                .checked_add(calc_fee(feeable_return.amount, feeable_return.fee_bps)?)?,
+        );
+    }
+    Ok(total)
+}
+
+/// Returns the highest effective fee of `routes`, which is only zero when none of their
+/// returns is charged at `post_swap`.
+fn max_fee_bps(routes: &[FeeRoute]) -> u16 {
+    routes
+        .iter()
+        .map(|(_, _, fee_bps)| fee_bps)
+        .max()
+        .copied()
+        .unwrap_or(0)
+}
+
+/// Lowers the fee of a sender, see `fee_adjustment`
+#[derive(Default)]
+struct FeeAdjustment {
+    /// Whether the sender pays no protocol fee at all
+    exempt: bool,
+    /// Fee of the volume tier the sender reached
+    cap: Option<u16>,
+    /// Highest holder discount the sender is eligible to
+    discount: Decimal,
+}
+
+/// Returns how the fee of `sender` is lowered: waived if it is exempt, else capped by its volume
+/// tier then discounted by its holdings. No adjustment applies without a sender.
+fn fee_adjustment(
+    deps: Deps,
+    env: &Env,
+    sender: Option<&Addr>,
+) -> Result<FeeAdjustment, ContractError> {
+    let Some(sender) = sender else {
+        return Ok(FeeAdjustment::default());
+    };
+    if FEE_EXEMPTIONS.has(deps.storage, sender) {
+        return Ok(FeeAdjustment {
+            exempt: true,
+            ..FeeAdjustment::default()
+        });
+    }
+    let cap = match VOLUME_TIERS.may_load(deps.storage)? {
+        Some(volume_tiers) => {
+            let volume = total_volume(&window_buckets(deps.storage, env, &volume_tiers, sender)?)?;
+            reached_tiers(&volume_tiers, volume)
+                .0
+                .map(|tier| tier.fee_bps)
+        }
+        None => None,
+    };
+    Ok(FeeAdjustment {
+        exempt: false,
+        cap,
+        discount: holder_discount(deps, sender)?,
+    })
+}
+
+/// Returns the highest discount `sender` is eligible to through its holdings. A holding that
+/// cannot be queried grants no discount, rather than failing the swap.
+fn holder_discount(deps: Deps, sender: &Addr) -> Result<Decimal, ContractError> {
+    let mut best = Decimal::zero();
+    for fee_discount in FEE_DISCOUNTS.may_load(deps.storage)?.unwrap_or_default() {
+        if fee_discount.discount <= best {
+            continue;
+        }
+        let is_eligible = match &fee_discount.condition {
+            HoldingCondition::Cw20Balance { token, min_balance } => {
+                query_token_balance(&deps.querier, sender, &token.to_string())
+                    .is_ok_and(|balance| balance >= *min_balance)
+            }
+            HoldingCondition::Cw721Owner { collection } => {
+                query_nft_owner(&deps.querier, sender, collection).unwrap_or(false)
+            }
+        };
+        if is_eligible {
+            best = fee_discount.discount;
+        }
+    }
+    Ok(best)
+}
+
+/// Returns whether `sender` is the owner or a fee recipient, who manage the accrued protocol fees.
+fn is_fee_manager(config: &Config, sender: &Addr) -> bool {
+    *sender == config.owner
+        || config
+            .fee_recipients
+            .iter()
+            .any(|fee_recipient| fee_recipient.address == *sender)
+}
+
+fn assert_fee_manager(config: &Config, sender: &Addr) -> Result<(), ContractError> {
+    if !is_fee_manager(config, sender) {
         return Err(ContractError::Unauthorized {});
     }
+    Ok(())
+}
 
-    let current_balance = query_balance(&deps.querier, &receiver, &asset_info)?;
-    let swap_amount = current_balance.checked_sub(prev_balance)?;
-    if swap_amount < minimum_receive {
-        Err(ContractError::AssertionMinimumReceive {
-            receive: minimum_receive,
-            amount: swap_amount,
+/// Asserts that `route` swaps the asset of `asset_id` into `target_asset` through chained hops
+/// with a known interface.
+fn assert_conversion_route(
+    route: &[SwapOperation],
+    asset_id: &str,
+    target_asset: &AssetInfo,
+) -> Result<(), ContractError> {
+    let (Some(first), Some(last)) = (route.first(), route.last()) else {
+        return Err(ContractError::InvalidRoute {});
+    };
+    let is_chained = route
+        .windows(2)
+        .all(|hops| hops[0].return_asset.id() == hops[1].offer_asset.id());
+    if first.offer_asset.id() != asset_id
+        || last.return_asset.id() != target_asset.id()
+        || asset_id == target_asset.id()
+        || !is_chained
+    {
+        return Err(ContractError::InvalidRoute {});
+    }
+    for (hop_index, swap_operation) in route.iter().enumerate() {
+        decode_interface(swap_operation, 0, hop_index)?;
+    }
+    Ok(())
+}
+
+/// Returns the fee recipients set by `msg`, its `fee_address` counting as a single recipient.
+fn msg_fee_recipients(msg: &InstantiateMsg) -> Result<Option<Vec<FeeRecipient>>, ContractError> {
+    let fee_recipients = match (&msg.fee_recipients, &msg.fee_address) {
+        (Some(fee_recipients), _) => fee_recipients.clone(),
+        (None, Some(fee_address)) => vec![FeeRecipient {
+            address: fee_address.clone(),
+            weight: 1,
+        }],
+        (None, None) => return Ok(None),
+    };
+    let invalid = |reason: &str| {
+        Err(ContractError::InvalidFeeRecipients {
+            reason: reason.to_string(),
         })
-    } else {
-        Ok(Response::default())
+    };
+    if fee_recipients.is_empty() {
+        return invalid("at least one recipient is required");
     }
+    if fee_recipients.len() > MAX_FEE_RECIPIENTS {
+        return invalid("too many recipients");
+    }
+    if fee_recipients
+        .iter()
+        .any(|fee_recipient| fee_recipient.weight == 0)
+    {
+        return invalid("weights must be positive");
+    }
+    Ok(Some(fee_recipients))
 }
 
-fn update_config(
-    deps: DepsMut,
-    info: MessageInfo,
-    msg: InstantiateMsg,
-) -> Result<Response, ContractError> {
-    let mut config = CONFIG.load(deps.storage)?;
+/// Returns the fee recipients of the stored config, including a config stored with a single
+/// `fee_address`.
+fn stored_fee_recipients(storage: &dyn Storage) -> Option<Vec<FeeRecipient>> {
+    if let Ok(Some(config)) = CONFIG.may_load(storage) {
+        return Some(config.fee_recipients);
+    }
+    LEGACY_CONFIG
+        .may_load(storage)
+        .ok()
+        .flatten()
+        .map(|legacy_config| {
+            vec![FeeRecipient {
+                address: legacy_config.fee_address,
+                weight: 1,
+            }]
+        })
+}
 
-    if info.sender != config.owner {
-        return Err(ContractError::Unauthorized {});
+fn assert_fee_discounts(discounts: &[FeeDiscount]) -> Result<(), ContractError> {
+    if discounts.len() > MAX_FEE_DISCOUNTS {
+        return Err(ContractError::InvalidFeeDiscounts {
+            reason: format!("at most {MAX_FEE_DISCOUNTS} discounts are allowed"),
+        });
+    }
+    if let Some(fee_discount) = discounts
+        .iter()
+        .find(|fee_discount| fee_discount.discount > Decimal::one())
+    {
+        return Err(ContractError::InvalidFeeDiscounts {
+            reason: format!("discount {} is above 1", fee_discount.discount),
+        });
     }
+    Ok(())
+}
 
-    if let Some(owner) = msg.owner {
-        config.owner = owner;
+fn assert_volume_tiers(volume_tiers: &VolumeTiers) -> Result<(), ContractError> {
+    let invalid = |reason: &str| {
+        Err(ContractError::InvalidVolumeTiers {
+            reason: reason.to_string(),
+        })
+    };
+    let (window_seconds, bucket_seconds) =
+        (volume_tiers.window_seconds, volume_tiers.bucket_seconds);
+    if bucket_seconds == 0 || window_seconds < bucket_seconds {
+        return invalid("window must hold at least one bucket");
     }
-    if let Some(fee_address) = msg.fee_address {
-        config.fee_address = fee_address;
+    if window_seconds / bucket_seconds > MAX_VOLUME_BUCKETS {
+        return invalid("window holds too many buckets");
     }
-    if let Some(fee_bps) = msg.fee_bps {
-        config.fee_bps = fee_bps;
+    if !volume_tiers
+        .tiers
+        .windows(2)
+        .all(|tiers| tiers[0].min_volume < tiers[1].min_volume)
+    {
+        return invalid("tiers must have increasing minimum volumes");
     }
-    if let Some(fee_assets) = msg.fee_assets {
-        config.fee_assets = fee_assets;
+    for tier in &volume_tiers.tiers {
+        assert_fee_bps(tier.fee_bps)?;
+    }
+    let price_routes = volume_tiers.price_routes.as_deref().unwrap_or_default();
+    for (index, price_route) in price_routes.iter().enumerate() {
+        let Some(first) = price_route.first() else {
+            return invalid("price routes must not be empty");
+        };
+        let asset_id = first.offer_asset.id();
+        if price_routes[..index]
+            .iter()
+            .any(|other| other[0].offer_asset.id() == asset_id)
+        {
+            return invalid("price routes must have distinct offer assets");
+        }
+        assert_conversion_route(price_route, &asset_id, &volume_tiers.quote_asset)?;
     }
+    Ok(())
+}
 
-    CONFIG.save(deps.storage, &config)?;
-    Ok(Response::new().add_attribute("action", "update_config"))
+/// Returns the buckets of `address` that are still within the rolling window.
+fn window_buckets(
+    storage: &dyn Storage,
+    env: &Env,
+    volume_tiers: &VolumeTiers,
+    address: &Addr,
+) -> StdResult<Vec<VolumeBucket>> {
+    let now = env.block.time.seconds();
+    let mut buckets = USER_VOLUMES.may_load(storage, address)?.unwrap_or_default();
+    buckets.retain(|bucket| bucket.start + volume_tiers.window_seconds > now);
+    Ok(buckets)
 }
 
-/// Returns the basis points value of `amount`.
-fn calc_fee(amount: Uint128, fee_bps: u16) -> StdResult<Uint128> {
-    Ok(amount
-        .checked_mul(Uint128::from(fee_bps))?
-        .checked_div(Uint128::from(10000u16))?)
+fn total_volume(buckets: &[VolumeBucket]) -> StdResult<Uint128> {
+    let mut volume = Uint128::zero();
+    for bucket in buckets {
+        volume = volume.checked_add(bucket.volume)?;
+    }
+    Ok(volume)
 }
 
-fn get_offer_return_asset(
-    routes: &[RouteInfoV2],
-) -> Result<(AssetInfo, AssetInfo), ContractError> {
-    let offer_asset_info = if let Some(route_info) = routes.first() {
-        if let Some(swap_operation) = route_info.route.first() {
-            Ok(swap_operation.offer_asset.clone())
-        } else {
-            Err(ContractError::InvalidRoute {})
+/// Returns the highest tier reached with `volume` and the one after it.
+fn reached_tiers(
+    volume_tiers: &VolumeTiers,
+    volume: Uint128,
+) -> (Option<&VolumeTier>, Option<&VolumeTier>) {
+    let tiers = &volume_tiers.tiers;
+    let reached = tiers
+        .iter()
+        .take_while(|tier| tier.min_volume <= volume)
+        .count();
+    (
+        reached.checked_sub(1).map(|index| &tiers[index]),
+        tiers.get(reached),
+    )
+}
+
+/// Adds the volume of the swap, valued in the quote asset by `quote_value`, to the rolling
+/// window of `sender`. Does nothing when volume tiers are disabled.
+fn track_user_volume<F>(
+    deps: DepsMut,
+    env: &Env,
+    sender: &Addr,
+    quote_value: F,
+) -> Result<(), ContractError>
+where
+    F: FnOnce(Deps, &VolumeTiers) -> Result<Uint128, ContractError>,
+{
+    let Some(volume_tiers) = VOLUME_TIERS.may_load(deps.storage)? else {
+        return Ok(());
+    };
+    let mut buckets = window_buckets(deps.storage, env, &volume_tiers, sender)?;
+
+    let volume = quote_value(deps.as_ref(), &volume_tiers)?;
+    if !volume.is_zero() {
+        let now = env.block.time.seconds();
+        let start = now - now % volume_tiers.bucket_seconds;
+        match buckets.last_mut() {
+            Some(bucket) if bucket.start == start => {
+                bucket.volume = bucket.volume.checked_add(volume)?;
+            }
+            _ => buckets.push(VolumeBucket { start, volume }),
         }
+    }
+    if buckets.is_empty() {
+        USER_VOLUMES.remove(deps.storage, sender);
     } else {
-        Err(ContractError::InvalidRoute {})
-    }?;
-    let return_asset_info = if let Some(route_info) = routes.first() {
-        if let Some(swap_operation) = route_info.route.last() {
-            Ok(swap_operation.return_asset.clone())
-        } else {
-            Err(ContractError::InvalidRoute {})
+        USER_VOLUMES.save(deps.storage, sender, &buckets)?;
+    }
+    Ok(())
+}
+
+/// Returns the offer asset of `route` followed by the return asset of each of its hops, each
+/// hop simulated on its own.
+fn route_hop_assets(
+    deps: Deps,
+    route: &[SwapOperation],
+    offer_asset: Asset,
+    route_index: usize,
+) -> Result<Vec<Asset>, ContractError> {
+    let mut assets = vec![offer_asset];
+    for (hop_index, swap_operation) in route.iter().enumerate() {
+        let offer_asset = assets[hop_index].clone();
+        assets.push(simulate_operation(
+            deps,
+            swap_operation,
+            offer_asset,
+            route_index,
+            hop_index,
+        )?);
+    }
+    Ok(assets)
+}
+
+/// Returns the value in the quote asset of a route holding `assets` before and after each of its
+/// hops: the amount of the first one that is the quote asset, or else the return of the price
+/// route of the first one that has one. Routes involving neither are worth nothing, so the owner
+/// sets a price route for the assets traded without the quote asset. A price route that fails to
+/// simulate is worth nothing too, rather than failing the swap.
+fn route_assets_quote_value(deps: Deps, volume_tiers: &VolumeTiers, assets: &[Asset]) -> Uint128 {
+    let quote_asset_id = volume_tiers.quote_asset.id();
+    if let Some(asset) = assets.iter().find(|asset| asset.id() == quote_asset_id) {
+        return asset.amount;
+    }
+    let price_routes = volume_tiers.price_routes.as_deref().unwrap_or_default();
+    for asset in assets {
+        let price_route = price_routes
+            .iter()
+            .find(|price_route| price_route[0].offer_asset.id() == asset.id());
+        if let Some(price_route) = price_route {
+            return route_hop_assets(deps, price_route, asset.clone(), 0)
+                .ok()
+                .and_then(|assets| assets.last().map(|asset| asset.amount))
+                .unwrap_or_default();
         }
-    } else {
-        Err(ContractError::InvalidRoute {})
-    }?;
-    Ok((offer_asset_info, return_asset_info))
+    }
+    Uint128::zero()
+}
+
+fn routes_quote_value(
+    deps: Deps,
+    volume_tiers: &VolumeTiers,
+    routes: &[RouteInfoV2],
+) -> Result<Uint128, ContractError> {
+    let mut volume = Uint128::zero();
+    for (route_index, route_info) in routes.iter().enumerate() {
+        let offer_asset = Asset {
+            info: route_info.route[0].offer_asset.clone(),
+            amount: route_info.offer_amount,
+        };
+        // A route that fails to simulate is not counted, rather than failing the swap
+        let value = route_hop_assets(deps, &route_info.route, offer_asset, route_index)
+            .map(|assets| route_assets_quote_value(deps, volume_tiers, &assets))
+            .unwrap_or_default();
+        volume = volume.checked_add(value)?;
+    }
+    Ok(volume)
+}
+
+/// Returns the amount of the quote asset that a route graph offers or returns, fees included,
+/// or else the value of its offer asset through its price route.
+fn route_v3_quote_value(
+    deps: Deps,
+    volume_tiers: &VolumeTiers,
+    route: &RouteInfoV3,
+) -> Result<Uint128, ContractError> {
+    let quote_asset_info = &volume_tiers.quote_asset;
+    if route.return_asset()?.id() != quote_asset_info.id() {
+        let offer_asset = Asset {
+            info: route.offer_asset()?,
+            amount: route.offer_amount,
+        };
+        return Ok(route_assets_quote_value(deps, volume_tiers, &[offer_asset]));
+    }
+    // A route that fails to simulate is not counted, rather than failing the swap
+    let Ok(result) = simulation_v3(deps, route.clone(), &FeeAdjustment::default()) else {
+        return Ok(Uint128::zero());
+    };
+    let mut volume = result.return_asset.amount;
+    for fee_asset in &result.fee_assets {
+        if fee_asset.info.id() == quote_asset_info.id() {
+            volume = volume.checked_add(fee_asset.amount)?;
+        }
+    }
+    Ok(volume)
+}
+
+/// Returns this contract's balance of `asset_info`, minus the fees it holds for integrators.
+fn available_balance(
+    deps: Deps,
+    env: &Env,
+    asset_info: &AssetInfo,
+) -> Result<Uint128, ContractError> {
+    let balance = query_balance(&deps.querier, &env.contract.address, asset_info)?;
+    let reserved = RESERVED_FEES
+        .may_load(deps.storage, &asset_info.id())?
+        .unwrap_or_default();
+    Ok(balance.checked_sub(reserved)?)
+}
+
+fn load_integrator(
+    storage: &dyn Storage,
+    id: Option<&String>,
+) -> Result<Option<(String, Integrator)>, ContractError> {
+    match id {
+        Some(id) => match INTEGRATORS.may_load(storage, id)? {
+            Some(integrator) => Ok(Some((id.clone(), integrator))),
+            None => Err(ContractError::IntegratorNotFound { id: id.clone() }),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Keeps the integrator's share of `fee` in this contract until it is claimed, returning the
+/// share.
+fn accrue_integrator_fee(
+    storage: &mut dyn Storage,
+    integrator: Option<&(String, Integrator)>,
+    fee: &Asset,
+) -> Result<Uint128, ContractError> {
+    let Some((id, integrator)) = integrator else {
+        return Ok(Uint128::zero());
+    };
+    let amount = fee.amount.mul_floor(integrator.fee_share);
+    if amount.is_zero() {
+        return Ok(amount);
+    }
+
+    let share = Asset {
+        info: fee.info.clone(),
+        amount,
+    };
+    accrue_asset(storage, &INTEGRATOR_FEES, id, &share)?;
+    reserve_fee(storage, &share.id(), amount)?;
+    Ok(amount)
+}
+
+/// Keeps `amount` of a protocol fee in this contract until the fee collector claims it.
+fn accrue_protocol_fee(
+    storage: &mut dyn Storage,
+    asset_info: &AssetInfo,
+    amount: Uint128,
+) -> StdResult<()> {
+    if amount.is_zero() {
+        return Ok(());
+    }
+    let asset_id = asset_info.id();
+    ACCRUED_FEES.update(storage, &asset_id, |accrued| -> StdResult<_> {
+        Ok(accrued.unwrap_or_default().checked_add(amount)?)
+    })?;
+    reserve_fee(storage, &asset_id, amount)
+}
+
+/// Splits `amount` across `fee_recipients` by weight, the rounding dust going to the first one.
+fn split_fee(fee_recipients: &[FeeRecipient], amount: Uint128) -> StdResult<Vec<(Addr, Uint128)>> {
+    let total_weight: u64 = fee_recipients
+        .iter()
+        .map(|fee_recipient| u64::from(fee_recipient.weight))
+        .sum();
+    let mut shares: Vec<(Addr, Uint128)> = fee_recipients
+        .iter()
+        .map(|fee_recipient| {
+            (
+                fee_recipient.address.clone(),
+                amount.multiply_ratio(fee_recipient.weight, total_weight),
+            )
+        })
+        .collect();
+    let mut dust = amount;
+    for (_, share) in &shares {
+        dust = dust.checked_sub(*share)?;
+    }
+    if let Some((_, share)) = shares.first_mut() {
+        *share = share.checked_add(dust)?;
+    }
+    Ok(shares)
+}
+
+/// Makes `amount` of `asset_id` held by this contract unavailable to swaps.
+fn reserve_fee(storage: &mut dyn Storage, asset_id: &str, amount: Uint128) -> StdResult<()> {
+    RESERVED_FEES.update(storage, asset_id, |reserved| -> StdResult<_> {
+        Ok(reserved.unwrap_or_default().checked_add(amount)?)
+    })?;
+    Ok(())
+}
+
+/// Releases `amount` of `asset_id` reserved by `reserve_fee`, once it leaves this contract.
+fn release_fee(storage: &mut dyn Storage, asset_id: &str, amount: Uint128) -> StdResult<()> {
+    RESERVED_FEES.update(storage, asset_id, |reserved| -> StdResult<_> {
+        Ok(reserved.unwrap_or_default().checked_sub(amount)?)
+    })?;
+    Ok(())
+}
+
+/// Adds `asset` to the amount of the same asset stored under `id` in `map`.
+fn accrue_asset(
+    storage: &mut dyn Storage,
+    map: &Map<(&str, &str), Asset>,
+    id: &str,
+    asset: &Asset,
+) -> StdResult<()> {
+    map.update(storage, (id, &asset.id()), |accrued| -> StdResult<_> {
+        let mut accrued = accrued.unwrap_or(Asset {
+            info: asset.info.clone(),
+            amount: Uint128::zero(),
+        });
+        accrued.amount = accrued.amount.checked_add(asset.amount)?;
+        Ok(accrued)
+    })?;
+    Ok(())
 }
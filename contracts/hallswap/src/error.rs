@@ -1,22 +1,137 @@
-use cosmwasm_std::{DivideByZeroError, OverflowError, StdError, Uint128};
+use cosmwasm_std::{Addr, Decimal, DivideByZeroError, OverflowError, StdError, Timestamp, Uint128};
+use math::error::MathError;
+use querier::error::QuerierError;
 use thiserror::Error;
 
+use crate::msg::Deadline;
+
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Querier(#[from] QuerierError),
+
+    #[error("{0}")]
+    Math(#[from] MathError),
+
     #[error("Unauthorized")]
     Unauthorized {},
 
     #[error("Invalid route")]
     InvalidRoute {},
 
+    // For route graphs, `route_index` is the node an edge leaves from and `hop_index` the
+    // position of the edge among the ones leaving that node
+    #[error(
+        "Pair not found; route: {route_index}, hop: {hop_index}, pool: {pool}, reason: {reason}"
+    )]
+    PairNotFound {
+        route_index: u32,
+        hop_index: u32,
+        pool: Addr,
+        reason: String,
+    },
+
+    #[error(
+        "Asset not in pair; route: {route_index}, hop: {hop_index}, pool: {pool}, asset: {asset}"
+    )]
+    AssetNotInPair {
+        route_index: u32,
+        hop_index: u32,
+        pool: Addr,
+        asset: String,
+    },
+
+    #[error("Simulation failed; route: {route_index}, hop: {hop_index}, reason: {source}")]
+    SimulationFailed {
+        route_index: u32,
+        hop_index: u32,
+        source: QuerierError,
+    },
+
+    #[error("Zero simulation output; route: {route_index}, hop: {hop_index}, pool: {pool}")]
+    ZeroSimulationOutput {
+        route_index: u32,
+        hop_index: u32,
+        pool: Addr,
+    },
+
+    #[error(
+        "Invalid interface; route: {route_index}, hop: {hop_index}, pool: {pool}, reason: {reason}"
+    )]
+    InterfaceDecode {
+        route_index: u32,
+        hop_index: u32,
+        pool: Addr,
+        reason: String,
+    },
+
+    #[error("Fee overflow; amount: {amount}, fee bps: {fee_bps}")]
+    FeeOverflow { amount: Uint128, fee_bps: u16 },
+
+    #[error("Invalid route graph: {reason}")]
+    InvalidRouteGraph { reason: String },
+
+    #[error("Invalid funds; asset: {asset}, expected: {expected}, received: {received}")]
+    InvalidFunds {
+        asset: String,
+        expected: Uint128,
+        received: Uint128,
+    },
+
+    #[error("Invalid slippage tolerance: {max_slippage_bps} bps")]
+    InvalidSlippageTolerance { max_slippage_bps: u16 },
+
+    #[error("Referral fee of {fee_bps} bps is above the maximum of {max_referral_bps} bps")]
+    InvalidReferralFee { fee_bps: u16, max_referral_bps: u16 },
+
+    #[error("Integrator {id} not found")]
+    IntegratorNotFound { id: String },
+
+    #[error("Invalid integrator fee share: {fee_share}")]
+    InvalidIntegratorFeeShare { fee_share: Decimal },
+
+    #[error("Invalid fee: {fee_bps} bps")]
+    InvalidFeeBps { fee_bps: u16 },
+
+    #[error("Invalid volume tiers: {reason}")]
+    InvalidVolumeTiers { reason: String },
+
+    #[error("Invalid fee discounts: {reason}")]
+    InvalidFeeDiscounts { reason: String },
+
+    #[error("Fee conversion is disabled")]
+    FeeConversionDisabled {},
+
+    #[error("No fee conversion route for {asset}")]
+    FeeConversionRouteNotFound { asset: String },
+
+    #[error("Minimum receive is required from senders other than the owner and fee recipients")]
+    MinimumReceiveRequired {},
+
+    #[error("Invalid fee recipients: {reason}")]
+    InvalidFeeRecipients { reason: String },
+
+    #[error("Swap deadline of {deadline} passed at block height {height} and time {time}")]
+    Expired {
+        deadline: Deadline,
+        height: u64,
+        time: Timestamp,
+    },
+
     #[error("Invalid CW20 hook message")]
     InvalidCw20HookMessage {},
 
     #[error("Assertion failed; minimum receive amount: {receive}, swap amount: {amount}")]
     AssertionMinimumReceive { receive: Uint128, amount: Uint128 },
+
+    #[error("Assertion failed; minimum profit: {minimum_profit}, profit: {profit}")]
+    AssertionMinimumProfit {
+        minimum_profit: Uint128,
+        profit: Uint128,
+    },
 }
 
 impl From<OverflowError> for ContractError {
@@ -1,5 +1,9 @@
+use std::fmt;
+
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{from_json, Addr, Binary, Decimal, QuerierWrapper, StdResult, Uint128};
+use cosmwasm_std::{
+    from_json, Addr, Binary, Decimal, QuerierWrapper, StdResult, Timestamp, Uint128,
+};
 use cw20::Cw20ReceiveMsg;
 use models::asset::Asset;
 use models::asset_info::AssetInfo;
@@ -9,12 +13,19 @@ use querier::querier::{
     query_pair_info,
 };
 
+use crate::state::{FeeConversion, FeeDiscount, FeeRecipient, VolumeTier, VolumeTiers};
+use crate::ContractError;
+
 #[cw_serde]
 pub struct InstantiateMsg {
     pub owner: Option<Addr>,
+    /// Single fee recipient, kept for compatibility with `fee_recipients`
     pub fee_address: Option<Addr>,
+    /// Takes precedence over `fee_address`
+    pub fee_recipients: Option<Vec<FeeRecipient>>,
     pub fee_bps: Option<u16>,
     pub fee_assets: Option<Vec<String>>,
+    pub max_referral_bps: Option<u16>,
 }
 
 #[cw_serde]
@@ -35,6 +46,62 @@ pub struct RouteInfoV2 {
     pub offer_amount: Uint128,
 }
 
+/// Cut of a swap paid to the frontend or wallet that sent it, charged on the same asset as
+/// the protocol fee
+#[cw_serde]
+pub struct Referral {
+    pub address: Addr,
+    pub fee_bps: u16,
+}
+
+/// Block time and/or height after which a swap is rejected
+#[cw_serde]
+pub struct Deadline {
+    pub timestamp: Option<Timestamp>,
+    pub height: Option<u64>,
+}
+
+impl fmt::Display for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.height, self.timestamp) {
+            (Some(height), Some(timestamp)) => {
+                write!(f, "block height {height} or time {timestamp}")
+            }
+            (Some(height), None) => write!(f, "block height {height}"),
+            (None, Some(timestamp)) => write!(f, "time {timestamp}"),
+            (None, None) => write!(f, "none"),
+        }
+    }
+}
+
+#[cw_serde]
+pub struct SwapOutput {
+    pub asset_info: AssetInfo,
+    pub minimum_receive: Uint128,
+    pub to: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct PostSwapOutput {
+    pub asset_info: AssetInfo,
+    pub to: Addr,
+    /// Fee charged on the return asset when no fee was charged on the offer assets
+    pub fee_bps: u16,
+    /// Asserted on the amount sent to `to`, including what the last hops paid it directly
+    pub minimum_receive: Uint128,
+}
+
+/// Where `minimum_receive` is asserted
+#[cw_serde]
+pub enum MinimumReceiveMode {
+    /// On the amount the router sends to the receiver once every route is executed, plus what
+    /// the last hops paid it directly as reported by their pools
+    RouterOutput {},
+    /// Also on the increase of the receiver's balance. Only suits receivers that keep what they
+    /// receive and that no route swaps on.
+    ReceiverBalance {},
+}
+
 #[cw_serde]
 pub struct SwapOperation {
     pub contract_addr: Addr,
@@ -43,6 +110,31 @@ pub struct SwapOperation {
     pub interface: Option<Interface>,
 }
 
+/// A route graph that may split and merge at intermediate assets. Node `0` is the offer
+/// asset and the only node without outgoing edges is the return asset. Every node splits
+/// the amount it holds across its outgoing edges by their `fraction`.
+#[cw_serde]
+pub struct RouteInfoV3 {
+    pub nodes: Vec<AssetInfo>,
+    pub edges: Vec<RouteEdge>,
+    pub offer_amount: Uint128,
+}
+
+#[cw_serde]
+pub struct RouteEdge {
+    pub from: u32,
+    pub to: u32,
+    pub fraction: Decimal,
+    pub contract_addr: Addr,
+    pub interface: Option<Interface>,
+}
+
+#[cw_serde]
+pub struct SplitOperation {
+    pub operation: SwapOperation,
+    pub fraction: Decimal,
+}
+
 #[cw_serde]
 pub enum Interface {
     Binary(Binary),
@@ -86,11 +178,13 @@ impl ContractInfo {
                 pair_type: PairType::Hybrid {},
             } => PairInfo {
                 asset_infos: query_astrovault_pool_info(querier, &self.contract_addr)?.asset_infos,
+                pair_type: None,
             },
             SwapInterface::Astrovault {
                 pair_type: PairType::Xyk {},
             } => PairInfo {
                 asset_infos: query_astrovault_pair(querier, &self.contract_addr)?.asset_infos,
+                pair_type: None,
             },
             SwapInterface::OraiDexV2 {} => {
                 query_orai_dex_v2_pair(querier, &self.contract_addr)?.info
@@ -127,11 +221,13 @@ impl SwapOperation {
                 pair_type: PairType::Hybrid {},
             } => PairInfo {
                 asset_infos: query_astrovault_pool_info(querier, &self.contract_addr)?.asset_infos,
+                pair_type: None,
             },
             SwapInterface::Astrovault {
                 pair_type: PairType::Xyk {},
             } => PairInfo {
                 asset_infos: query_astrovault_pair(querier, &self.contract_addr)?.asset_infos,
+                pair_type: None,
             },
             SwapInterface::OraiDexV2 {} => {
                 query_orai_dex_v2_pair(querier, &self.contract_addr)?.info
@@ -141,6 +237,126 @@ impl SwapOperation {
     }
 }
 
+impl RouteInfoV3 {
+    pub fn offer_asset(&self) -> Result<AssetInfo, ContractError> {
+        self.nodes
+            .first()
+            .cloned()
+            .ok_or(ContractError::InvalidRoute {})
+    }
+
+    pub fn return_asset(&self) -> Result<AssetInfo, ContractError> {
+        let sink = self
+            .execution_order()?
+            .into_iter()
+            .find(|node| self.outgoing(*node).is_empty())
+            .ok_or(ContractError::InvalidRoute {})?;
+        Ok(self.nodes[sink].clone())
+    }
+
+    /// Returns the swap operations leaving `node`, in the order they were given.
+    pub fn outgoing(&self, node: usize) -> Vec<SplitOperation> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.from as usize == node)
+            .map(|edge| SplitOperation {
+                operation: SwapOperation {
+                    contract_addr: edge.contract_addr.clone(),
+                    offer_asset: self.nodes[edge.from as usize].clone(),
+                    return_asset: self.nodes[edge.to as usize].clone(),
+                    interface: edge.interface.clone(),
+                },
+                fraction: edge.fraction,
+            })
+            .collect()
+    }
+
+    /// Validates the graph and returns its nodes in topological order.
+    pub fn execution_order(&self) -> Result<Vec<usize>, ContractError> {
+        let invalid = |reason: &str| ContractError::InvalidRouteGraph {
+            reason: reason.to_string(),
+        };
+        let node_count = self.nodes.len();
+        if node_count < 2 || self.edges.is_empty() {
+            return Err(invalid("a route needs at least two nodes and one edge"));
+        }
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if self.nodes[..idx]
+                .iter()
+                .any(|other| other.id() == node.id())
+            {
+                return Err(invalid(&format!(
+                    "asset {} appears in more than one node",
+                    node.id()
+                )));
+            }
+        }
+
+        let mut in_degree = vec![0usize; node_count];
+        let mut out_fraction = vec![Decimal::zero(); node_count];
+        for edge in &self.edges {
+            let (from, to) = (edge.from as usize, edge.to as usize);
+            if from >= node_count || to >= node_count || from == to {
+                return Err(invalid(&format!(
+                    "edge {} -> {} is out of bounds",
+                    edge.from, edge.to
+                )));
+            }
+            if edge.fraction.is_zero() || edge.fraction > Decimal::one() {
+                return Err(invalid(&format!(
+                    "edge {} -> {} has an invalid fraction",
+                    from, to
+                )));
+            }
+            in_degree[to] += 1;
+            out_fraction[from] = out_fraction[from].checked_add(edge.fraction)?;
+        }
+        if in_degree[0] != 0 {
+            return Err(invalid("the offer node cannot have incoming edges"));
+        }
+        if let Some(node) = (1..node_count).find(|node| in_degree[*node] == 0) {
+            return Err(invalid(&format!(
+                "node {} is disconnected from the offer node",
+                node
+            )));
+        }
+        let sinks = out_fraction
+            .iter()
+            .filter(|fraction| fraction.is_zero())
+            .count();
+        if sinks != 1 {
+            return Err(invalid("a route must end at exactly one return node"));
+        }
+        if let Some(node) = out_fraction
+            .iter()
+            .position(|fraction| !fraction.is_zero() && *fraction != Decimal::one())
+        {
+            return Err(invalid(&format!(
+                "outgoing fractions of node {} must sum to one",
+                node
+            )));
+        }
+
+        // Kahn's algorithm; anything left unvisited sits on a cycle
+        let mut order = vec![0usize];
+        let mut idx = 0;
+        while let Some(node) = order.get(idx).copied() {
+            for edge in self.edges.iter().filter(|edge| edge.from as usize == node) {
+                let to = edge.to as usize;
+                in_degree[to] -= 1;
+                if in_degree[to] == 0 {
+                    order.push(to);
+                }
+            }
+            idx += 1;
+        }
+        if order.len() != node_count {
+            return Err(invalid("the route graph contains a cycle"));
+        }
+        Ok(order)
+    }
+}
+
 #[cw_serde]
 pub enum ExecuteMsg {
     Receive(Cw20ReceiveMsg),
@@ -149,21 +365,106 @@ pub enum ExecuteMsg {
         routes: Vec<RouteInfo>,
         minimum_receive: Uint128,
         to: Option<Addr>,
+        deadline: Option<Deadline>,
     },
     ExecuteSwapOp {
         operation: SwapOperation,
         amount: Option<Uint128>,
+        /// Pays the return asset to this address instead of the router, for the interfaces
+        /// that support it
+        to: Option<Addr>,
     },
+    /// Routes may start from several offer assets. Native coins are sent along with the
+    /// message, and the cw20 offer assets not sent through `Receive` are pulled from the sender
+    /// with `TransferFrom`, which needs an allowance to this contract.
     ExecuteRoutesV2 {
         routes: Vec<RouteInfoV2>,
         minimum_receive: Uint128,
         to: Option<Addr>,
+        /// Refunds the routes that fail instead of reverting the whole swap
+        allow_partial: Option<bool>,
+        deadline: Option<Deadline>,
+        /// Raises `minimum_receive` to the simulated return of `routes` at execution, minus
+        /// this tolerance
+        max_slippage_bps: Option<u16>,
+        /// Quote the client got before sending the swap, also minus `max_slippage_bps`, to cap
+        /// the drift since quoting
+        reference_quote: Option<Uint128>,
+        /// Defaults to `RouterOutput`
+        minimum_receive_mode: Option<MinimumReceiveMode>,
+        /// Capped by `Config.max_referral_bps`
+        referral: Option<Referral>,
+        /// Registered integrator earning its share of the protocol fee
+        integrator: Option<String>,
+    },
+    ExecuteRoutesV3 {
+        route: RouteInfoV3,
+        minimum_receive: Uint128,
+        to: Option<Addr>,
+        deadline: Option<Deadline>,
+        /// Defaults to `RouterOutput`
+        minimum_receive_mode: Option<MinimumReceiveMode>,
+    },
+    /// Swaps one offer asset into every asset of `outputs`, the return asset of each route
+    /// deciding which output it goes to
+    ExecuteRoutesMultiOutput {
+        routes: Vec<RouteInfoV2>,
+        outputs: Vec<SwapOutput>,
+        deadline: Option<Deadline>,
+        /// Defaults to `RouterOutput`
+        minimum_receive_mode: Option<MinimumReceiveMode>,
+    },
+    /// Executes routes that start and end at the same asset, asserting the net profit
+    ExecuteArbitrage {
+        routes: Vec<RouteInfoV2>,
+        minimum_profit: Uint128,
+        to: Option<Addr>,
+        deadline: Option<Deadline>,
+    },
+    ExecuteRoute {
+        route: Vec<SwapOperation>,
+        amount: Uint128,
+        /// Receiver of the last hop, see `ExecuteSwapOp`
+        to: Option<Addr>,
+    },
+    ExecuteSettlePartialRoutes {
+        refund_to: Addr,
+        referral: Option<Addr>,
+        integrator: Option<String>,
+    },
+    /// Accrues or burns what the conversion routes returned since `prev_balance`, see
+    /// `ConvertFees`
+    ExecuteSettleFeeConversion {
+        asset_info: AssetInfo,
+        prev_balance: Uint128,
+        minimum_receive: Uint128,
+        burn: bool,
+    },
+    ExecuteSplitOp {
+        operations: Vec<SplitOperation>,
+        amount: Option<Uint128>,
+    },
+    ExecuteSnapshotReturn {
+        return_asset_info: AssetInfo,
+        /// Fee of the routes executed since the previous snapshot
+        fee_bps: u16,
     },
     ExecutePostSwap {
-        offer_asset_info: AssetInfo,
+        offer_assets: Vec<Asset>,
+        outputs: Vec<PostSwapOutput>,
+        referral: Option<Referral>,
+        integrator: Option<String>,
+        /// Whether the sender of the swap is exempt from the protocol fee
+        fee_exempt: bool,
+    },
+    ExecutePostArbitrage {
+        asset_info: AssetInfo,
         offer_amount: Uint128,
-        return_asset_info: AssetInfo,
+        prev_balance: Uint128,
+        minimum_profit: Uint128,
         to: Addr,
+        /// Fee on the profit, adjusted for the sender of the arbitrage
+        fee_bps: u16,
     },
     AssertMinimumReceive {
         receiver: Addr,
@@ -172,19 +473,250 @@ pub enum ExecuteMsg {
         minimum_receive: Uint128,
     },
     UpdateConfig(InstantiateMsg),
+    /// Registers or updates an integrator, owner only
+    RegisterIntegrator {
+        id: String,
+        address: Addr,
+        fee_share: Decimal,
+    },
+    /// Sends every fee accrued by an integrator to its address
+    ClaimIntegratorFees {
+        id: String,
+    },
+    /// Overrides the fee of swaps offering or returning an asset, owner only. An unset
+    /// `fee_bps` removes the override.
+    SetAssetFee {
+        asset_info: AssetInfo,
+        fee_bps: Option<u16>,
+    },
+    /// Overrides the fee of swaps from `offer_asset_info` to `return_asset_info`, owner only.
+    /// An unset `fee_bps` removes the override.
+    SetPairFee {
+        offer_asset_info: AssetInfo,
+        return_asset_info: AssetInfo,
+        fee_bps: Option<u16>,
+    },
+    /// Sets the volume-based fee tiers, owner only. Unset `volume_tiers` disables them.
+    SetVolumeTiers {
+        volume_tiers: Option<VolumeTiers>,
+    },
+    /// Replaces the fee discounts of token and NFT holders, owner only
+    SetFeeDiscounts {
+        discounts: Vec<FeeDiscount>,
+    },
+    /// Adds and removes senders exempt from the protocol fee, owner only
+    UpdateFeeExemptions {
+        add: Vec<Addr>,
+        remove: Vec<Addr>,
+    },
+    /// Splits the protocol fees accrued in `assets` across the fee recipients by weight. Owner
+    /// or fee recipients only. A fee recipient claiming can send its own share to `recipient`.
+    ClaimFees {
+        assets: Vec<AssetInfo>,
+        recipient: Option<Addr>,
+    },
+    /// Sets the asset the accrued fees are converted into, owner only. Unset `fee_conversion`
+    /// disables the conversion.
+    SetFeeConversion {
+        fee_conversion: Option<FeeConversion>,
+    },
+    /// Sets the route converting the accrued fees of `asset_info`, owner only. An unset `route`
+    /// removes it.
+    SetFeeConversionRoute {
+        asset_info: AssetInfo,
+        route: Option<Vec<SwapOperation>>,
+    },
+    /// Converts the accrued fees of `assets`, or of every asset with a route if unset, into the
+    /// target asset through their conversion routes. With `burn` set, the return and the fees
+    /// already accrued in the target asset are burned. Anyone can call it. `minimum_receive`
+    /// raises the return asserted, the converted fees included, and is required from senders
+    /// other than the owner and fee recipients, as the slippage tolerance only applies to a
+    /// simulation in the same block.
+    ConvertFees {
+        assets: Option<Vec<AssetInfo>>,
+        minimum_receive: Option<Uint128>,
+    },
+}
+
+impl ExecuteMsg {
+    /// Returns the deadline of the swap entry points.
+    pub fn deadline(&self) -> Option<&Deadline> {
+        match self {
+            ExecuteMsg::ExecuteRoutes { deadline, .. }
+            | ExecuteMsg::ExecuteRoutesV2 { deadline, .. }
+            | ExecuteMsg::ExecuteRoutesV3 { deadline, .. }
+            | ExecuteMsg::ExecuteRoutesMultiOutput { deadline, .. }
+            | ExecuteMsg::ExecuteArbitrage { deadline, .. } => deadline.as_ref(),
+            _ => None,
+        }
+    }
 }
 
 #[cw_serde]
 #[derive(QueryResponses)]
 pub enum QueryMsg {
     #[returns(QuerySimulationResult)]
-    Simulation { routes: Vec<RouteInfoV2> },
+    /// Set `sender` to apply its volume tier and holder discount to the fee
+    Simulation {
+        routes: Vec<RouteInfoV2>,
+        referral: Option<Referral>,
+        sender: Option<Addr>,
+    },
+    /// Simulates routes that start and end at the same asset, see `ExecuteArbitrage`. Set
+    /// `sender` to apply its exemption, volume tier and holder discount to the fee.
+    #[returns(QuerySimulationResult)]
+    SimulationArbitrage {
+        routes: Vec<RouteInfoV2>,
+        sender: Option<Addr>,
+    },
+    #[returns(QuerySimulationResult)]
+    SimulationV3 {
+        route: RouteInfoV3,
+        sender: Option<Addr>,
+    },
+    /// Checks `routes` as `ExecuteRoutesV2` would, reporting every problem found
+    #[returns(ValidateRoutesResponse)]
+    ValidateRoutes { routes: Vec<RouteInfoV2> },
+    /// Fees accrued by an integrator and not claimed yet
+    #[returns(Vec<Asset>)]
+    IntegratorFees { id: String },
+    /// Offer amounts of every swap tagged with an integrator
+    #[returns(Vec<Asset>)]
+    IntegratorVolume { id: String },
+    /// Fee applied to swaps from `offer_asset_info` to `return_asset_info`
+    #[returns(EffectiveFeeResponse)]
+    EffectiveFee {
+        offer_asset_info: AssetInfo,
+        return_asset_info: AssetInfo,
+    },
+    /// Volume tier of `address`, unset when volume tiers are disabled
+    #[returns(Option<UserTierResponse>)]
+    UserTier { address: Addr },
+    /// Protocol fees accrued and not claimed yet, by ascending asset id
+    #[returns(Vec<AccruedFee>)]
+    AccruedFees {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Senders exempt from the protocol fee, in ascending order
+    #[returns(Vec<Addr>)]
+    FeeExemptions {
+        start_after: Option<Addr>,
+        limit: Option<u32>,
+    },
+    /// Cumulative protocol fees burned by `ConvertFees`, by ascending asset id
+    #[returns(Vec<BurnedFee>)]
+    BurnedFees {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+#[cw_serde]
+pub struct EffectiveFeeResponse {
+    pub fee_bps: u16,
+}
+
+#[cw_serde]
+pub struct AccruedFee {
+    pub asset: String,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct BurnedFee {
+    pub asset: String,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct UserTierResponse {
+    /// Volume of the address over the rolling window, in the quote asset
+    pub volume: Uint128,
+    /// Tier reached by the address, unset below the first tier
+    pub tier: Option<VolumeTier>,
+    /// Next tier to reach, unset at the last tier
+    pub next_tier: Option<VolumeTier>,
 }
 
 #[cw_serde]
 pub struct QuerySimulationResult {
     pub return_asset: Asset,
+    /// First entry of `fee_assets`, kept for clients that predate multi-input swaps
     pub fee_asset: Option<Asset>,
+    pub fee_assets: Vec<Asset>,
+    pub referral_fee_assets: Vec<Asset>,
+    /// Hops on pools used more than once, whose quote accounts for the earlier hops
+    pub adjusted_hops: Vec<AdjustedHop>,
+}
+
+#[cw_serde]
+pub struct AdjustedHop {
+    pub route_index: u32,
+    pub hop_index: u32,
+    pub pool: Addr,
+    /// Quote against the pool state before the simulation
+    pub naive_return_amount: Uint128,
+    /// Quote against the pool state left by the earlier hops on the same pool
+    pub sequential_return_amount: Uint128,
+}
+
+#[cw_serde]
+pub struct ValidateRoutesResponse {
+    pub valid: bool,
+    pub errors: Vec<RouteDiagnostic>,
+}
+
+#[cw_serde]
+pub struct RouteDiagnostic {
+    /// Not set when the issue is about the routes as a whole
+    pub route_index: Option<u32>,
+    /// Not set when the issue is about the route as a whole
+    pub hop_index: Option<u32>,
+    pub issue: RouteIssue,
+}
+
+#[cw_serde]
+pub enum RouteIssue {
+    NoRoutes {},
+    EmptyRoute {},
+    InterfaceDecode {
+        reason: String,
+    },
+    PairNotFound {
+        pool: Addr,
+        reason: String,
+    },
+    OfferAssetNotInPair {
+        pool: Addr,
+        asset: String,
+    },
+    ReturnAssetNotInPair {
+        pool: Addr,
+        asset: String,
+    },
+    SameOfferAndReturnAsset {
+        asset: String,
+    },
+    /// The offer asset of a hop is not the return asset of the previous hop
+    Disconnected {
+        expected: String,
+        found: String,
+    },
+    /// The route does not end at the same return asset as the first route
+    ReturnAssetMismatch {
+        expected: String,
+        found: String,
+    },
+    /// The route starts from the return asset, which only arbitrage allows
+    OfferIsReturnAsset {
+        asset: String,
+    },
+    /// A hop after the first offers an offer asset of the routes or their return asset, whose
+    /// whole balance the router would swap
+    OffersSweptAsset {
+        asset: String,
+    },
 }
 
 #[cw_serde]
@@ -1,7 +1,11 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::Addr;
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Decimal, Empty, Uint128};
+use cw_storage_plus::{Item, Map};
 use models::asset::Asset;
+use models::asset_info::AssetInfo;
+use serde::{Deserialize, Serialize};
+
+use crate::msg::SwapOperation;
 
 /// Stores the contract config at the given key
 pub const CONFIG: Item<Config> = Item::new("config");
@@ -11,13 +15,181 @@ pub const CONFIG: Item<Config> = Item::new("config");
 pub struct Config {
     /// Address allowed to change this config
     pub owner: Addr,
-    /// Address where all fees will go to
-    pub fee_address: Addr,
+    /// Addresses the claimed protocol fees are split across, by weight
+    pub fee_recipients: Vec<FeeRecipient>,
     /// Fee amount in basis points
     pub fee_bps: u16,
     /// Valid assets that could be used as fees
     pub fee_assets: Vec<String>,
+    /// Highest referral fee a swap can set, in basis points
+    pub max_referral_bps: u16,
+}
+
+/// Recipient of a share of the protocol fees
+#[cw_serde]
+pub struct FeeRecipient {
+    pub address: Addr,
+    /// Share of the fees relative to the sum of the weights of every recipient
+    pub weight: u32,
+}
+
+/// Fee collector of the configs stored before `Config::fee_recipients`, read when migrating
+#[derive(Serialize, Deserialize)]
+pub struct LegacyConfig {
+    pub fee_address: Addr,
 }
 
+/// Reads the config stored before `Config::fee_recipients`, ignoring the other fields
+pub const LEGACY_CONFIG: Item<LegacyConfig> = Item::new("config");
+
 /// Tracks if user has paid fees during the swap
-pub const FEES_COLLECTED: Item<Asset> = Item::new("fees_collected");
+pub const FEES_COLLECTED: Item<Vec<Asset>> = Item::new("fees_collected");
+
+/// Tracks the referral fees paid during the swap, charged like `FEES_COLLECTED`
+pub const REFERRAL_FEES_COLLECTED: Item<Vec<Asset>> = Item::new("referral_fees_collected");
+
+/// Return amount of the routes charged at `post_swap` (Case 2) with the same fee
+#[cw_serde]
+pub struct FeeableReturn {
+    pub fee_bps: u16,
+    pub amount: Uint128,
+}
+
+/// Return amounts of the routes charged at `post_swap`, one per fee, recorded after each group
+/// of routes sharing a fee so that every route is charged its own fee
+pub const FEEABLE_RETURNS: Item<Vec<FeeableReturn>> = Item::new("feeable_returns");
+
+/// Return amount that the last hops of the swap paid its receiver directly, as reported by their
+/// pools, counted with the amount `post_swap` sends it.
+///
+/// The router does not see these payments, so the minimum receive of `RouterOutput` trusts the
+/// `return_amount` attribute of pools the sender routed through. A pool overstating it would
+/// pass the assertion with less paid; senders routing through pools they do not trust use
+/// `ReceiverBalance`, which also measures the receiver's balance.
+pub const DIRECT_RETURN: Item<Uint128> = Item::new("direct_return");
+
+/// Route of a partial swap, keyed by its reply id
+#[cw_serde]
+pub struct PartialRoute {
+    /// Offer amount of the route, including its fee
+    pub offer_asset: Asset,
+    /// Fee charged on the offer asset (Case 1), paid only if the route succeeds
+    pub fee_amount: Uint128,
+    /// Referral fee charged alongside `fee_amount`
+    pub referral_fee_amount: Uint128,
+    pub failed: bool,
+}
+
+/// Tracks the routes of a partial swap until they are settled
+pub const PARTIAL_ROUTES: Map<u64, PartialRoute> = Map::new("partial_routes");
+
+/// Frontend or wallet registered by the owner to earn a share of the protocol fee
+#[cw_serde]
+pub struct Integrator {
+    /// Address allowed to claim the accrued fees, which are also sent there
+    pub address: Addr,
+    /// Share of the protocol fee of the swaps tagged with this integrator
+    pub fee_share: Decimal,
+}
+
+/// Stores the integrators by id
+pub const INTEGRATORS: Map<&str, Integrator> = Map::new("integrators");
+
+/// Fees accrued by every integrator and not claimed yet, keyed by integrator id and asset id
+pub const INTEGRATOR_FEES: Map<(&str, &str), Asset> = Map::new("integrator_fees");
+
+/// Offer amounts of the swaps tagged with every integrator, keyed by integrator id and asset id
+pub const INTEGRATOR_VOLUMES: Map<(&str, &str), Asset> = Map::new("integrator_volumes");
+
+/// Protocol fees accrued and not claimed yet, keyed by asset id
+pub const ACCRUED_FEES: Map<&str, Uint128> = Map::new("accrued_fees");
+
+/// Total of `ACCRUED_FEES` and `INTEGRATOR_FEES` per asset id, held by this contract but not
+/// available to swaps
+pub const RESERVED_FEES: Map<&str, Uint128> = Map::new("reserved_fees");
+
+/// Fee overrides in bps keyed by asset id, applying to swaps offering or returning the asset
+pub const ASSET_FEES: Map<&str, u16> = Map::new("asset_fees");
+
+/// Fee overrides in bps keyed by offer and return asset ids, taking precedence over `ASSET_FEES`
+pub const PAIR_FEES: Map<(&str, &str), u16> = Map::new("pair_fees");
+
+/// Fee applied to a sender once its volume over the window reaches `min_volume`
+#[cw_serde]
+pub struct VolumeTier {
+    /// Volume in the quote asset from which the tier applies
+    pub min_volume: Uint128,
+    /// Highest fee in basis points charged to senders in this tier
+    pub fee_bps: u16,
+}
+
+/// Volume-based fee tiers, tracking the volume of every sender over a rolling window
+#[cw_serde]
+pub struct VolumeTiers {
+    /// Asset the volume is valued in, through the simulation of every route
+    pub quote_asset: AssetInfo,
+    /// Length of the rolling window in seconds
+    pub window_seconds: u64,
+    /// Length of the buckets the window is split into, in seconds
+    pub bucket_seconds: u64,
+    /// Tiers sorted by increasing `min_volume`
+    pub tiers: Vec<VolumeTier>,
+    /// Routes swapping other assets into `quote_asset`, at most one per offer asset, valuing the
+    /// swaps whose routes never involve it
+    pub price_routes: Option<Vec<Vec<SwapOperation>>>,
+}
+
+/// Stores the volume tiers, unset when they are disabled
+pub const VOLUME_TIERS: Item<VolumeTiers> = Item::new("volume_tiers");
+
+/// Volume of a sender during the bucket starting at `start`, in seconds
+#[cw_serde]
+pub struct VolumeBucket {
+    pub start: u64,
+    pub volume: Uint128,
+}
+
+/// Buckets of every sender within the rolling window, oldest first
+pub const USER_VOLUMES: Map<&Addr, Vec<VolumeBucket>> = Map::new("user_volumes");
+
+/// Holding that makes a sender eligible to a fee discount
+#[cw_serde]
+pub enum HoldingCondition {
+    /// Balance of at least `min_balance` of the cw20 `token`
+    Cw20Balance { token: Addr, min_balance: Uint128 },
+    /// At least one token of the cw721 `collection`
+    Cw721Owner { collection: Addr },
+}
+
+/// Share of the fee waived for senders meeting `condition`
+#[cw_serde]
+pub struct FeeDiscount {
+    pub condition: HoldingCondition,
+    pub discount: Decimal,
+}
+
+/// Stores the fee discounts, the highest one a sender is eligible to applies
+pub const FEE_DISCOUNTS: Item<Vec<FeeDiscount>> = Item::new("fee_discounts");
+
+/// Senders that pay no protocol fee, such as the treasury and partner or keeper contracts
+pub const FEE_EXEMPTIONS: Map<&Addr, Empty> = Map::new("fee_exemptions");
+
+/// Conversion of the accrued protocol fees into a single asset
+#[cw_serde]
+pub struct FeeConversion {
+    /// Asset the accrued fees are converted into
+    pub target_asset: AssetInfo,
+    /// Highest slippage from the simulated return accepted when converting, in basis points
+    pub max_slippage_bps: u16,
+    /// Burns the target asset instead of accruing it, as a buyback of the governance token
+    pub burn: bool,
+}
+
+/// Stores the fee conversion, unset when it is disabled
+pub const FEE_CONVERSION: Item<FeeConversion> = Item::new("fee_conversion");
+
+/// Route converting the accrued fees of an asset into the target asset, keyed by asset id
+pub const FEE_CONVERSION_ROUTES: Map<&str, Vec<SwapOperation>> = Map::new("fee_conversion_routes");
+
+/// Cumulative amount of protocol fees burned, keyed by asset id
+pub const BURNED_FEES: Map<&str, Uint128> = Map::new("burned_fees");
@@ -0,0 +1,80 @@
+use cosmwasm_std::{coins, Addr, Uint128};
+
+use super::{native, route, Suite};
+use crate::msg::{ExecuteMsg, QueryMsg, QuerySimulationResult, RouteInfoV2};
+
+/// Arbitrage of 1_000_000 uusd between two pools pricing uluna differently
+fn arbitrage_routes(suite: &mut Suite) -> Vec<RouteInfoV2> {
+    let (uusd, uluna) = (native("uusd"), native("uluna"));
+    let cheap_pool = suite.create_pair([(&uusd, 1_000_000_000), (&uluna, 2_000_000_000)]);
+    let expensive_pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    vec![route(
+        &[
+            (&cheap_pool, &uusd, &uluna),
+            (&expensive_pool, &uluna, &uusd),
+        ],
+        1_000_000,
+    )]
+}
+
+fn simulate_arbitrage(suite: &Suite, routes: &[RouteInfoV2], sender: &Addr) -> u128 {
+    let result: QuerySimulationResult = suite.query(&QueryMsg::SimulationArbitrage {
+        routes: routes.to_vec(),
+        sender: Some(sender.clone()),
+    });
+    result.return_asset.amount.u128()
+}
+
+fn arbitrage(suite: &mut Suite, routes: Vec<RouteInfoV2>) -> u128 {
+    let (user, uusd) = (suite.user.clone(), native("uusd"));
+    suite.fund(&user, &uusd, 1_000_000);
+    suite
+        .execute(
+            &user,
+            &ExecuteMsg::ExecuteArbitrage {
+                routes,
+                minimum_profit: Uint128::zero(),
+                to: None,
+                deadline: None,
+            },
+            &coins(1_000_000, "uusd"),
+        )
+        .unwrap();
+    suite.balance(&user, &uusd)
+}
+
+#[test]
+fn arbitrage_charges_fee_on_profit() {
+    let mut suite = Suite::with_fee(100, &[]);
+    let routes = arbitrage_routes(&mut suite);
+    let user = suite.user.clone();
+    let return_amount = suite.pool_return(&routes);
+    let profit = return_amount - 1_000_000;
+
+    let simulated = simulate_arbitrage(&suite, &routes, &user);
+    assert_eq!(simulated, return_amount - profit / 100);
+    assert_eq!(arbitrage(&mut suite, routes), simulated);
+    assert_eq!(suite.accrued_fee(&native("uusd")), profit / 100);
+}
+
+#[test]
+fn arbitrage_waives_fee_of_exempt_sender() {
+    let mut suite = Suite::with_fee(100, &[]);
+    let routes = arbitrage_routes(&mut suite);
+    let (owner, user) = (suite.owner.clone(), suite.user.clone());
+    suite
+        .execute(
+            &owner,
+            &ExecuteMsg::UpdateFeeExemptions {
+                add: vec![user.clone()],
+                remove: vec![],
+            },
+            &[],
+        )
+        .unwrap();
+    let return_amount = suite.pool_return(&routes);
+
+    assert_eq!(simulate_arbitrage(&suite, &routes, &user), return_amount);
+    assert_eq!(arbitrage(&mut suite, routes), return_amount);
+    assert_eq!(suite.accrued_fee(&native("uusd")), 0);
+}
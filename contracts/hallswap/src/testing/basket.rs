@@ -0,0 +1,118 @@
+use cosmwasm_std::coins;
+
+use super::{error_message, native, route, swap_msg, Suite};
+use crate::ContractError;
+
+#[test]
+fn basket_mixes_native_coins_and_a_pulled_cw20() {
+    let mut suite = Suite::with_fee(30, &[]);
+    let (uluna, uusd) = (native("uluna"), native("uusd"));
+    let token = suite.create_token();
+    let luna_pair = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 2_000_000_000)]);
+    let token_pair = suite.create_pair([(&token, 3_000_000_000), (&uusd, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uluna, 1_000_000);
+    suite.fund(&user, &token, 3_000_000);
+    suite.increase_allowance(&user, &token, 3_000_000);
+
+    let routes = vec![
+        route(&[(&luna_pair, &uluna, &uusd)], 1_000_000),
+        route(&[(&token_pair, &token, &uusd)], 3_000_000),
+    ];
+    let expected = suite.simulate(&routes);
+    suite
+        .execute(
+            &user,
+            &swap_msg(routes, expected),
+            &coins(1_000_000, "uluna"),
+        )
+        .unwrap();
+
+    assert_eq!(suite.balance(&user, &uusd), expected);
+    assert_eq!(suite.balance(&user, &uluna), 0);
+    assert_eq!(suite.balance(&user, &token), 0);
+    // Only the accrued fee is left on the router
+    assert_eq!(suite.balance(&suite.router, &uluna), 0);
+    assert_eq!(suite.balance(&suite.router, &token), 0);
+    assert_eq!(
+        suite.balance(&suite.router, &uusd),
+        suite.accrued_fee(&uusd)
+    );
+}
+
+#[test]
+fn basket_pulls_a_second_cw20_next_to_the_one_sent() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let uusd = native("uusd");
+    let (token_a, token_b) = (suite.create_token(), suite.create_token());
+    let pair_a = suite.create_pair([(&token_a, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let pair_b = suite.create_pair([(&token_b, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &token_a, 1_000_000);
+    suite.fund(&user, &token_b, 2_000_000);
+    suite.increase_allowance(&user, &token_b, 2_000_000);
+
+    let routes = vec![
+        route(&[(&pair_a, &token_a, &uusd)], 1_000_000),
+        route(&[(&pair_b, &token_b, &uusd)], 2_000_000),
+    ];
+    let expected = suite.simulate(&routes);
+    suite
+        .send(&user, &token_a, 1_000_000, &swap_msg(routes, expected))
+        .unwrap();
+
+    assert_eq!(suite.balance(&user, &uusd), expected);
+    assert_eq!(suite.balance(&user, &token_a), 0);
+    assert_eq!(suite.balance(&user, &token_b), 0);
+}
+
+#[test]
+fn basket_without_allowance_fails() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let (uluna, uusd) = (native("uluna"), native("uusd"));
+    let token = suite.create_token();
+    let luna_pair = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let token_pair = suite.create_pair([(&token, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uluna, 1_000_000);
+    suite.fund(&user, &token, 1_000_000);
+
+    let routes = vec![
+        route(&[(&luna_pair, &uluna, &uusd)], 1_000_000),
+        route(&[(&token_pair, &token, &uusd)], 1_000_000),
+    ];
+    suite
+        .execute(&user, &swap_msg(routes, 0), &coins(1_000_000, "uluna"))
+        .unwrap_err();
+    assert_eq!(suite.balance(&user, &uluna), 1_000_000);
+    assert_eq!(suite.balance(&user, &token), 1_000_000);
+}
+
+#[test]
+fn basket_rejects_later_hops_offering_an_offer_asset() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let (uluna, uusd) = (native("uluna"), native("uusd"));
+    let token = suite.create_token();
+    let luna_pair = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let token_pair = suite.create_pair([(&token, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uluna, 1_000_000);
+    suite.fund(&user, &token, 1_000_000);
+    suite.increase_allowance(&user, &token, 1_000_000);
+
+    // The second hop of the token route would swap the uluna offered by the first route
+    let routes = vec![
+        route(&[(&luna_pair, &uluna, &uusd)], 1_000_000),
+        route(
+            &[(&token_pair, &token, &uluna), (&luna_pair, &uluna, &uusd)],
+            1_000_000,
+        ),
+    ];
+    let err = suite
+        .execute(&user, &swap_msg(routes, 0), &coins(1_000_000, "uluna"))
+        .unwrap_err();
+    assert_eq!(
+        error_message(err),
+        ContractError::InvalidRoute {}.to_string()
+    );
+}
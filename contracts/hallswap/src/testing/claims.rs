@@ -0,0 +1,124 @@
+use cosmwasm_std::{coins, Addr};
+
+use super::{native, route, swap_msg, Suite};
+use crate::msg::{ExecuteMsg, InstantiateMsg};
+use crate::state::FeeRecipient;
+
+/// Router splitting its fees between `alice` and `bob` with weights 2 and 1, after a swap of the
+/// user accruing `fee_amount` of uluna
+fn claims_suite(fee_amount: u128) -> Suite {
+    let recipients = [("alice", 2), ("bob", 1)].map(|(address, weight)| FeeRecipient {
+        address: Addr::unchecked(address),
+        weight,
+    });
+    let mut suite = Suite::new(InstantiateMsg {
+        owner: None,
+        fee_address: None,
+        fee_recipients: Some(recipients.to_vec()),
+        fee_bps: Some(30),
+        fee_assets: Some(vec!["uluna".to_string()]),
+        max_referral_bps: Some(100),
+    });
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    // The smallest offer amount whose 30 bps fee reaches `fee_amount`
+    let offer_amount = (fee_amount * 10_000).div_ceil(30);
+    let user = suite.user.clone();
+    suite.fund(&user, &uluna, offer_amount);
+    suite
+        .execute(
+            &user,
+            &swap_msg(vec![route(&[(&pool, &uluna, &uusd)], offer_amount)], 0),
+            &coins(offer_amount, "uluna"),
+        )
+        .unwrap();
+    assert_eq!(suite.accrued_fee(&uluna), fee_amount);
+    suite
+}
+
+#[test]
+fn claim_fees_splits_by_weight_with_the_dust_to_the_first_recipient() {
+    let mut suite = claims_suite(30_001);
+    let uluna = native("uluna");
+    let owner = suite.owner.clone();
+    suite
+        .execute(
+            &owner,
+            &ExecuteMsg::ClaimFees {
+                assets: vec![uluna.clone()],
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.balance(&Addr::unchecked("alice"), &uluna), 20_001);
+    assert_eq!(suite.balance(&Addr::unchecked("bob"), &uluna), 10_000);
+    assert_eq!(suite.accrued_fee(&uluna), 0);
+}
+
+#[test]
+fn claim_fees_sends_the_share_of_the_sender_to_its_recipient() {
+    let mut suite = claims_suite(30_000);
+    let uluna = native("uluna");
+    suite
+        .execute(
+            &Addr::unchecked("bob"),
+            &ExecuteMsg::ClaimFees {
+                assets: vec![uluna.clone()],
+                recipient: Some(Addr::unchecked("carol")),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.balance(&Addr::unchecked("alice"), &uluna), 20_000);
+    assert_eq!(suite.balance(&Addr::unchecked("bob"), &uluna), 0);
+    assert_eq!(suite.balance(&Addr::unchecked("carol"), &uluna), 10_000);
+}
+
+#[test]
+fn accrued_fees_are_reserved_from_later_swaps_until_claimed() {
+    let mut suite = Suite::with_fee(30, &["uluna"]);
+    let [uatom, uluna, uusd] = ["uatom", "uluna", "uusd"].map(native);
+    let luna_pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let atom_pool = suite.create_pair([(&uluna, 1_000_000_000), (&uatom, 1_000_000_000)]);
+    let (owner, user) = (suite.owner.clone(), suite.user.clone());
+    suite.fund(&user, &uluna, 1_000_000);
+    suite
+        .execute(
+            &user,
+            &swap_msg(vec![route(&[(&luna_pool, &uluna, &uusd)], 1_000_000)], 0),
+            &coins(1_000_000, "uluna"),
+        )
+        .unwrap();
+    assert_eq!(suite.accrued_fee(&uluna), 3_000);
+    assert_eq!(suite.balance(&suite.router, &uluna), 3_000);
+
+    // The second hop swaps the router's uluna balance, which must leave the accrued fees out
+    suite.fund(&user, &uusd, 500_000);
+    let routes = vec![route(
+        &[(&luna_pool, &uusd, &uluna), (&atom_pool, &uluna, &uatom)],
+        500_000,
+    )];
+    let return_amount = suite.pool_return(&routes);
+    let expected = return_amount - return_amount * 30 / 10_000;
+    suite
+        .execute(&user, &swap_msg(routes, expected), &coins(500_000, "uusd"))
+        .unwrap();
+    assert_eq!(suite.balance(&user, &uatom), expected);
+    assert_eq!(suite.balance(&suite.router, &uluna), 3_000);
+
+    let owner_balance = suite.balance(&owner, &uluna);
+    suite
+        .execute(
+            &owner,
+            &ExecuteMsg::ClaimFees {
+                assets: vec![uluna.clone()],
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.balance(&owner, &uluna), owner_balance + 3_000);
+    assert_eq!(suite.accrued_fee(&uluna), 0);
+    assert_eq!(suite.balance(&suite.router, &uluna), 0);
+}
@@ -0,0 +1,187 @@
+use cosmwasm_std::{coins, Addr, Uint128};
+use models::asset_info::AssetInfo;
+
+use super::{error_message, mock_cw20, native, route, swap_msg, Suite};
+use crate::msg::{BurnedFee, ExecuteMsg, QueryMsg};
+use crate::state::FeeConversion;
+
+/// Router with 30_000 uluna of fees accrued by a swap of the user, converting them into
+/// `target_asset` through a pool holding it alongside uluna. Returns the target asset and that
+/// pool, unset when the target asset is uluna itself
+fn conversion_suite(
+    target_asset: impl FnOnce(&mut Suite) -> AssetInfo,
+    burn: bool,
+) -> (Suite, AssetInfo, Option<Addr>) {
+    let mut suite = Suite::with_fee(30, &["uluna"]);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let target_asset = target_asset(&mut suite);
+    let pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let owner = suite.owner.clone();
+    suite
+        .execute(
+            &owner,
+            &ExecuteMsg::SetFeeConversion {
+                fee_conversion: Some(FeeConversion {
+                    target_asset: target_asset.clone(),
+                    max_slippage_bps: 100,
+                    burn,
+                }),
+            },
+            &[],
+        )
+        .unwrap();
+    let target_pool = (target_asset.id() != uluna.id()).then(|| {
+        let target_pool =
+            suite.create_pair([(&uluna, 1_000_000_000), (&target_asset, 1_000_000_000)]);
+        suite
+            .execute(
+                &owner,
+                &ExecuteMsg::SetFeeConversionRoute {
+                    asset_info: uluna.clone(),
+                    route: Some(route(&[(&target_pool, &uluna, &target_asset)], 0).route),
+                },
+                &[],
+            )
+            .unwrap();
+        target_pool
+    });
+
+    let user = suite.user.clone();
+    suite.fund(&user, &uluna, 10_000_000);
+    suite
+        .execute(
+            &user,
+            &swap_msg(vec![route(&[(&pool, &uluna, &uusd)], 10_000_000)], 0),
+            &coins(10_000_000, "uluna"),
+        )
+        .unwrap();
+    assert_eq!(suite.accrued_fee(&uluna), 30_000);
+    (suite, target_asset, target_pool)
+}
+
+fn convert_fees(minimum_receive: Option<u128>) -> ExecuteMsg {
+    ExecuteMsg::ConvertFees {
+        assets: None,
+        minimum_receive: minimum_receive.map(Uint128::new),
+    }
+}
+
+#[test]
+fn convert_fees_requires_a_minimum_receive_from_other_senders() {
+    let (mut suite, _, pool) = conversion_suite(|_| native("uusd"), false);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let expected = suite.pool_return(&[route(&[(&pool.unwrap(), &uluna, &uusd)], 30_000)]);
+    let user = suite.user.clone();
+    let err = suite.execute(&user, &convert_fees(None), &[]).unwrap_err();
+    assert_eq!(
+        error_message(err),
+        "Minimum receive is required from senders other than the owner and fee recipients"
+    );
+
+    suite
+        .execute(&user, &convert_fees(Some(expected)), &[])
+        .unwrap();
+    assert_eq!(suite.accrued_fee(&uluna), 0);
+    assert_eq!(suite.accrued_fee(&uusd), expected);
+}
+
+#[test]
+fn convert_fees_asserts_the_caller_minimum_receive() {
+    let (mut suite, _, pool) = conversion_suite(|_| native("uusd"), false);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let expected = suite.pool_return(&[route(&[(&pool.unwrap(), &uluna, &uusd)], 30_000)]);
+    let owner = suite.owner.clone();
+
+    let err = suite
+        .execute(&owner, &convert_fees(Some(expected + 1)), &[])
+        .unwrap_err();
+    assert_eq!(
+        error_message(err),
+        format!(
+            "Assertion failed; minimum receive amount: {}, swap amount: {expected}",
+            expected + 1
+        )
+    );
+
+    suite
+        .execute(&owner, &convert_fees(Some(expected)), &[])
+        .unwrap();
+    assert_eq!(suite.accrued_fee(&uluna), 0);
+    assert_eq!(suite.accrued_fee(&uusd), expected);
+}
+
+fn burned_fees(suite: &Suite) -> Vec<BurnedFee> {
+    suite.query(&QueryMsg::BurnedFees {
+        start_after: None,
+        limit: None,
+    })
+}
+
+#[test]
+fn convert_fees_burns_the_native_target_asset() {
+    let (mut suite, _, pool) = conversion_suite(|_| native("uusd"), true);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let expected = suite.pool_return(&[route(&[(&pool.unwrap(), &uluna, &uusd)], 30_000)]);
+    let owner = suite.owner.clone();
+    suite.execute(&owner, &convert_fees(None), &[]).unwrap();
+
+    assert_eq!(suite.accrued_fee(&uluna), 0);
+    assert_eq!(suite.accrued_fee(&uusd), 0);
+    assert_eq!(suite.balance(&suite.router, &uusd), 0);
+    assert_eq!(
+        burned_fees(&suite),
+        vec![BurnedFee {
+            asset: "uusd".to_string(),
+            amount: Uint128::new(expected),
+        }]
+    );
+}
+
+#[test]
+fn convert_fees_burns_the_cw20_target_asset() {
+    let (mut suite, token, pool) = conversion_suite(Suite::create_token, true);
+    let uluna = native("uluna");
+    let expected = suite.pool_return(&[route(&[(&pool.unwrap(), &uluna, &token)], 30_000)]);
+    let AssetInfo::Token { contract_addr } = &token else {
+        unreachable!()
+    };
+    let total_supply = |suite: &Suite| -> u128 {
+        let response: mock_cw20::TotalSupplyResponse = suite
+            .app
+            .wrap()
+            .query_wasm_smart(contract_addr, &mock_cw20::QueryMsg::TotalSupply {})
+            .unwrap();
+        response.total_supply.u128()
+    };
+    let supply = total_supply(&suite);
+    let owner = suite.owner.clone();
+    suite.execute(&owner, &convert_fees(None), &[]).unwrap();
+
+    assert_eq!(suite.balance(&suite.router, &token), 0);
+    assert_eq!(total_supply(&suite), supply - expected);
+    assert_eq!(
+        burned_fees(&suite),
+        vec![BurnedFee {
+            asset: token.id(),
+            amount: Uint128::new(expected),
+        }]
+    );
+}
+
+#[test]
+fn convert_fees_burns_the_fees_accrued_in_the_target_asset() {
+    let (mut suite, _, _) = conversion_suite(|_| native("uluna"), true);
+    let uluna = native("uluna");
+    let owner = suite.owner.clone();
+    suite.execute(&owner, &convert_fees(None), &[]).unwrap();
+
+    assert_eq!(suite.accrued_fee(&uluna), 0);
+    assert_eq!(suite.balance(&suite.router, &uluna), 0);
+    assert_eq!(
+        burned_fees(&suite),
+        vec![BurnedFee {
+            asset: "uluna".to_string(),
+            amount: Uint128::new(30_000),
+        }]
+    );
+}
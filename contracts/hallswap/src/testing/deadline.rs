@@ -0,0 +1,101 @@
+use cosmwasm_std::{coins, Addr, Timestamp};
+use models::asset_info::AssetInfo;
+
+use super::{error_message, native, route, swap_msg_with, Suite, SwapOptions};
+use crate::msg::{Deadline, ExecuteMsg};
+
+/// Router with a uluna and a cw20 pool against uusd, and a user holding both offer assets
+fn deadline_suite() -> (Suite, [(AssetInfo, Addr); 2]) {
+    let mut suite = Suite::with_fee(0, &[]);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let token = suite.create_token();
+    let luna_pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let token_pool = suite.create_pair([(&token, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uluna, 1_000_000);
+    suite.fund(&user, &token, 1_000_000);
+    (suite, [(uluna, luna_pool), (token, token_pool)])
+}
+
+/// Swaps 1000 of `offer_asset` before `deadline`, through the cw20 hook for tokens
+fn swap_before(
+    suite: &mut Suite,
+    (offer_asset, pool): &(AssetInfo, Addr),
+    deadline: Deadline,
+) -> anyhow::Result<()> {
+    let msg: ExecuteMsg = swap_msg_with(
+        vec![route(&[(pool, offer_asset, &native("uusd"))], 1_000)],
+        0,
+        SwapOptions {
+            deadline: Some(deadline),
+            ..SwapOptions::default()
+        },
+    );
+    let user = suite.user.clone();
+    match offer_asset {
+        AssetInfo::NativeToken { denom } => suite.execute(&user, &msg, &coins(1_000, denom)),
+        AssetInfo::Token { .. } => suite.send(&user, offer_asset, 1_000, &msg),
+    }
+    .map(|_| ())
+}
+
+fn now(suite: &Suite) -> (u64, Timestamp) {
+    let block = suite.app.block_info();
+    (block.height, block.time)
+}
+
+#[test]
+fn swap_expires_after_its_deadline_time() {
+    let (mut suite, offers) = deadline_suite();
+    let (height, time) = now(&suite);
+    for offer in &offers {
+        let deadline = Deadline {
+            timestamp: Some(time.minus_seconds(1)),
+            height: None,
+        };
+        let err = swap_before(&mut suite, offer, deadline).unwrap_err();
+        assert_eq!(
+            error_message(err),
+            format!(
+                "Swap deadline of time {} passed at block height {height} and time {time}",
+                time.minus_seconds(1)
+            )
+        );
+    }
+}
+
+#[test]
+fn swap_expires_after_its_deadline_height() {
+    let (mut suite, offers) = deadline_suite();
+    let (height, time) = now(&suite);
+    for offer in &offers {
+        let deadline = Deadline {
+            timestamp: Some(time.plus_seconds(60)),
+            height: Some(height - 1),
+        };
+        let err = swap_before(&mut suite, offer, deadline).unwrap_err();
+        assert_eq!(
+            error_message(err),
+            format!(
+                "Swap deadline of block height {} or time {} passed at block height {height} \
+                 and time {time}",
+                height - 1,
+                time.plus_seconds(60)
+            )
+        );
+    }
+}
+
+#[test]
+fn swap_runs_until_its_deadline() {
+    let (mut suite, offers) = deadline_suite();
+    let (height, time) = now(&suite);
+    for offer in &offers {
+        let deadline = Deadline {
+            timestamp: Some(time),
+            height: Some(height),
+        };
+        swap_before(&mut suite, offer, deadline).unwrap();
+    }
+    assert!(suite.balance(&suite.user, &native("uusd")) > 0);
+}
@@ -0,0 +1,149 @@
+use cosmwasm_std::{coins, Addr, Decimal, Uint128};
+use cw_multi_test::Executor;
+use models::asset_info::AssetInfo;
+
+use super::{fee, mock_cw721, native, route, swap_msg, Suite};
+use crate::msg::{ExecuteMsg, QueryMsg, QuerySimulationResult, RouteInfoV2};
+use crate::state::{FeeDiscount, HoldingCondition};
+
+const FEE_BPS: u128 = 100;
+const OFFER_AMOUNT: u128 = 1_000_000;
+
+/// Router charging uusd offers, waiving half the fee for holders of 1000 of a cw20 and a
+/// quarter for owners of an NFT of a collection the owner holds the only token of
+fn discount_suite() -> (Suite, AssetInfo, Addr, RouteInfoV2) {
+    let mut suite = Suite::with_fee(FEE_BPS as u16, &["uusd"]);
+    let (uusd, uluna) = (native("uusd"), native("uluna"));
+    let pool = suite.create_pair([(&uusd, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let token = suite.create_token();
+    let cw721_code_id = suite.app.store_code(mock_cw721::contract());
+    let collection = suite
+        .app
+        .instantiate_contract(
+            cw721_code_id,
+            suite.owner.clone(),
+            &mock_cw721::InstantiateMsg {
+                token_ids: vec!["1".to_string()],
+            },
+            &[],
+            "collection",
+            None,
+        )
+        .unwrap();
+    let AssetInfo::Token { contract_addr } = &token else {
+        unreachable!()
+    };
+    let discounts = vec![
+        FeeDiscount {
+            condition: HoldingCondition::Cw20Balance {
+                token: contract_addr.clone(),
+                min_balance: Uint128::new(1_000),
+            },
+            discount: Decimal::percent(50),
+        },
+        FeeDiscount {
+            condition: HoldingCondition::Cw721Owner {
+                collection: collection.clone(),
+            },
+            discount: Decimal::percent(25),
+        },
+    ];
+    let owner = suite.owner.clone();
+    suite
+        .execute(&owner, &ExecuteMsg::SetFeeDiscounts { discounts }, &[])
+        .unwrap();
+    let user = suite.user.clone();
+    suite.fund(&user, &uusd, 10 * OFFER_AMOUNT);
+    let route = route(&[(&pool, &uusd, &uluna)], OFFER_AMOUNT);
+    (suite, token, collection, route)
+}
+
+fn simulate_for(suite: &Suite, route: &RouteInfoV2, sender: Option<&Addr>) -> u128 {
+    let result: QuerySimulationResult = suite.query(&QueryMsg::Simulation {
+        routes: vec![route.clone()],
+        referral: None,
+        sender: sender.cloned(),
+    });
+    result.return_asset.amount.u128()
+}
+
+/// Asserts that the user's swap and its simulation both charge `fee_bps`
+fn assert_swap_fee(suite: &mut Suite, route: &RouteInfoV2, fee_bps: u128) {
+    let mut charged_route = route.clone();
+    charged_route.offer_amount = Uint128::new(OFFER_AMOUNT - fee(OFFER_AMOUNT, fee_bps));
+    let expected = suite.pool_return(&[charged_route]);
+    let user = suite.user.clone();
+    assert_eq!(simulate_for(suite, route, Some(&user)), expected);
+
+    let uluna = native("uluna");
+    let balance = suite.balance(&user, &uluna);
+    let accrued = suite.accrued_fee(&native("uusd"));
+    suite
+        .execute(
+            &user,
+            &swap_msg(vec![route.clone()], expected),
+            &coins(OFFER_AMOUNT, "uusd"),
+        )
+        .unwrap();
+    assert_eq!(suite.balance(&user, &uluna) - balance, expected);
+    assert_eq!(
+        suite.accrued_fee(&native("uusd")) - accrued,
+        fee(OFFER_AMOUNT, fee_bps)
+    );
+}
+
+#[test]
+fn cw20_holders_get_their_discount() {
+    let (mut suite, token, _, route) = discount_suite();
+    let user = suite.user.clone();
+    suite.fund(&user, &token, 999);
+    assert_swap_fee(&mut suite, &route, FEE_BPS);
+
+    suite.fund(&user, &token, 1);
+    assert_swap_fee(&mut suite, &route, FEE_BPS / 2);
+    // Without a sender, the simulation cannot know of the discount
+    assert!(simulate_for(&suite, &route, None) < simulate_for(&suite, &route, Some(&user)));
+}
+
+#[test]
+fn cw721_owners_get_their_discount() {
+    let (mut suite, _, collection, route) = discount_suite();
+    let (owner, user) = (suite.owner.clone(), suite.user.clone());
+    assert_swap_fee(&mut suite, &route, FEE_BPS);
+
+    let transfer = |recipient: &Addr| mock_cw721::ExecuteMsg::TransferNft {
+        recipient: recipient.to_string(),
+        token_id: "1".to_string(),
+    };
+    suite
+        .app
+        .execute_contract(owner.clone(), collection.clone(), &transfer(&user), &[])
+        .unwrap();
+    assert_swap_fee(&mut suite, &route, FEE_BPS * 3 / 4);
+
+    suite
+        .app
+        .execute_contract(user, collection, &transfer(&owner), &[])
+        .unwrap();
+    assert_swap_fee(&mut suite, &route, FEE_BPS);
+}
+
+#[test]
+fn holders_of_both_get_the_highest_discount() {
+    let (mut suite, token, collection, route) = discount_suite();
+    let (owner, user) = (suite.owner.clone(), suite.user.clone());
+    suite
+        .app
+        .execute_contract(
+            owner,
+            collection,
+            &mock_cw721::ExecuteMsg::TransferNft {
+                recipient: user.to_string(),
+                token_id: "1".to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    suite.fund(&user, &token, 1_000);
+    assert_swap_fee(&mut suite, &route, FEE_BPS / 2);
+}
@@ -0,0 +1,59 @@
+use cosmwasm_std::Coin;
+
+use super::{fee, native, route, swap_msg, Suite};
+use crate::msg::ExecuteMsg;
+
+#[test]
+fn basket_charges_every_route_its_own_return_fee() {
+    let mut suite = Suite::with_fee(30, &["uosmo"]);
+    let [uluna, uatom, uosmo, uusd] = ["uluna", "uatom", "uosmo", "uusd"].map(native);
+    let owner = suite.owner.clone();
+    suite
+        .execute(
+            &owner,
+            &ExecuteMsg::SetAssetFee {
+                asset_info: uluna.clone(),
+                fee_bps: Some(100),
+            },
+            &[],
+        )
+        .unwrap();
+    let luna_pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let atom_pool = suite.create_pair([(&uatom, 1_000_000_000), (&uusd, 2_000_000_000)]);
+    let osmo_pool = suite.create_pair([(&uosmo, 1_000_000_000), (&uusd, 500_000_000)]);
+    let user = suite.user.clone();
+    let funds = [
+        ("uatom", 2_000_000),
+        ("uluna", 1_000_000),
+        ("uosmo", 3_000_000),
+    ];
+    for (denom, amount) in funds {
+        suite.fund(&user, &native(denom), amount);
+    }
+
+    // uluna and uatom are charged on their return, uosmo on its offer amount
+    let routes = vec![
+        route(&[(&luna_pool, &uluna, &uusd)], 1_000_000),
+        route(&[(&atom_pool, &uatom, &uusd)], 2_000_000),
+        route(&[(&osmo_pool, &uosmo, &uusd)], 3_000_000),
+    ];
+    let luna_return = suite.pool_return(&routes[..1]);
+    let atom_return = suite.pool_return(&routes[1..2]);
+    let osmo_fee = fee(3_000_000, 30);
+    let osmo_return =
+        suite.pool_return(&[route(&[(&osmo_pool, &uosmo, &uusd)], 3_000_000 - osmo_fee)]);
+    let return_fee = fee(luna_return, 100) + fee(atom_return, 30);
+    let expected = luna_return + atom_return + osmo_return - return_fee;
+    assert_eq!(suite.simulate(&routes), expected);
+
+    let funds: Vec<Coin> = funds
+        .iter()
+        .map(|&(denom, amount)| Coin::new(amount, denom))
+        .collect();
+    suite
+        .execute(&user, &swap_msg(routes, expected), &funds)
+        .unwrap();
+    assert_eq!(suite.balance(&user, &uusd), expected);
+    assert_eq!(suite.accrued_fee(&uusd), return_fee);
+    assert_eq!(suite.accrued_fee(&uosmo), osmo_fee);
+}
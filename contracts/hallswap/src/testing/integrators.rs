@@ -0,0 +1,70 @@
+use cosmwasm_std::{coins, Addr, Decimal, Uint128};
+use models::asset::Asset;
+
+use super::{native, route, swap_msg_with, Suite, SwapOptions};
+use crate::msg::{ExecuteMsg, QueryMsg};
+
+#[test]
+fn integrator_accrues_its_share_of_the_fee_and_claims_it() {
+    let mut suite = Suite::with_fee(30, &["uluna"]);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let (owner, user, wallet) = (
+        suite.owner.clone(),
+        suite.user.clone(),
+        Addr::unchecked("wallet"),
+    );
+    suite
+        .execute(
+            &owner,
+            &ExecuteMsg::RegisterIntegrator {
+                id: "wallet".to_string(),
+                address: wallet.clone(),
+                fee_share: Decimal::percent(40),
+            },
+            &[],
+        )
+        .unwrap();
+    suite.fund(&user, &uluna, 1_000_000);
+    let msg = swap_msg_with(
+        vec![route(&[(&pool, &uluna, &uusd)], 1_000_000)],
+        0,
+        SwapOptions {
+            integrator: Some("wallet".to_string()),
+            ..SwapOptions::default()
+        },
+    );
+    suite
+        .execute(&user, &msg, &coins(1_000_000, "uluna"))
+        .unwrap();
+
+    // 40% of the 3000 fee goes to the integrator
+    let integrator_fees = |suite: &Suite| -> Vec<Asset> {
+        suite.query(&QueryMsg::IntegratorFees {
+            id: "wallet".to_string(),
+        })
+    };
+    let asset = |amount: u128| Asset {
+        info: uluna.clone(),
+        amount: Uint128::new(amount),
+    };
+    let volume: Vec<Asset> = suite.query(&QueryMsg::IntegratorVolume {
+        id: "wallet".to_string(),
+    });
+    assert_eq!(volume, vec![asset(1_000_000)]);
+    assert_eq!(integrator_fees(&suite), vec![asset(1_200)]);
+    assert_eq!(suite.accrued_fee(&uluna), 1_800);
+
+    suite
+        .execute(
+            &wallet,
+            &ExecuteMsg::ClaimIntegratorFees {
+                id: "wallet".to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.balance(&wallet, &uluna), 1_200);
+    assert_eq!(integrator_fees(&suite), vec![]);
+    assert_eq!(suite.balance(&suite.router, &uluna), 1_800);
+}
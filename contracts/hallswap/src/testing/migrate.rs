@@ -0,0 +1,111 @@
+use cosmwasm_std::{
+    from_json, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdResult,
+};
+use cw_multi_test::{App, ContractWrapper, Executor};
+
+use crate::contract::{execute, instantiate, migrate, query, reply};
+use crate::msg::InstantiateMsg;
+use crate::state::{Config, FeeRecipient};
+
+/// Config of the routers deployed before `fee_recipients`, stored under the same key
+const LEGACY_CONFIG_JSON: &str =
+    r#"{"owner":"owner","fee_address":"collector","fee_bps":30,"fee_assets":["uusd"]}"#;
+
+/// Stands in for a router deployed before `fee_recipients`, storing only its config
+fn legacy_instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _msg: Empty,
+) -> StdResult<Response> {
+    deps.storage.set(b"config", LEGACY_CONFIG_JSON.as_bytes());
+    Ok(Response::default())
+}
+
+fn legacy_execute(_: DepsMut, _: Env, _: MessageInfo, _: Empty) -> StdResult<Response> {
+    Ok(Response::default())
+}
+
+fn legacy_query(_: Deps, _: Env, _: Empty) -> StdResult<Binary> {
+    Ok(Binary::default())
+}
+
+fn migrate_msg(max_referral_bps: Option<u16>) -> InstantiateMsg {
+    InstantiateMsg {
+        owner: None,
+        fee_address: None,
+        fee_recipients: None,
+        fee_bps: Some(30),
+        fee_assets: Some(vec!["uusd".to_string()]),
+        max_referral_bps,
+    }
+}
+
+fn stored_config(app: &App, router: &Addr) -> Config {
+    let config = app
+        .wrap()
+        .query_wasm_raw(router, b"config".as_slice())
+        .unwrap()
+        .unwrap();
+    from_json(config).unwrap()
+}
+
+#[test]
+fn migrate_turns_the_legacy_fee_address_into_the_only_recipient() {
+    let mut app = App::default();
+    let owner = Addr::unchecked("owner");
+    let legacy_code_id = app.store_code(Box::new(ContractWrapper::new(
+        legacy_execute,
+        legacy_instantiate,
+        legacy_query,
+    )));
+    let router_code_id = app.store_code(Box::new(
+        ContractWrapper::new(execute, instantiate, query)
+            .with_reply(reply)
+            .with_migrate(migrate),
+    ));
+    let router = app
+        .instantiate_contract(
+            legacy_code_id,
+            owner.clone(),
+            &Empty {},
+            &[],
+            "hallswap",
+            Some(owner.to_string()),
+        )
+        .unwrap();
+
+    app.migrate_contract(
+        owner.clone(),
+        router.clone(),
+        &migrate_msg(None),
+        router_code_id,
+    )
+    .unwrap();
+    let collector = vec![FeeRecipient {
+        address: Addr::unchecked("collector"),
+        weight: 1,
+    }];
+    assert_eq!(
+        stored_config(&app, &router),
+        Config {
+            owner: owner.clone(),
+            fee_recipients: collector.clone(),
+            fee_bps: 30,
+            fee_assets: vec!["uusd".to_string()],
+            max_referral_bps: 0,
+        }
+    );
+
+    // Later migrations keep the recipients of the current config
+    app.migrate_contract(
+        owner.clone(),
+        router.clone(),
+        &migrate_msg(Some(50)),
+        router_code_id,
+    )
+    .unwrap();
+    let config = stored_config(&app, &router);
+    assert_eq!(config.fee_recipients, collector);
+    assert_eq!(config.max_referral_bps, 50);
+}
@@ -0,0 +1,115 @@
+use cosmwasm_std::{coins, Addr};
+use cw_multi_test::AppResponse;
+
+use super::{error_message, native, route, swap_msg_with, Suite, SwapOptions};
+use crate::msg::{ExecuteMsg, MinimumReceiveMode, RouteInfoV2};
+
+fn swap_to_msg(
+    routes: Vec<RouteInfoV2>,
+    minimum_receive: u128,
+    to: &Addr,
+    minimum_receive_mode: Option<MinimumReceiveMode>,
+) -> ExecuteMsg {
+    swap_msg_with(
+        routes,
+        minimum_receive,
+        SwapOptions {
+            to: Some(to.clone()),
+            minimum_receive_mode,
+            ..SwapOptions::default()
+        },
+    )
+}
+
+/// Returns whether `pool` sent `amount` of uusd to `recipient` itself
+fn pool_paid(response: &AppResponse, pool: &Addr, recipient: &Addr, amount: u128) -> bool {
+    response.events.iter().any(|event| {
+        let attribute = |key: &str| {
+            event
+                .attributes
+                .iter()
+                .find(|attribute| attribute.key == key)
+                .map(|attribute| attribute.value.clone())
+        };
+        event.ty == "transfer"
+            && attribute("sender") == Some(pool.to_string())
+            && attribute("recipient") == Some(recipient.to_string())
+            && attribute("amount") == Some(format!("{amount}uusd"))
+    })
+}
+
+#[test]
+fn router_output_counts_what_the_last_hop_paid_the_receiver() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let (user, receiver) = (suite.user.clone(), Addr::unchecked("receiver"));
+    suite.fund(&user, &uluna, 2_000_000);
+    let routes = vec![route(&[(&pool, &uluna, &uusd)], 1_000_000)];
+    let expected = suite.simulate(&routes);
+
+    let err = suite
+        .execute(
+            &user,
+            &swap_to_msg(routes.clone(), expected + 1, &receiver, None),
+            &coins(1_000_000, "uluna"),
+        )
+        .unwrap_err();
+    assert_eq!(
+        error_message(err),
+        format!(
+            "Assertion failed; minimum receive amount: {}, swap amount: {expected}",
+            expected + 1
+        )
+    );
+
+    let response = suite
+        .execute(
+            &user,
+            &swap_to_msg(routes, expected, &receiver, None),
+            &coins(1_000_000, "uluna"),
+        )
+        .unwrap();
+    assert!(pool_paid(&response, &pool, &receiver, expected));
+    assert_eq!(suite.balance(&receiver, &uusd), expected);
+    assert_eq!(suite.balance(&suite.router, &uusd), 0);
+}
+
+#[test]
+fn receiver_balance_also_asserts_the_router_output() {
+    let mut suite = Suite::with_fee(30, &["uluna"]);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let (user, receiver) = (suite.user.clone(), Addr::unchecked("receiver"));
+    suite.fund(&user, &uluna, 2_000_000);
+    // The receiver already holds more than the minimum, which a balance check alone ignores
+    suite.fund(&receiver, &uusd, 5_000_000);
+    let routes = vec![route(&[(&pool, &uluna, &uusd)], 1_000_000)];
+    let expected = suite.simulate(&routes);
+    let mode = Some(MinimumReceiveMode::ReceiverBalance {});
+
+    let err = suite
+        .execute(
+            &user,
+            &swap_to_msg(routes.clone(), expected + 1, &receiver, mode.clone()),
+            &coins(1_000_000, "uluna"),
+        )
+        .unwrap_err();
+    assert_eq!(
+        error_message(err),
+        format!(
+            "Assertion failed; minimum receive amount: {}, swap amount: {expected}",
+            expected + 1
+        )
+    );
+
+    let response = suite
+        .execute(
+            &user,
+            &swap_to_msg(routes, expected, &receiver, mode),
+            &coins(1_000_000, "uluna"),
+        )
+        .unwrap();
+    assert!(pool_paid(&response, &pool, &receiver, expected));
+    assert_eq!(suite.balance(&receiver, &uusd), 5_000_000 + expected);
+}
@@ -0,0 +1,156 @@
+//! Minimal cw20 covering the messages the router sends and the queries it makes.
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_json_binary, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError,
+    StdResult, Storage, Uint128,
+};
+use cw20::{BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::{Item, Map};
+
+const BALANCES: Map<&Addr, Uint128> = Map::new("balances");
+const ALLOWANCES: Map<(&Addr, &Addr), Uint128> = Map::new("allowances");
+const TOTAL_SUPPLY: Item<Uint128> = Item::new("total_supply");
+
+#[cw_serde]
+pub struct InstantiateMsg {
+    pub initial_balances: Vec<Cw20Coin>,
+}
+
+#[cw_serde]
+pub struct TotalSupplyResponse {
+    pub total_supply: Uint128,
+}
+
+#[cw_serde]
+pub enum QueryMsg {
+    Balance { address: String },
+    TotalSupply {},
+}
+
+pub fn contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
+
+fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: InstantiateMsg,
+) -> StdResult<Response> {
+    let mut total_supply = Uint128::zero();
+    for coin in msg.initial_balances {
+        let address = deps.api.addr_validate(&coin.address)?;
+        BALANCES.save(deps.storage, &address, &coin.amount)?;
+        total_supply += coin.amount;
+    }
+    TOTAL_SUPPLY.save(deps.storage, &total_supply)?;
+    Ok(Response::default())
+}
+
+fn execute(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: Cw20ExecuteMsg,
+) -> StdResult<Response> {
+    match msg {
+        Cw20ExecuteMsg::Transfer { recipient, amount } => {
+            let recipient = deps.api.addr_validate(&recipient)?;
+            transfer(deps, &info.sender, &recipient, amount)?;
+            Ok(Response::default())
+        }
+        Cw20ExecuteMsg::Send {
+            contract,
+            amount,
+            msg,
+        } => {
+            let contract = deps.api.addr_validate(&contract)?;
+            transfer(deps, &info.sender, &contract, amount)?;
+            Ok(Response::new().add_message(
+                Cw20ReceiveMsg {
+                    sender: info.sender.to_string(),
+                    amount,
+                    msg,
+                }
+                .into_cosmos_msg(contract)?,
+            ))
+        }
+        Cw20ExecuteMsg::TransferFrom {
+            owner,
+            recipient,
+            amount,
+        } => {
+            let (owner, recipient) = (
+                deps.api.addr_validate(&owner)?,
+                deps.api.addr_validate(&recipient)?,
+            );
+            ALLOWANCES.update(deps.storage, (&owner, &info.sender), |allowance| {
+                allowance
+                    .unwrap_or_default()
+                    .checked_sub(amount)
+                    .map_err(StdError::overflow)
+            })?;
+            transfer(deps, &owner, &recipient, amount)?;
+            Ok(Response::default())
+        }
+        Cw20ExecuteMsg::IncreaseAllowance {
+            spender, amount, ..
+        } => {
+            let spender = deps.api.addr_validate(&spender)?;
+            ALLOWANCES.update(deps.storage, (&info.sender, &spender), |allowance| {
+                allowance
+                    .unwrap_or_default()
+                    .checked_add(amount)
+                    .map_err(StdError::overflow)
+            })?;
+            Ok(Response::default())
+        }
+        Cw20ExecuteMsg::Burn { amount } => {
+            sub_balance(deps.storage, &info.sender, amount)?;
+            TOTAL_SUPPLY.update(deps.storage, |total_supply| {
+                total_supply.checked_sub(amount).map_err(StdError::overflow)
+            })?;
+            Ok(Response::default())
+        }
+        _ => Err(StdError::generic_err("unsupported by the mock cw20")),
+    }
+}
+
+fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Balance { address } => {
+            let address = deps.api.addr_validate(&address)?;
+            to_json_binary(&BalanceResponse {
+                balance: BALANCES
+                    .may_load(deps.storage, &address)?
+                    .unwrap_or_default(),
+            })
+        }
+        QueryMsg::TotalSupply {} => to_json_binary(&TotalSupplyResponse {
+            total_supply: TOTAL_SUPPLY.load(deps.storage)?,
+        }),
+    }
+}
+
+fn transfer(deps: DepsMut, from: &Addr, to: &Addr, amount: Uint128) -> StdResult<()> {
+    sub_balance(deps.storage, from, amount)?;
+    BALANCES.update(deps.storage, to, |balance| {
+        balance
+            .unwrap_or_default()
+            .checked_add(amount)
+            .map_err(StdError::overflow)
+    })?;
+    Ok(())
+}
+
+fn sub_balance(storage: &mut dyn Storage, address: &Addr, amount: Uint128) -> StdResult<()> {
+    BALANCES.update(storage, address, |balance| {
+        balance
+            .unwrap_or_default()
+            .checked_sub(amount)
+            .map_err(StdError::overflow)
+    })?;
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! Minimal cw721 covering the ownership query the router makes for holder discounts.
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_json_binary, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Order, Response,
+    StdError, StdResult,
+};
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::{Bound, Map};
+use querier::msg::{Cw721QueryMsg, Cw721TokensResponse};
+
+const OWNERS: Map<&str, Addr> = Map::new("owners");
+
+#[cw_serde]
+pub struct InstantiateMsg {
+    /// Token ids minted to the instantiator
+    pub token_ids: Vec<String>,
+}
+
+#[cw_serde]
+pub enum ExecuteMsg {
+    TransferNft { recipient: String, token_id: String },
+}
+
+pub fn contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
+
+fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> StdResult<Response> {
+    for token_id in msg.token_ids {
+        OWNERS.save(deps.storage, &token_id, &info.sender)?;
+    }
+    Ok(Response::default())
+}
+
+fn execute(deps: DepsMut, _env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+    match msg {
+        ExecuteMsg::TransferNft {
+            recipient,
+            token_id,
+        } => {
+            if OWNERS.load(deps.storage, &token_id)? != info.sender {
+                return Err(StdError::generic_err("not the token owner"));
+            }
+            let recipient = deps.api.addr_validate(&recipient)?;
+            OWNERS.save(deps.storage, &token_id, &recipient)?;
+            Ok(Response::default())
+        }
+    }
+}
+
+fn query(deps: Deps, _env: Env, msg: Cw721QueryMsg) -> StdResult<Binary> {
+    match msg {
+        Cw721QueryMsg::Tokens {
+            owner,
+            start_after,
+            limit,
+        } => {
+            let owner = deps.api.addr_validate(&owner)?;
+            let start = start_after.as_deref().map(Bound::exclusive);
+            let tokens = OWNERS
+                .range(deps.storage, start, None, Order::Ascending)
+                .filter(|item| item.as_ref().map_or(true, |(_, holder)| *holder == owner))
+                .map(|item| item.map(|(token_id, _)| token_id))
+                .take(limit.unwrap_or(10) as usize)
+                .collect::<StdResult<_>>()?;
+            to_json_binary(&Cw721TokensResponse { tokens })
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Pair speaking the Astroport interface, quoting with `math::xyk`, or `math::stableswap` when
+//! given stable parameters, against its own balances.
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    from_json, to_json_binary, Addr, Binary, Decimal, Deps, DepsMut, Empty, Env, MessageInfo,
+    Response, StdError, StdResult, Uint128,
+};
+use cw20::Cw20ReceiveMsg;
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::{Item, Map};
+use math::{stableswap, xyk};
+use models::asset::Asset;
+use models::asset_info::AssetInfo;
+use querier::msg::{
+    AstroportPairType, AstroportStableParams, PairConfigResponse, PairInfo, PairQueryMsg,
+    PairSimulationResponse, PoolResponse,
+};
+use querier::querier::query_balance;
+
+use crate::msg::PairCw20HookMsg;
+
+const CONFIG: Item<InstantiateMsg> = Item::new("config");
+/// Decimals of every asset, under the key Astroport stable pairs use
+const PRECISIONS: Map<String, u8> = Map::new("precisions");
+/// Share of the quoted return that swaps withhold, see `ExecuteMsg::SetSwapShortfall`
+const SWAP_SHORTFALL_BPS: Item<u16> = Item::new("swap_shortfall_bps");
+
+#[cw_serde]
+pub struct InstantiateMsg {
+    pub asset_infos: Vec<AssetInfo>,
+    pub commission_rate: Decimal,
+    pub pair_type: AstroportPairType,
+    pub stable: Option<StableParams>,
+}
+
+#[cw_serde]
+pub struct StableParams {
+    pub amp: u64,
+    /// Decimals of the assets, in the order of `asset_infos`
+    pub precisions: Vec<u8>,
+}
+
+#[cw_serde]
+pub enum ExecuteMsg {
+    Swap {
+        offer_asset: Asset,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<String>,
+    },
+    Receive(Cw20ReceiveMsg),
+    /// Makes swaps return `bps` less than simulations quote, as when the price moves between
+    /// a quote and the swap
+    SetSwapShortfall {
+        bps: u16,
+    },
+}
+
+pub fn contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
+
+fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: InstantiateMsg,
+) -> StdResult<Response> {
+    if let Some(stable) = &msg.stable {
+        for (info, precision) in msg.asset_infos.iter().zip(&stable.precisions) {
+            PRECISIONS.save(deps.storage, info.id(), precision)?;
+        }
+    }
+    CONFIG.save(deps.storage, &msg)?;
+    Ok(Response::default())
+}
+
+fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+    match msg {
+        ExecuteMsg::Swap {
+            offer_asset, to, ..
+        } => {
+            let sent = info
+                .funds
+                .iter()
+                .find(|coin| coin.denom == offer_asset.id())
+                .map_or(Uint128::zero(), |coin| coin.amount);
+            if sent != offer_asset.amount || offer_asset.amount.is_zero() {
+                return Err(StdError::generic_err("offer asset not sent"));
+            }
+            swap(deps.as_ref(), &env, info.sender, offer_asset, to)
+        }
+        ExecuteMsg::Receive(cw20_msg) => {
+            let PairCw20HookMsg::Swap { to, .. } = from_json(&cw20_msg.msg)?;
+            let offer_asset = Asset {
+                info: AssetInfo::Token {
+                    contract_addr: info.sender,
+                },
+                amount: cw20_msg.amount,
+            };
+            swap(
+                deps.as_ref(),
+                &env,
+                Addr::unchecked(cw20_msg.sender),
+                offer_asset,
+                to,
+            )
+        }
+        ExecuteMsg::SetSwapShortfall { bps } => {
+            SWAP_SHORTFALL_BPS.save(deps.storage, &bps)?;
+            Ok(Response::default())
+        }
+    }
+}
+
+fn query(deps: Deps, env: Env, msg: PairQueryMsg) -> StdResult<Binary> {
+    let config = CONFIG.load(deps.storage)?;
+    match msg {
+        PairQueryMsg::Pair {} => to_json_binary(&PairInfo {
+            asset_infos: config.asset_infos,
+            pair_type: Some(config.pair_type),
+        }),
+        PairQueryMsg::Pool {} => to_json_binary(&PoolResponse {
+            assets: config
+                .asset_infos
+                .iter()
+                .map(|info| {
+                    Ok(Asset {
+                        info: info.clone(),
+                        amount: reserve(deps, &env, info)?,
+                    })
+                })
+                .collect::<StdResult<_>>()?,
+            total_share: Uint128::zero(),
+        }),
+        PairQueryMsg::Simulation { offer_asset } => {
+            let (return_amount, _) = compute_swap(deps, &env, &offer_asset, Uint128::zero())?;
+            to_json_binary(&PairSimulationResponse { return_amount })
+        }
+        PairQueryMsg::Config {} => to_json_binary(&PairConfigResponse {
+            params: config
+                .stable
+                .map(|stable| {
+                    to_json_binary(&AstroportStableParams {
+                        amp: Decimal::from_ratio(stable.amp, 1u8),
+                    })
+                })
+                .transpose()?,
+        }),
+    }
+}
+
+fn swap(
+    deps: Deps,
+    env: &Env,
+    sender: Addr,
+    offer_asset: Asset,
+    to: Option<String>,
+) -> StdResult<Response> {
+    // The offer amount is already part of the balance
+    let (return_amount, ask_info) = compute_swap(deps, env, &offer_asset, offer_asset.amount)?;
+    let shortfall_bps = SWAP_SHORTFALL_BPS
+        .may_load(deps.storage)?
+        .unwrap_or_default();
+    let return_amount = return_amount.multiply_ratio(10000 - shortfall_bps, 10000u16);
+    let receiver = match to {
+        Some(to) => deps.api.addr_validate(&to)?,
+        None => sender,
+    };
+    Ok(Response::new()
+        .add_message(ask_info.to_send_msg(receiver.to_string(), return_amount))
+        .add_attribute("return_amount", return_amount))
+}
+
+/// Returns the return amount and asset of offering `offer_asset` while the pair already holds
+/// `received` of it.
+fn compute_swap(
+    deps: Deps,
+    env: &Env,
+    offer_asset: &Asset,
+    received: Uint128,
+) -> StdResult<(Uint128, AssetInfo)> {
+    let config = CONFIG.load(deps.storage)?;
+    let offer_index = config
+        .asset_infos
+        .iter()
+        .position(|info| info.id() == offer_asset.id())
+        .ok_or_else(|| StdError::generic_err("asset not in pair"))?;
+    let ask_index = 1 - offer_index;
+    let mut reserves = config
+        .asset_infos
+        .iter()
+        .map(|info| reserve(deps, env, info))
+        .collect::<StdResult<Vec<_>>>()?;
+    reserves[offer_index] = reserves[offer_index].checked_sub(received)?;
+    let return_amount = match &config.stable {
+        None => xyk::compute_swap(
+            reserves[offer_index],
+            reserves[ask_index],
+            offer_asset.amount,
+            config.commission_rate,
+        )
+        .map(|swap| swap.return_amount),
+        Some(stable) => stableswap::compute_swap(
+            stable.amp,
+            &reserves,
+            &stable.precisions,
+            offer_index,
+            ask_index,
+            offer_asset.amount,
+            config.commission_rate,
+        )
+        .map(|swap| swap.return_amount),
+    }
+    .map_err(|err| StdError::generic_err(err.to_string()))?;
+    Ok((return_amount, config.asset_infos[ask_index].clone()))
+}
+
+fn reserve(deps: Deps, env: &Env, info: &AssetInfo) -> StdResult<Uint128> {
+    query_balance(&deps.querier, &env.contract.address, info)
+        .map_err(|err| StdError::generic_err(err.to_string()))
+}
@@ -0,0 +1,345 @@
+mod mock_cw20;
+mod mock_cw721;
+mod mock_pair;
+
+mod arbitrage;
+mod basket;
+mod claims;
+mod conversion;
+mod deadline;
+mod discounts;
+mod fees;
+mod integrators;
+mod migrate;
+mod minimum_receive;
+mod multi_output;
+mod partial;
+mod referral;
+mod shared_pools;
+mod simulation_errors;
+mod slippage;
+mod v3;
+mod validate_routes;
+mod volume;
+
+use cosmwasm_std::{coins, to_json_binary, Addr, Coin, Decimal, Uint128};
+use cw20::{Cw20Coin, Cw20ExecuteMsg};
+use cw_multi_test::{App, AppResponse, BankSudo, ContractWrapper, Executor, SudoMsg};
+use models::asset::Asset;
+use models::asset_info::AssetInfo;
+use querier::msg::{AstroportPairType, PairQueryMsg, PairSimulationResponse};
+use querier::querier::query_balance;
+use serde::de::DeserializeOwned;
+
+use crate::contract::{execute, instantiate, query, reply};
+use crate::msg::{
+    AccruedFee, Deadline, ExecuteMsg, InstantiateMsg, MinimumReceiveMode, QueryMsg,
+    QuerySimulationResult, Referral, RouteInfoV2, SwapOperation,
+};
+use mock_pair::StableParams;
+
+/// Supply of every test token, held by the owner until it funds pairs or users
+const TOKEN_SUPPLY: u128 = 1_000_000_000_000_000;
+
+/// Router deployed on a `cw-multi-test` app, along with mock pairs and cw20 tokens
+struct Suite {
+    app: App,
+    owner: Addr,
+    user: Addr,
+    router: Addr,
+    cw20_code_id: u64,
+    pair_code_id: u64,
+}
+
+impl Suite {
+    fn new(msg: InstantiateMsg) -> Self {
+        let mut app = App::default();
+        let owner = Addr::unchecked("owner");
+        let router_code_id = app.store_code(Box::new(
+            ContractWrapper::new(execute, instantiate, query).with_reply(reply),
+        ));
+        let cw20_code_id = app.store_code(mock_cw20::contract());
+        let pair_code_id = app.store_code(mock_pair::contract());
+        let router = app
+            .instantiate_contract(router_code_id, owner.clone(), &msg, &[], "hallswap", None)
+            .unwrap();
+        Suite {
+            app,
+            owner,
+            user: Addr::unchecked("user"),
+            router,
+            cw20_code_id,
+            pair_code_id,
+        }
+    }
+
+    /// Router owned by `owner`, which also receives every protocol fee
+    fn with_fee(fee_bps: u16, fee_assets: &[&str]) -> Self {
+        Suite::new(InstantiateMsg {
+            owner: None,
+            fee_address: None,
+            fee_recipients: None,
+            fee_bps: Some(fee_bps),
+            fee_assets: Some(fee_assets.iter().map(|asset| asset.to_string()).collect()),
+            max_referral_bps: Some(100),
+        })
+    }
+
+    fn create_token(&mut self) -> AssetInfo {
+        let contract_addr = self
+            .app
+            .instantiate_contract(
+                self.cw20_code_id,
+                self.owner.clone(),
+                &mock_cw20::InstantiateMsg {
+                    initial_balances: vec![Cw20Coin {
+                        address: self.owner.to_string(),
+                        amount: Uint128::new(TOKEN_SUPPLY),
+                    }],
+                },
+                &[],
+                "token",
+                None,
+            )
+            .unwrap();
+        AssetInfo::Token { contract_addr }
+    }
+
+    /// Creates a constant product pair holding `reserves`, with a 0.3% commission
+    fn create_pair(&mut self, reserves: [(&AssetInfo, u128); 2]) -> Addr {
+        self.create_typed_pair(reserves, AstroportPairType::Xyk {}, None)
+    }
+
+    /// Creates a pair reporting `pair_type` and holding `reserves`, with a 0.3% commission. It
+    /// quotes with stableswap math when given `stable` parameters, else constant product math.
+    fn create_typed_pair(
+        &mut self,
+        reserves: [(&AssetInfo, u128); 2],
+        pair_type: AstroportPairType,
+        stable: Option<StableParams>,
+    ) -> Addr {
+        let pair = self
+            .app
+            .instantiate_contract(
+                self.pair_code_id,
+                self.owner.clone(),
+                &mock_pair::InstantiateMsg {
+                    asset_infos: reserves.iter().map(|(info, _)| (*info).clone()).collect(),
+                    commission_rate: Decimal::permille(3),
+                    pair_type,
+                    stable,
+                },
+                &[],
+                "pair",
+                None,
+            )
+            .unwrap();
+        // Empty reserves are left unfunded, the bank refusing to mint nothing
+        for (info, amount) in reserves.into_iter().filter(|(_, amount)| *amount > 0) {
+            self.fund(&pair, info, amount);
+        }
+        pair
+    }
+
+    /// Mints native coins to `to`, or transfers tokens from the owner
+    fn fund(&mut self, to: &Addr, info: &AssetInfo, amount: u128) {
+        match info {
+            AssetInfo::NativeToken { denom } => {
+                self.app
+                    .sudo(SudoMsg::Bank(BankSudo::Mint {
+                        to_address: to.to_string(),
+                        amount: coins(amount, denom),
+                    }))
+                    .unwrap();
+            }
+            AssetInfo::Token { contract_addr } => {
+                self.app
+                    .execute_contract(
+                        self.owner.clone(),
+                        contract_addr.clone(),
+                        &Cw20ExecuteMsg::Transfer {
+                            recipient: to.to_string(),
+                            amount: Uint128::new(amount),
+                        },
+                        &[],
+                    )
+                    .unwrap();
+            }
+        }
+    }
+
+    fn execute(
+        &mut self,
+        sender: &Addr,
+        msg: &ExecuteMsg,
+        funds: &[Coin],
+    ) -> anyhow::Result<AppResponse> {
+        self.app
+            .execute_contract(sender.clone(), self.router.clone(), msg, funds)
+    }
+
+    /// Sends `amount` of the cw20 `token` to the router along with `msg`
+    fn send(
+        &mut self,
+        sender: &Addr,
+        token: &AssetInfo,
+        amount: u128,
+        msg: &ExecuteMsg,
+    ) -> anyhow::Result<AppResponse> {
+        self.app.execute_contract(
+            sender.clone(),
+            Addr::unchecked(token.id()),
+            &Cw20ExecuteMsg::Send {
+                contract: self.router.to_string(),
+                amount: Uint128::new(amount),
+                msg: to_json_binary(msg).unwrap(),
+            },
+            &[],
+        )
+    }
+
+    fn increase_allowance(&mut self, owner: &Addr, token: &AssetInfo, amount: u128) {
+        self.app
+            .execute_contract(
+                owner.clone(),
+                Addr::unchecked(token.id()),
+                &Cw20ExecuteMsg::IncreaseAllowance {
+                    spender: self.router.to_string(),
+                    amount: Uint128::new(amount),
+                    expires: None,
+                },
+                &[],
+            )
+            .unwrap();
+    }
+
+    fn query<T: DeserializeOwned>(&self, msg: &QueryMsg) -> T {
+        self.app
+            .wrap()
+            .query_wasm_smart(self.router.clone(), msg)
+            .unwrap()
+    }
+
+    /// Returns the return amount the router quotes for `routes`
+    fn simulate(&self, routes: &[RouteInfoV2]) -> u128 {
+        let result: QuerySimulationResult = self.query(&QueryMsg::Simulation {
+            routes: routes.to_vec(),
+            referral: None,
+            sender: None,
+        });
+        result.return_asset.amount.u128()
+    }
+
+    /// Returns the return of `routes` before any router fee, chaining the simulations of their
+    /// pools
+    fn pool_return(&self, routes: &[RouteInfoV2]) -> u128 {
+        let mut return_amount = 0;
+        for route_info in routes {
+            let mut amount = route_info.offer_amount;
+            for operation in &route_info.route {
+                let response: PairSimulationResponse = self
+                    .app
+                    .wrap()
+                    .query_wasm_smart(
+                        &operation.contract_addr,
+                        &PairQueryMsg::Simulation {
+                            offer_asset: Asset {
+                                info: operation.offer_asset.clone(),
+                                amount,
+                            },
+                        },
+                    )
+                    .unwrap();
+                amount = response.return_amount;
+            }
+            return_amount += amount.u128();
+        }
+        return_amount
+    }
+
+    /// Returns the protocol fee accrued in `info`
+    fn accrued_fee(&self, info: &AssetInfo) -> u128 {
+        let accrued_fees: Vec<AccruedFee> = self.query(&QueryMsg::AccruedFees {
+            start_after: None,
+            limit: Some(30),
+        });
+        accrued_fees
+            .into_iter()
+            .find(|accrued_fee| accrued_fee.asset == info.id())
+            .map_or(0, |accrued_fee| accrued_fee.amount.u128())
+    }
+
+    fn balance(&self, addr: &Addr, info: &AssetInfo) -> u128 {
+        query_balance(&self.app.wrap(), addr, info).unwrap().u128()
+    }
+}
+
+fn native(denom: &str) -> AssetInfo {
+    AssetInfo::NativeToken {
+        denom: denom.to_string(),
+    }
+}
+
+/// Returns a route through `hops`, each a pair with its offer and return asset
+fn route(hops: &[(&Addr, &AssetInfo, &AssetInfo)], offer_amount: u128) -> RouteInfoV2 {
+    RouteInfoV2 {
+        route: hops
+            .iter()
+            .map(|(pair, offer_asset, return_asset)| SwapOperation {
+                contract_addr: (*pair).clone(),
+                offer_asset: (*offer_asset).clone(),
+                return_asset: (*return_asset).clone(),
+                interface: None,
+            })
+            .collect(),
+        offer_amount: Uint128::new(offer_amount),
+    }
+}
+
+/// Optional fields of `ExecuteRoutesV2`, all unset by default
+#[derive(Default)]
+struct SwapOptions {
+    to: Option<Addr>,
+    allow_partial: Option<bool>,
+    deadline: Option<Deadline>,
+    max_slippage_bps: Option<u16>,
+    reference_quote: Option<u128>,
+    minimum_receive_mode: Option<MinimumReceiveMode>,
+    referral: Option<Referral>,
+    integrator: Option<String>,
+}
+
+/// `ExecuteRoutesV2` without any of its options
+fn swap_msg(routes: Vec<RouteInfoV2>, minimum_receive: u128) -> ExecuteMsg {
+    swap_msg_with(routes, minimum_receive, SwapOptions::default())
+}
+
+/// `ExecuteRoutesV2` with `options`
+fn swap_msg_with(
+    routes: Vec<RouteInfoV2>,
+    minimum_receive: u128,
+    options: SwapOptions,
+) -> ExecuteMsg {
+    ExecuteMsg::ExecuteRoutesV2 {
+        routes,
+        minimum_receive: Uint128::new(minimum_receive),
+        to: options.to,
+        allow_partial: options.allow_partial,
+        deadline: options.deadline,
+        max_slippage_bps: options.max_slippage_bps,
+        reference_quote: options.reference_quote.map(Uint128::new),
+        minimum_receive_mode: options.minimum_receive_mode,
+        referral: options.referral,
+        integrator: options.integrator,
+    }
+}
+
+/// Fee of `fee_bps` on `amount`, floored like the router does
+fn fee(amount: u128, fee_bps: u128) -> u128 {
+    amount * fee_bps / 10_000
+}
+
+/// Returns the message of the error the router failed with
+fn error_message(err: anyhow::Error) -> String {
+    err.root_cause().to_string()
+}
@@ -0,0 +1,83 @@
+use cosmwasm_std::{coins, Uint128};
+
+use super::{error_message, native, route, Suite};
+use crate::msg::{ExecuteMsg, RouteInfoV2, SwapOutput};
+use crate::ContractError;
+use models::asset_info::AssetInfo;
+
+fn multi_output_msg(routes: Vec<RouteInfoV2>, outputs: &[(&AssetInfo, u128)]) -> ExecuteMsg {
+    ExecuteMsg::ExecuteRoutesMultiOutput {
+        routes,
+        outputs: outputs
+            .iter()
+            .map(|(asset_info, minimum_receive)| SwapOutput {
+                asset_info: (*asset_info).clone(),
+                minimum_receive: Uint128::new(*minimum_receive),
+                to: None,
+            })
+            .collect(),
+        deadline: None,
+        minimum_receive_mode: None,
+    }
+}
+
+#[test]
+fn multi_output_pays_every_output() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let (uusd, uluna, uatom) = (native("uusd"), native("uluna"), native("uatom"));
+    let luna_pair = suite.create_pair([(&uusd, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let atom_pair = suite.create_pair([(&uusd, 1_000_000_000), (&uatom, 2_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uusd, 3_000_000);
+
+    let luna_route = route(&[(&luna_pair, &uusd, &uluna)], 1_000_000);
+    let atom_route = route(&[(&atom_pair, &uusd, &uatom)], 2_000_000);
+    let (luna_return, atom_return) = (
+        suite.simulate(std::slice::from_ref(&luna_route)),
+        suite.simulate(std::slice::from_ref(&atom_route)),
+    );
+    suite
+        .execute(
+            &user,
+            &multi_output_msg(
+                vec![luna_route, atom_route],
+                &[(&uluna, luna_return), (&uatom, atom_return)],
+            ),
+            &coins(3_000_000, "uusd"),
+        )
+        .unwrap();
+
+    assert_eq!(suite.balance(&user, &uluna), luna_return);
+    assert_eq!(suite.balance(&user, &uatom), atom_return);
+    assert_eq!(suite.balance(&user, &uusd), 0);
+}
+
+#[test]
+fn multi_output_rejects_later_hops_offering_an_output() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let (uusd, uluna, uatom) = (native("uusd"), native("uluna"), native("uatom"));
+    let luna_pair = suite.create_pair([(&uusd, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let atom_pair = suite.create_pair([(&uluna, 1_000_000_000), (&uatom, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uusd, 2_000_000);
+
+    // The second hop of the uatom route would swap the uluna returned by the first route
+    let routes = vec![
+        route(&[(&luna_pair, &uusd, &uluna)], 1_000_000),
+        route(
+            &[(&luna_pair, &uusd, &uluna), (&atom_pair, &uluna, &uatom)],
+            1_000_000,
+        ),
+    ];
+    let err = suite
+        .execute(
+            &user,
+            &multi_output_msg(routes, &[(&uluna, 0), (&uatom, 0)]),
+            &coins(2_000_000, "uusd"),
+        )
+        .unwrap_err();
+    assert_eq!(
+        error_message(err),
+        ContractError::InvalidRoute {}.to_string()
+    );
+}
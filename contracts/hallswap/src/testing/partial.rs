@@ -0,0 +1,47 @@
+use cosmwasm_std::{coins, Addr, Event};
+
+use super::{fee, native, route, swap_msg_with, Suite, SwapOptions};
+use crate::msg::Referral;
+
+#[test]
+fn partial_swap_refunds_failed_routes_with_their_fees() {
+    let mut suite = Suite::with_fee(30, &["uluna"]);
+    let [uatom, uluna, uusd] = ["uatom", "uluna", "uusd"].map(native);
+    let luna_pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    // Does not hold uluna, so the second route fails
+    let atom_pool = suite.create_pair([(&uatom, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let (user, frontend) = (suite.user.clone(), Addr::unchecked("frontend"));
+    suite.fund(&user, &uluna, 3_000_000);
+
+    let (fee_amount, referral_fee_amount) = (fee(1_000_000, 30), fee(1_000_000, 50));
+    let expected = suite.pool_return(&[route(
+        &[(&luna_pool, &uluna, &uusd)],
+        1_000_000 - fee_amount - referral_fee_amount,
+    )]);
+    let msg = swap_msg_with(
+        vec![
+            route(&[(&luna_pool, &uluna, &uusd)], 1_000_000),
+            route(&[(&atom_pool, &uluna, &uusd)], 2_000_000),
+        ],
+        expected,
+        SwapOptions {
+            allow_partial: Some(true),
+            referral: Some(Referral {
+                address: frontend.clone(),
+                fee_bps: 50,
+            }),
+            ..SwapOptions::default()
+        },
+    );
+    let response = suite
+        .execute(&user, &msg, &coins(3_000_000, "uluna"))
+        .unwrap();
+    assert!(response.has_event(&Event::new("wasm").add_attribute("failed_route", "1")));
+
+    // The failed route is refunded in full, only the route that succeeded pays its fees
+    assert_eq!(suite.balance(&user, &uluna), 2_000_000);
+    assert_eq!(suite.balance(&user, &uusd), expected);
+    assert_eq!(suite.accrued_fee(&uluna), fee_amount);
+    assert_eq!(suite.balance(&frontend, &uluna), referral_fee_amount);
+    assert_eq!(suite.balance(&suite.router, &uluna), fee_amount);
+}
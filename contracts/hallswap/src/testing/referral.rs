@@ -0,0 +1,87 @@
+use cosmwasm_std::{coins, Addr};
+
+use super::{error_message, fee, native, route, swap_msg_with, Suite, SwapOptions};
+use crate::msg::{ExecuteMsg, Referral, RouteInfoV2};
+
+fn referral_swap_msg(routes: Vec<RouteInfoV2>, minimum_receive: u128, fee_bps: u16) -> ExecuteMsg {
+    let referral = Referral {
+        address: Addr::unchecked("frontend"),
+        fee_bps,
+    };
+    swap_msg_with(
+        routes,
+        minimum_receive,
+        SwapOptions {
+            referral: Some(referral),
+            ..SwapOptions::default()
+        },
+    )
+}
+
+#[test]
+fn referral_is_paid_on_the_offer_asset_alongside_the_fee() {
+    let mut suite = Suite::with_fee(30, &["uluna"]);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uluna, 1_000_000);
+    let routes = vec![route(&[(&pool, &uluna, &uusd)], 1_000_000)];
+
+    let err = suite
+        .execute(
+            &user,
+            &referral_swap_msg(routes.clone(), 0, 101),
+            &coins(1_000_000, "uluna"),
+        )
+        .unwrap_err();
+    assert_eq!(
+        error_message(err),
+        "Referral fee of 101 bps is above the maximum of 100 bps"
+    );
+
+    let (fee_amount, referral_fee_amount) = (fee(1_000_000, 30), fee(1_000_000, 50));
+    let expected = suite.pool_return(&[route(
+        &[(&pool, &uluna, &uusd)],
+        1_000_000 - fee_amount - referral_fee_amount,
+    )]);
+    suite
+        .execute(
+            &user,
+            &referral_swap_msg(routes, expected, 50),
+            &coins(1_000_000, "uluna"),
+        )
+        .unwrap();
+    assert_eq!(suite.balance(&user, &uusd), expected);
+    assert_eq!(suite.accrued_fee(&uluna), fee_amount);
+    assert_eq!(
+        suite.balance(&Addr::unchecked("frontend"), &uluna),
+        referral_fee_amount
+    );
+}
+
+#[test]
+fn referral_is_paid_on_the_return_asset_alongside_the_fee() {
+    let mut suite = Suite::with_fee(30, &[]);
+    let [uluna, uusd] = ["uluna", "uusd"].map(native);
+    let pool = suite.create_pair([(&uluna, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uluna, 1_000_000);
+    let routes = vec![route(&[(&pool, &uluna, &uusd)], 1_000_000)];
+
+    let return_amount = suite.pool_return(&routes);
+    let (fee_amount, referral_fee_amount) = (fee(return_amount, 30), fee(return_amount, 50));
+    let expected = return_amount - fee_amount - referral_fee_amount;
+    suite
+        .execute(
+            &user,
+            &referral_swap_msg(routes, expected, 50),
+            &coins(1_000_000, "uluna"),
+        )
+        .unwrap();
+    assert_eq!(suite.balance(&user, &uusd), expected);
+    assert_eq!(suite.accrued_fee(&uusd), fee_amount);
+    assert_eq!(
+        suite.balance(&Addr::unchecked("frontend"), &uusd),
+        referral_fee_amount
+    );
+}
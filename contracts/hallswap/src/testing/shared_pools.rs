@@ -0,0 +1,182 @@
+use cosmwasm_std::{coins, Addr};
+use querier::msg::AstroportPairType;
+
+use super::{native, route, swap_msg, StableParams, Suite};
+use crate::msg::{QueryMsg, QuerySimulationResult, RouteInfoV2};
+
+/// Simulates `routes`, then funds the user with `funds` and swaps them through `routes` with the
+/// simulated return as minimum. Returns the simulation and the amount of `return_denom` received.
+fn simulate_and_swap(
+    suite: &mut Suite,
+    routes: Vec<RouteInfoV2>,
+    funds: &[(&str, u128)],
+    return_denom: &str,
+) -> (QuerySimulationResult, u128) {
+    let user = suite.user.clone();
+    let simulation: QuerySimulationResult = suite.query(&QueryMsg::Simulation {
+        routes: routes.clone(),
+        referral: None,
+        sender: None,
+    });
+    let mut sent = vec![];
+    for &(denom, amount) in funds {
+        suite.fund(&user, &native(denom), amount);
+        sent.extend(coins(amount, denom));
+    }
+    sent.sort_by(|a, b| a.denom.cmp(&b.denom));
+    suite
+        .execute(
+            &user,
+            &swap_msg(routes, simulation.return_asset.amount.u128()),
+            &sent,
+        )
+        .unwrap();
+    let received = suite.balance(&user, &native(return_denom));
+    (simulation, received)
+}
+
+/// Two routes swapping uusd for uluna on the same pool
+fn same_direction_routes(pool: &Addr) -> Vec<RouteInfoV2> {
+    let (uusd, uluna) = (native("uusd"), native("uluna"));
+    vec![
+        route(&[(pool, &uusd, &uluna)], 100_000_000),
+        route(&[(pool, &uusd, &uluna)], 200_000_000),
+    ]
+}
+
+#[test]
+fn shared_xyk_pool_is_quoted_against_updated_reserves() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let (uusd, uluna) = (native("uusd"), native("uluna"));
+    let pool = suite.create_pair([(&uusd, 1_000_000_000), (&uluna, 2_000_000_000)]);
+    let routes = same_direction_routes(&pool);
+    let naive_return = suite.simulate(&routes[..1]) + suite.simulate(&routes[1..]);
+
+    let (simulation, received) =
+        simulate_and_swap(&mut suite, routes, &[("uusd", 300_000_000)], "uluna");
+    let simulated = simulation.return_asset.amount.u128();
+    assert!(simulated < naive_return);
+    assert!(simulated <= received);
+    assert!(received - simulated <= 2);
+    assert_eq!(simulation.adjusted_hops.len(), 1);
+}
+
+#[test]
+fn shared_stable_pool_is_quoted_with_its_amp_and_precisions() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let (uusd, uluna) = (native("uusd"), native("uluna"));
+    let pool = suite.create_typed_pair(
+        [
+            (&uusd, 1_000_000_000),
+            (&uluna, 1_000_000_000_000_000_000_000),
+        ],
+        AstroportPairType::Stable {},
+        Some(StableParams {
+            amp: 50,
+            precisions: vec![6, 18],
+        }),
+    );
+    let routes = same_direction_routes(&pool);
+    let naive_return = suite.simulate(&routes[..1]) + suite.simulate(&routes[1..]);
+
+    let (simulation, received) =
+        simulate_and_swap(&mut suite, routes, &[("uusd", 300_000_000)], "uluna");
+    let simulated = simulation.return_asset.amount.u128();
+    assert!(simulated < naive_return);
+    assert!(simulated <= received);
+    // Within the rounding of the invariant, at 18 decimals
+    assert!(received - simulated <= 1_000_000_000_000);
+}
+
+#[test]
+fn shared_custom_pool_falls_back_to_pool_simulations() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let (uusd, uluna) = (native("uusd"), native("uluna"));
+    let pool = suite.create_typed_pair(
+        [(&uusd, 1_000_000_000), (&uluna, 2_000_000_000)],
+        AstroportPairType::Custom("concentrated".to_string()),
+        None,
+    );
+    let routes = same_direction_routes(&pool);
+    let naive_return = suite.simulate(&routes[..1]) + suite.simulate(&routes[1..]);
+
+    let (simulation, received) =
+        simulate_and_swap(&mut suite, routes, &[("uusd", 300_000_000)], "uluna");
+    let simulated = simulation.return_asset.amount.u128();
+    assert!(simulated < naive_return);
+    assert!(simulated <= received);
+    // Only the commission the first hop left in the pool is missed
+    assert!(received - simulated <= simulated / 1_000);
+}
+
+/// Two routes through a shared pool offered uusd by the first one and uluna by the second one
+fn reverse_direction_routes(suite: &mut Suite, pair_type: AstroportPairType) -> Vec<RouteInfoV2> {
+    let [ueur, ugbp, uusd, uluna, uatom] = ["ueur", "ugbp", "uusd", "uluna", "uatom"].map(native);
+    let shared = suite.create_typed_pair(
+        [(&uusd, 1_000_000_000), (&uluna, 1_000_000_000)],
+        pair_type,
+        None,
+    );
+    let eur_pool = suite.create_pair([(&ueur, 1_000_000_000), (&uusd, 1_000_000_000)]);
+    let gbp_pool = suite.create_pair([(&ugbp, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let usd_pool = suite.create_pair([(&uusd, 1_000_000_000), (&uatom, 1_000_000_000)]);
+    let luna_pool = suite.create_pair([(&uluna, 1_000_000_000), (&uatom, 1_000_000_000)]);
+    vec![
+        route(
+            &[
+                (&eur_pool, &ueur, &uusd),
+                (&shared, &uusd, &uluna),
+                (&luna_pool, &uluna, &uatom),
+            ],
+            100_000_000,
+        ),
+        route(
+            &[
+                (&gbp_pool, &ugbp, &uluna),
+                (&shared, &uluna, &uusd),
+                (&usd_pool, &uusd, &uatom),
+            ],
+            50_000_000,
+        ),
+    ]
+}
+
+#[test]
+fn shared_xyk_pool_quotes_reverse_direction_hops() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let routes = reverse_direction_routes(&mut suite, AstroportPairType::Xyk {});
+    let naive_return = suite.simulate(&routes[..1]) + suite.simulate(&routes[1..]);
+
+    let (simulation, received) = simulate_and_swap(
+        &mut suite,
+        routes,
+        &[("ueur", 100_000_000), ("ugbp", 50_000_000)],
+        "uatom",
+    );
+    let simulated = simulation.return_asset.amount.u128();
+    // The first route made uluna scarcer in the shared pool, so the second one gets more uusd
+    assert!(simulated > naive_return);
+    assert!(simulated <= received);
+    assert!(received - simulated <= 2);
+}
+
+#[test]
+fn shared_custom_pool_keeps_naive_quote_for_reverse_direction_hops() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let routes = reverse_direction_routes(
+        &mut suite,
+        AstroportPairType::Custom("concentrated".to_string()),
+    );
+    let naive_return = suite.simulate(&routes[..1]) + suite.simulate(&routes[1..]);
+
+    let (simulation, received) = simulate_and_swap(
+        &mut suite,
+        routes,
+        &[("ueur", 100_000_000), ("ugbp", 50_000_000)],
+        "uatom",
+    );
+    let simulated = simulation.return_asset.amount.u128();
+    // A lower bound, as the pool pays more uusd for the uluna it was drained of
+    assert_eq!(simulated, naive_return);
+    assert!(simulated < received);
+}
@@ -0,0 +1,169 @@
+use cosmwasm_std::{coins, Addr, Binary, StdResult, Uint128};
+use models::asset_info::AssetInfo;
+
+use super::{error_message, native, route, swap_msg, Suite};
+use crate::msg::{Interface, QueryMsg, QuerySimulationResult, RouteInfoV2};
+use crate::ContractError;
+
+/// Router with a uusd/uluna pool to start routes from
+fn simulation_suite() -> (Suite, [AssetInfo; 3], Addr) {
+    let mut suite = Suite::with_fee(0, &[]);
+    let assets = ["uusd", "uluna", "uatom"].map(native);
+    let luna_pool = suite.create_pair([(&assets[0], 1_000_000_000), (&assets[1], 1_000_000_000)]);
+    (suite, assets, luna_pool)
+}
+
+/// Returns a valid uusd -> uatom route followed by one failing at its second hop, `failing_hop`
+fn routes_failing_at(
+    suite: &mut Suite,
+    luna_pool: &Addr,
+    failing_hop: (&Addr, &AssetInfo, &AssetInfo),
+) -> Vec<RouteInfoV2> {
+    let (uusd, uluna, uatom) = (native("uusd"), native("uluna"), native("uatom"));
+    let atom_pool = suite.create_pair([(&uusd, 1_000_000_000), (&uatom, 1_000_000_000)]);
+    vec![
+        route(&[(&atom_pool, &uusd, &uatom)], 1_000),
+        route(&[(luna_pool, &uusd, &uluna), failing_hop], 1_000),
+    ]
+}
+
+fn try_simulate(suite: &Suite, routes: Vec<RouteInfoV2>) -> StdResult<QuerySimulationResult> {
+    suite.app.wrap().query_wasm_smart(
+        suite.router.clone(),
+        &QueryMsg::Simulation {
+            routes,
+            referral: None,
+            sender: None,
+        },
+    )
+}
+
+#[test]
+fn simulation_fails_on_a_failing_pool() {
+    let (mut suite, [uusd, uluna, uatom], luna_pool) = simulation_suite();
+    // Holds none of its assets, so its own simulation fails
+    let empty_pool = suite.create_pair([(&uluna, 0), (&uatom, 0)]);
+
+    let err = try_simulate(
+        &suite,
+        vec![route(
+            &[(&luna_pool, &uusd, &uluna), (&empty_pool, &uluna, &uatom)],
+            1_000_000,
+        )],
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string().contains(&format!(
+            "Simulation failed; route: 0, hop: 1, reason: Simulation query to {empty_pool} \
+             failed: "
+        )),
+        "{err}"
+    );
+}
+
+#[test]
+fn simulation_fails_on_a_zero_output() {
+    let (mut suite, [uusd, uluna, uatom], luna_pool) = simulation_suite();
+    let shallow_pool = suite.create_pair([(&uluna, 1_000_000_000_000), (&uatom, 1)]);
+
+    let err = try_simulate(
+        &suite,
+        vec![route(
+            &[(&luna_pool, &uusd, &uluna), (&shallow_pool, &uluna, &uatom)],
+            1_000,
+        )],
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string().contains(
+            &ContractError::ZeroSimulationOutput {
+                route_index: 0,
+                hop_index: 1,
+                pool: shallow_pool,
+            }
+            .to_string()
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn simulation_locates_a_hop_on_a_contract_that_is_not_a_pair() {
+    let (mut suite, [_, uluna, uatom], luna_pool) = simulation_suite();
+    let token = suite.create_token();
+    let AssetInfo::Token {
+        contract_addr: not_a_pair,
+    } = &token
+    else {
+        unreachable!()
+    };
+    let routes = routes_failing_at(&mut suite, &luna_pool, (not_a_pair, &uluna, &uatom));
+
+    let err = try_simulate(&suite, routes).unwrap_err();
+    assert!(
+        err.to_string().contains(&format!(
+            "Pair not found; route: 1, hop: 1, pool: {not_a_pair}, reason: "
+        )),
+        "{err}"
+    );
+}
+
+#[test]
+fn simulation_locates_a_hop_on_a_pair_without_its_asset() {
+    let (mut suite, [_, uluna, uatom], luna_pool) = simulation_suite();
+    let routes = routes_failing_at(&mut suite, &luna_pool, (&luna_pool, &uluna, &uatom));
+
+    let err = try_simulate(&suite, routes).unwrap_err();
+    assert!(
+        err.to_string().contains(
+            &ContractError::AssetNotInPair {
+                route_index: 1,
+                hop_index: 1,
+                pool: luna_pool,
+                asset: uatom.id(),
+            }
+            .to_string()
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn swap_locates_a_hop_with_an_undecodable_interface() {
+    let (mut suite, [uusd, uluna, uatom], luna_pool) = simulation_suite();
+    let atom_pool = suite.create_pair([(&uluna, 1_000_000_000), (&uatom, 1_000_000_000)]);
+    let mut routes = routes_failing_at(&mut suite, &luna_pool, (&atom_pool, &uluna, &uatom));
+    routes[1].route[1].interface = Some(Interface::Binary(Binary::from(b"not json")));
+    let user = suite.user.clone();
+    suite.fund(&user, &uusd, 2_000);
+
+    let err = suite
+        .execute(&user, &swap_msg(routes, 0), &coins(2_000, "uusd"))
+        .unwrap_err();
+    assert!(error_message(err).starts_with(&format!(
+        "Invalid interface; route: 1, hop: 1, pool: {atom_pool}, reason: "
+    )));
+}
+
+#[test]
+fn simulation_reports_an_overflowing_fee() {
+    let mut suite = Suite::with_fee(30, &["uusd"]);
+    let (uusd, uluna) = (native("uusd"), native("uluna"));
+    let luna_pool = suite.create_pair([(&uusd, 1_000_000_000), (&uluna, 1_000_000_000)]);
+
+    let err = try_simulate(
+        &suite,
+        vec![route(&[(&luna_pool, &uusd, &uluna)], u128::MAX)],
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string().contains(
+            &ContractError::FeeOverflow {
+                amount: Uint128::MAX,
+                fee_bps: 30,
+            }
+            .to_string()
+        ),
+        "{err}"
+    );
+}
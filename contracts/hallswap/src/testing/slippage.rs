@@ -0,0 +1,125 @@
+use cosmwasm_std::{coins, Addr, Uint128};
+use cw_multi_test::Executor;
+
+use super::{error_message, mock_pair, native, route, swap_msg_with, Suite, SwapOptions};
+use crate::msg::{ExecuteMsg, RouteInfoV2};
+use crate::ContractError;
+
+/// Router with a uusd/uluna pool and a user holding uusd to offer
+fn slippage_suite() -> (Suite, Addr, RouteInfoV2) {
+    let mut suite = Suite::with_fee(0, &[]);
+    let (uusd, uluna) = (native("uusd"), native("uluna"));
+    let pool = suite.create_pair([(&uusd, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uusd, 100_000_000);
+    let route = route(&[(&pool, &uusd, &uluna)], 1_000_000);
+    (suite, pool, route)
+}
+
+fn swap(
+    suite: &mut Suite,
+    route: &RouteInfoV2,
+    minimum_receive: u128,
+    max_slippage_bps: Option<u16>,
+    reference_quote: Option<u128>,
+) -> anyhow::Result<()> {
+    let user = suite.user.clone();
+    let msg: ExecuteMsg = swap_msg_with(
+        vec![route.clone()],
+        minimum_receive,
+        SwapOptions {
+            max_slippage_bps,
+            reference_quote,
+            ..SwapOptions::default()
+        },
+    );
+    suite
+        .execute(&user, &msg, &coins(route.offer_amount.u128(), "uusd"))
+        .map(|_| ())
+}
+
+fn below_minimum(receive: u128, amount: u128) -> String {
+    ContractError::AssertionMinimumReceive {
+        receive: Uint128::new(receive),
+        amount: Uint128::new(amount),
+    }
+    .to_string()
+}
+
+/// Returns `amount` minus `bps`, rounded down as the router does
+fn minus_bps(amount: u128, bps: u128) -> u128 {
+    amount * (10000 - bps) / 10000
+}
+
+#[test]
+fn slippage_raises_the_minimum_to_the_simulated_return() {
+    let (mut suite, pool, route) = slippage_suite();
+    let owner = suite.owner.clone();
+    suite
+        .app
+        .execute_contract(
+            owner,
+            pool,
+            &mock_pair::ExecuteMsg::SetSwapShortfall { bps: 200 },
+            &[],
+        )
+        .unwrap();
+    let simulated = suite.simulate(std::slice::from_ref(&route));
+    let swapped = minus_bps(simulated, 200);
+
+    let err = swap(&mut suite, &route, 0, Some(100), None).unwrap_err();
+    assert_eq!(
+        error_message(err),
+        below_minimum(minus_bps(simulated, 100), swapped)
+    );
+    swap(&mut suite, &route, 0, Some(300), None).unwrap();
+    assert_eq!(suite.balance(&suite.user, &native("uluna")), swapped);
+}
+
+#[test]
+fn reference_quote_caps_the_drift_since_quoting() {
+    let (mut suite, _, route) = slippage_suite();
+    let quote = suite.simulate(std::slice::from_ref(&route));
+    // Another swap moves the price before the user's lands
+    let mut large_route = route.clone();
+    large_route.offer_amount = Uint128::new(50_000_000);
+    swap(&mut suite, &large_route, 0, None, None).unwrap();
+    let simulated = suite.simulate(std::slice::from_ref(&route));
+    assert!(minus_bps(quote, 100) > simulated);
+
+    let err = swap(&mut suite, &route, 0, Some(100), Some(quote)).unwrap_err();
+    assert_eq!(
+        error_message(err),
+        below_minimum(minus_bps(quote, 100), simulated)
+    );
+    // The simulation at execution alone only guards against what moves within the block
+    swap(&mut suite, &route, 0, Some(100), None).unwrap();
+}
+
+#[test]
+fn slippage_keeps_the_highest_minimum_receive() {
+    let (mut suite, _, route) = slippage_suite();
+    let simulated = suite.simulate(std::slice::from_ref(&route));
+
+    // An explicit minimum above the derived ones still applies
+    let err = swap(
+        &mut suite,
+        &route,
+        simulated + 1,
+        Some(100),
+        Some(simulated),
+    )
+    .unwrap_err();
+    assert_eq!(error_message(err), below_minimum(simulated + 1, simulated));
+    // And a lower one gives way to the reference quote
+    let err = swap(&mut suite, &route, 1, Some(100), Some(2 * simulated)).unwrap_err();
+    assert_eq!(
+        error_message(err),
+        below_minimum(minus_bps(2 * simulated, 100), simulated)
+    );
+    let err = swap(&mut suite, &route, 0, Some(10001), None).unwrap_err();
+    assert_eq!(error_message(err), "Invalid slippage tolerance: 10001 bps");
+
+    swap(&mut suite, &route, 1, Some(100), Some(simulated)).unwrap();
+    assert_eq!(suite.balance(&suite.user, &native("uluna")), simulated);
+}
@@ -0,0 +1,154 @@
+use cosmwasm_std::{coins, Addr, Uint128};
+use models::asset_info::AssetInfo;
+
+use super::{error_message, native, route, Suite};
+use crate::msg::{ExecuteMsg, QueryMsg, QuerySimulationResult, RouteEdge, RouteInfoV3};
+
+fn edge(from: u32, to: u32, fraction: &str, pool: &Addr) -> RouteEdge {
+    RouteEdge {
+        from,
+        to,
+        fraction: fraction.parse().unwrap(),
+        contract_addr: pool.clone(),
+        interface: None,
+    }
+}
+
+fn route_v3(nodes: &[&AssetInfo], edges: Vec<RouteEdge>, offer_amount: u128) -> RouteInfoV3 {
+    RouteInfoV3 {
+        nodes: nodes.iter().map(|node| (*node).clone()).collect(),
+        edges,
+        offer_amount: Uint128::new(offer_amount),
+    }
+}
+
+fn execute_v3(suite: &mut Suite, route: RouteInfoV3, minimum_receive: u128) -> anyhow::Result<()> {
+    let user = suite.user.clone();
+    let funds = coins(route.offer_amount.u128(), "uusd");
+    suite
+        .execute(
+            &user,
+            &ExecuteMsg::ExecuteRoutesV3 {
+                route,
+                minimum_receive: Uint128::new(minimum_receive),
+                to: None,
+                deadline: None,
+                minimum_receive_mode: None,
+            },
+            &funds,
+        )
+        .map(|_| ())
+}
+
+/// uusd -> uluna -> uatom -> uosmo, with two uluna/uatom pools
+fn v3_suite() -> (Suite, [AssetInfo; 4], [Addr; 4]) {
+    let mut suite = Suite::with_fee(0, &[]);
+    let assets = ["uusd", "uluna", "uatom", "uosmo"].map(native);
+    let [uusd, uluna, uatom, uosmo] = &assets;
+    let pools = [
+        suite.create_pair([(uusd, 1_000_000_000), (uluna, 2_000_000_000)]),
+        suite.create_pair([(uluna, 1_000_000_000), (uatom, 1_000_000_000)]),
+        suite.create_pair([(uluna, 500_000_000), (uatom, 700_000_000)]),
+        suite.create_pair([(uatom, 3_000_000_000), (uosmo, 1_000_000_000)]),
+    ];
+    let user = suite.user.clone();
+    suite.fund(&user, uusd, 10_000_000);
+    (suite, assets, pools)
+}
+
+#[test]
+fn v3_rejects_invalid_graphs() {
+    let (mut suite, [uusd, uluna, uatom, uosmo], [usd_luna, luna_atom, _, atom_osmo]) = v3_suite();
+    let cases = [
+        (
+            route_v3(
+                &[&uusd, &uluna, &uatom, &uosmo],
+                vec![
+                    edge(0, 1, "1", &usd_luna),
+                    edge(1, 2, "1", &luna_atom),
+                    edge(2, 1, "0.5", &luna_atom),
+                    edge(2, 3, "0.5", &atom_osmo),
+                ],
+                1_000_000,
+            ),
+            "the route graph contains a cycle",
+        ),
+        (
+            route_v3(
+                &[&uusd, &uluna, &uatom],
+                vec![edge(0, 1, "1", &usd_luna)],
+                1_000_000,
+            ),
+            "node 2 is disconnected from the offer node",
+        ),
+        (
+            route_v3(
+                &[&uusd, &uluna, &uatom],
+                vec![edge(0, 1, "0.5", &usd_luna), edge(0, 2, "0.5", &usd_luna)],
+                1_000_000,
+            ),
+            "a route must end at exactly one return node",
+        ),
+        (
+            route_v3(
+                &[&uusd, &uluna, &uatom],
+                vec![
+                    edge(0, 1, "0.6", &usd_luna),
+                    edge(0, 2, "0.3", &usd_luna),
+                    edge(1, 2, "1", &luna_atom),
+                ],
+                1_000_000,
+            ),
+            "outgoing fractions of node 0 must sum to one",
+        ),
+    ];
+    for (route, reason) in cases {
+        let err = execute_v3(&mut suite, route, 0).unwrap_err();
+        assert_eq!(error_message(err), format!("Invalid route graph: {reason}"));
+    }
+    assert_eq!(suite.balance(&suite.user, &uusd), 10_000_000);
+}
+
+#[test]
+fn v3_executes_the_simulated_split() {
+    let (mut suite, [uusd, uluna, uatom, uosmo], [usd_luna, luna_atom, luna_atom_2, atom_osmo]) =
+        v3_suite();
+    // Uneven fractions on an odd amount leave a remainder on the second edge
+    let split_route = route_v3(
+        &[&uusd, &uluna, &uatom, &uosmo],
+        vec![
+            edge(0, 1, "1", &usd_luna),
+            edge(1, 2, "0.3", &luna_atom),
+            edge(1, 2, "0.7", &luna_atom_2),
+            edge(2, 3, "1", &atom_osmo),
+        ],
+        1_000_001,
+    );
+    let simulation: QuerySimulationResult = suite.query(&QueryMsg::SimulationV3 {
+        route: split_route.clone(),
+        sender: None,
+    });
+    let simulated = simulation.return_asset.amount.u128();
+    assert_eq!(simulation.return_asset.info, uosmo);
+
+    // The first edge of the split gets its floored share and the last one the rest
+    let luna = suite.pool_return(&[route(&[(&usd_luna, &uusd, &uluna)], 1_000_001)]);
+    let first_share = luna * 3 / 10;
+    let atom = suite.pool_return(&[
+        route(&[(&luna_atom, &uluna, &uatom)], first_share),
+        route(&[(&luna_atom_2, &uluna, &uatom)], luna - first_share),
+    ]);
+    let osmo = suite.pool_return(&[route(&[(&atom_osmo, &uatom, &uosmo)], atom)]);
+    assert_eq!(simulated, osmo);
+
+    let err = execute_v3(&mut suite, split_route.clone(), simulated + 1).unwrap_err();
+    assert!(error_message(err).starts_with("Assertion failed; minimum receive amount"));
+    execute_v3(&mut suite, split_route, simulated).unwrap();
+
+    let user = suite.user.clone();
+    assert_eq!(suite.balance(&user, &uosmo), simulated);
+    assert_eq!(suite.balance(&user, &uusd), 10_000_000 - 1_000_001);
+    for intermediate in [&uluna, &uatom] {
+        assert_eq!(suite.balance(&suite.router, intermediate), 0);
+    }
+}
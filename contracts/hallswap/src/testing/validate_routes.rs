@@ -0,0 +1,111 @@
+use cosmwasm_std::coins;
+
+use super::{error_message, native, route, swap_msg, Suite};
+use crate::msg::{QueryMsg, RouteDiagnostic, RouteInfoV2, RouteIssue, ValidateRoutesResponse};
+
+fn validate(suite: &Suite, routes: &[RouteInfoV2]) -> ValidateRoutesResponse {
+    suite.query(&QueryMsg::ValidateRoutes {
+        routes: routes.to_vec(),
+    })
+}
+
+fn diagnostic(route_index: u32, hop_index: u32, issue: RouteIssue) -> RouteDiagnostic {
+    RouteDiagnostic {
+        route_index: Some(route_index),
+        hop_index: Some(hop_index),
+        issue,
+    }
+}
+
+#[test]
+fn validate_routes_reports_every_issue_per_hop() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let [uusd, uluna, uatom] = ["uusd", "uluna", "uatom"].map(native);
+    let luna_pool = suite.create_pair([(&uusd, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let atom_pool = suite.create_pair([(&uluna, 1_000_000_000), (&uatom, 1_000_000_000)]);
+    let usd_atom_pool = suite.create_pair([(&uusd, 1_000_000_000), (&uatom, 1_000_000_000)]);
+
+    let valid_route = route(
+        &[(&luna_pool, &uusd, &uluna), (&atom_pool, &uluna, &uatom)],
+        1_000,
+    );
+    let response = validate(&suite, std::slice::from_ref(&valid_route));
+    assert!(response.valid);
+    assert_eq!(response.errors, vec![]);
+
+    let routes = vec![
+        valid_route,
+        // Swaps on a pool without its return asset, then continues from the wrong asset
+        route(
+            &[(&luna_pool, &uusd, &uatom), (&atom_pool, &uluna, &uatom)],
+            1_000,
+        ),
+        // Swaps the return asset away on its second hop
+        route(
+            &[
+                (&usd_atom_pool, &uusd, &uatom),
+                (&atom_pool, &uatom, &uluna),
+                (&atom_pool, &uluna, &uatom),
+            ],
+            1_000,
+        ),
+    ];
+    let response = validate(&suite, &routes);
+    assert!(!response.valid);
+    assert_eq!(
+        response.errors,
+        vec![
+            diagnostic(
+                1,
+                0,
+                RouteIssue::ReturnAssetNotInPair {
+                    pool: luna_pool,
+                    asset: uatom.id(),
+                },
+            ),
+            diagnostic(
+                1,
+                1,
+                RouteIssue::Disconnected {
+                    expected: uatom.id(),
+                    found: uluna.id(),
+                },
+            ),
+            diagnostic(2, 1, RouteIssue::OffersSweptAsset { asset: uatom.id() }),
+        ]
+    );
+}
+
+#[test]
+fn validate_routes_reports_what_execution_rejects() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let [uusd, uluna] = ["uusd", "uluna"].map(native);
+    let luna_pool = suite.create_pair([(&uusd, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let user = suite.user.clone();
+    suite.fund(&user, &uusd, 2_000);
+
+    // The second route swaps away the uluna the routes return, then the uusd they offer
+    let routes = vec![
+        route(&[(&luna_pool, &uusd, &uluna)], 1_000),
+        route(
+            &[
+                (&luna_pool, &uusd, &uluna),
+                (&luna_pool, &uluna, &uusd),
+                (&luna_pool, &uusd, &uluna),
+            ],
+            1_000,
+        ),
+    ];
+    let response = validate(&suite, &routes);
+    assert_eq!(
+        response.errors,
+        vec![
+            diagnostic(1, 1, RouteIssue::OffersSweptAsset { asset: uluna.id() }),
+            diagnostic(1, 2, RouteIssue::OffersSweptAsset { asset: uusd.id() }),
+        ]
+    );
+    let err = suite
+        .execute(&user, &swap_msg(routes, 0), &coins(2_000, "uusd"))
+        .unwrap_err();
+    assert_eq!(error_message(err), "Invalid route");
+}
@@ -0,0 +1,86 @@
+use cosmwasm_std::{Coin, Uint128};
+
+use super::{error_message, native, route, swap_msg_with, Suite, SwapOptions};
+use crate::msg::{ExecuteMsg, QueryMsg, SwapOperation, UserTierResponse};
+use crate::state::{VolumeTier, VolumeTiers};
+
+fn volume_tiers(quote_asset: &str, price_routes: Vec<Vec<SwapOperation>>) -> VolumeTiers {
+    VolumeTiers {
+        quote_asset: native(quote_asset),
+        window_seconds: 86_400,
+        bucket_seconds: 3_600,
+        tiers: vec![VolumeTier {
+            min_volume: Uint128::new(1_000_000_000),
+            fee_bps: 10,
+        }],
+        price_routes: Some(price_routes),
+    }
+}
+
+fn user_volume(suite: &Suite) -> u128 {
+    let response: Option<UserTierResponse> = suite.query(&QueryMsg::UserTier {
+        address: suite.user.clone(),
+    });
+    response.unwrap().volume.u128()
+}
+
+#[test]
+fn routes_without_the_quote_asset_are_valued_through_price_routes() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let [uatom, uluna, uosmo, uusd] = ["uatom", "uluna", "uosmo", "uusd"].map(native);
+    let atom_luna_pool = suite.create_pair([(&uatom, 1_000_000_000), (&uluna, 3_000_000_000)]);
+    let osmo_luna_pool = suite.create_pair([(&uosmo, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let atom_usd_pool = suite.create_pair([(&uatom, 1_000_000_000), (&uusd, 2_000_000_000)]);
+    let price_route = route(&[(&atom_usd_pool, &uatom, &uusd)], 1_000_000);
+    let owner = suite.owner.clone();
+    suite
+        .execute(
+            &owner,
+            &ExecuteMsg::SetVolumeTiers {
+                volume_tiers: Some(volume_tiers("uusd", vec![price_route.route.clone()])),
+            },
+            &[],
+        )
+        .unwrap();
+    let user = suite.user.clone();
+    suite.fund(&user, &uatom, 1_000_000);
+    suite.fund(&user, &uosmo, 1_000_000);
+
+    // Neither route involves uusd, only uatom has a price route
+    let routes = vec![
+        route(&[(&atom_luna_pool, &uatom, &uluna)], 1_000_000),
+        route(&[(&osmo_luna_pool, &uosmo, &uluna)], 1_000_000),
+    ];
+    let msg = swap_msg_with(
+        routes,
+        0,
+        SwapOptions {
+            max_slippage_bps: Some(100),
+            ..SwapOptions::default()
+        },
+    );
+    let funds = [Coin::new(1_000_000, "uatom"), Coin::new(1_000_000, "uosmo")];
+    suite.execute(&user, &msg, &funds).unwrap();
+    assert_eq!(user_volume(&suite), suite.pool_return(&[price_route]));
+}
+
+#[test]
+fn set_volume_tiers_rejects_price_routes_not_ending_at_the_quote_asset() {
+    let mut suite = Suite::with_fee(0, &[]);
+    let [uatom, uluna] = ["uatom", "uluna"].map(native);
+    let pool = suite.create_pair([(&uatom, 1_000_000_000), (&uluna, 1_000_000_000)]);
+    let owner = suite.owner.clone();
+    let err = suite
+        .execute(
+            &owner,
+            &ExecuteMsg::SetVolumeTiers {
+                volume_tiers: Some(volume_tiers(
+                    "uusd",
+                    vec![route(&[(&pool, &uatom, &uluna)], 0).route],
+                )),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(error_message(err), "Invalid route");
+}
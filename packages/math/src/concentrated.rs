@@ -0,0 +1,578 @@
+use cosmwasm_std::{Decimal, Uint128, Uint256, Uint512};
+
+use crate::error::MathError;
+
+/// Lowest and highest ticks of pools priced at `1.0001^tick`
+pub const MIN_TICK: i32 = -887_272;
+pub const MAX_TICK: i32 = 887_272;
+
+/// Fractional bits of the square root prices, like Uniswap V3 `sqrtPriceX96`
+const RESOLUTION: u32 = 96;
+/// Fractional bits of the fee growth per unit of liquidity
+const FEE_GROWTH_RESOLUTION: u32 = 128;
+
+/// `2^128 / sqrt(1.0001)^(2^i)` for every bit `i` of a tick, from Uniswap V3 `TickMath`
+const TICK_RATIOS: [u128; 20] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+
+/// Initialized tick of a pool snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TickInfo {
+    pub index: i32,
+    /// Square root price at the tick, with 96 fractional bits
+    pub sqrt_price: Uint256,
+    /// Liquidity added when the price crosses the tick upwards, removed when downwards
+    pub liquidity_net: i128,
+}
+
+/// State of a concentrated liquidity pool, in terms of its first (`0`) and second (`1`) asset
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolSnapshot {
+    /// Square root of the price of asset 0 in asset 1, with 96 fractional bits
+    pub sqrt_price: Uint256,
+    /// Tick containing the current price
+    pub tick: i32,
+    /// Liquidity in range at the current price
+    pub liquidity: Uint128,
+    /// Initialized ticks sorted by index. Ticks far from the current price may be left out,
+    /// in which case swaps reaching them fail with `InsufficientLiquidity`.
+    pub ticks: Vec<TickInfo>,
+}
+
+/// Result of swapping within a single price range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next: Uint256,
+    pub amount_in: Uint256,
+    pub amount_out: Uint256,
+    pub fee_amount: Uint256,
+}
+
+/// Result of a concentrated liquidity swap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConcentratedSwap {
+    /// Amount of the asked asset sent to the trader
+    pub return_amount: Uint128,
+    /// Part of the offer amount kept as fee by the liquidity providers
+    pub fee_amount: Uint128,
+    /// Square root price after the swap
+    pub sqrt_price: Uint256,
+    /// Liquidity in range after the swap
+    pub liquidity: Uint128,
+    /// Fee growth per unit of liquidity in the offered asset, with 128 fractional bits
+    pub fee_growth: Uint256,
+    pub ticks_crossed: u32,
+}
+
+/// Returns the square root price at `tick` of a pool priced at `1.0001^tick`, with 96
+/// fractional bits and the same rounding as Uniswap V3, which OraiDEX V3 ticks also follow.
+/// Pools with another tick spacing scheme, like Osmosis, must fill `TickInfo::sqrt_price`
+/// from their own tick prices.
+pub fn sqrt_price_at_tick(tick: i32) -> Result<Uint256, MathError> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return Err(MathError::InvalidTick { tick });
+    }
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 1 != 0 {
+        Uint256::from(TICK_RATIOS[0])
+    } else {
+        Uint256::one() << 128
+    };
+    for (bit, &tick_ratio) in TICK_RATIOS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << bit) != 0 {
+            ratio = Uint256::try_from((Uint512::from(ratio) * Uint512::from(tick_ratio)) >> 128)?;
+        }
+    }
+    if tick > 0 {
+        ratio = Uint256::MAX / ratio;
+    }
+
+    // Round up so that the price at a tick is never below it
+    let remainder = ratio % (Uint256::one() << 32);
+    Ok((ratio >> 32) + Uint256::from(!remainder.is_zero() as u8))
+}
+
+/// Returns the swap of `offer_amount` of asset 0 when `zero_for_one`, else of asset 1, crossing
+/// the ticks of the snapshot as the price moves.
+///
+/// `fee_rate` is taken on the input like Uniswap V3, OraiDEX V3 and Osmosis CL pools do, with
+/// the fee growth of every range weighted by its liquidity.
+pub fn compute_swap(
+    pool: &PoolSnapshot,
+    offer_amount: Uint128,
+    zero_for_one: bool,
+    fee_rate: Decimal,
+) -> Result<ConcentratedSwap, MathError> {
+    let mut amount_remaining = Uint256::from(offer_amount);
+    let mut return_amount = Uint256::zero();
+    let mut fee_amount = Uint256::zero();
+    let mut fee_growth = Uint256::zero();
+    let mut sqrt_price = pool.sqrt_price;
+    let mut tick = pool.tick;
+    let mut liquidity = pool.liquidity;
+    let mut ticks_crossed = 0;
+
+    while !amount_remaining.is_zero() {
+        let next_tick = if zero_for_one {
+            pool.ticks.iter().rev().find(|next| next.index <= tick)
+        } else {
+            pool.ticks.iter().find(|next| next.index > tick)
+        }
+        .ok_or(MathError::InsufficientLiquidity {})?;
+
+        let step = compute_swap_step(
+            sqrt_price,
+            next_tick.sqrt_price,
+            liquidity,
+            amount_remaining,
+            fee_rate,
+        )?;
+        amount_remaining = amount_remaining
+            .checked_sub(step.amount_in)?
+            .checked_sub(step.fee_amount)?;
+        return_amount = return_amount.checked_add(step.amount_out)?;
+        fee_amount = fee_amount.checked_add(step.fee_amount)?;
+        if !liquidity.is_zero() {
+            fee_growth = fee_growth.checked_add(mul_div(
+                step.fee_amount,
+                Uint256::one() << FEE_GROWTH_RESOLUTION,
+                liquidity.into(),
+                false,
+            )?)?;
+        }
+        sqrt_price = step.sqrt_price_next;
+
+        if sqrt_price != next_tick.sqrt_price {
+            break;
+        }
+        // Crossing downwards leaves the range the tick starts
+        let liquidity_net = if zero_for_one {
+            -next_tick.liquidity_net
+        } else {
+            next_tick.liquidity_net
+        };
+        liquidity = if liquidity_net < 0 {
+            liquidity.checked_sub(Uint128::new(liquidity_net.unsigned_abs()))?
+        } else {
+            liquidity.checked_add(Uint128::new(liquidity_net.unsigned_abs()))?
+        };
+        tick = if zero_for_one {
+            next_tick.index - 1
+        } else {
+            next_tick.index
+        };
+        ticks_crossed += 1;
+    }
+
+    Ok(ConcentratedSwap {
+        return_amount: return_amount.try_into()?,
+        fee_amount: fee_amount.try_into()?,
+        sqrt_price,
+        liquidity,
+        fee_growth,
+        ticks_crossed,
+    })
+}
+
+/// Returns the swap of up to `amount_remaining`, fee included, from `sqrt_price_current`
+/// towards `sqrt_price_target` with constant `liquidity`, rounding like Uniswap V3 `SwapMath`.
+pub fn compute_swap_step(
+    sqrt_price_current: Uint256,
+    sqrt_price_target: Uint256,
+    liquidity: Uint128,
+    amount_remaining: Uint256,
+    fee_rate: Decimal,
+) -> Result<SwapStep, MathError> {
+    let zero_for_one = sqrt_price_current >= sqrt_price_target;
+    let liquidity = Uint256::from(liquidity);
+    let fee_rate = Uint256::from(fee_rate.atomics());
+    let fee_complement = Uint256::from(Decimal::one().atomics()).checked_sub(fee_rate)?;
+    let denominator = Uint256::from(Decimal::one().atomics());
+
+    let amount_remaining_less_fee = mul_div(amount_remaining, fee_complement, denominator, false)?;
+    let amount_in_to_target = if zero_for_one {
+        amount_0_delta(sqrt_price_target, sqrt_price_current, liquidity, true)?
+    } else {
+        amount_1_delta(sqrt_price_current, sqrt_price_target, liquidity, true)?
+    };
+    let sqrt_price_next = if amount_remaining_less_fee >= amount_in_to_target {
+        sqrt_price_target
+    } else {
+        next_sqrt_price_from_input(
+            sqrt_price_current,
+            liquidity,
+            amount_remaining_less_fee,
+            zero_for_one,
+        )?
+    };
+
+    let reached_target = sqrt_price_next == sqrt_price_target;
+    let (amount_in, amount_out) = if zero_for_one {
+        (
+            amount_0_delta(sqrt_price_next, sqrt_price_current, liquidity, true)?,
+            amount_1_delta(sqrt_price_next, sqrt_price_current, liquidity, false)?,
+        )
+    } else {
+        (
+            amount_1_delta(sqrt_price_current, sqrt_price_next, liquidity, true)?,
+            amount_0_delta(sqrt_price_current, sqrt_price_next, liquidity, false)?,
+        )
+    };
+    let amount_in = if reached_target {
+        amount_in_to_target
+    } else {
+        amount_in
+    };
+    // The whole remainder goes to fees when the price stops within the range
+    let fee_amount = if reached_target {
+        mul_div(amount_in, fee_rate, fee_complement, true)?
+    } else {
+        amount_remaining.checked_sub(amount_in)?
+    };
+
+    Ok(SwapStep {
+        sqrt_price_next,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+/// Returns the amount of asset 0 between two square root prices: `L * (1/sqrt_a - 1/sqrt_b)`
+fn amount_0_delta(
+    sqrt_price_a: Uint256,
+    sqrt_price_b: Uint256,
+    liquidity: Uint256,
+    round_up: bool,
+) -> Result<Uint256, MathError> {
+    let (lower, upper) = sorted(sqrt_price_a, sqrt_price_b);
+    if lower.is_zero() {
+        return Err(MathError::InvalidSqrtPrice {});
+    }
+    let numerator = Uint512::from(liquidity) << RESOLUTION;
+    let amount = mul_div_512(numerator, (upper - lower).into(), upper.into(), round_up)?;
+    Ok(Uint256::try_from(div_512(amount, lower.into(), round_up)?)?)
+}
+
+/// Returns the amount of asset 1 between two square root prices: `L * (sqrt_b - sqrt_a)`
+fn amount_1_delta(
+    sqrt_price_a: Uint256,
+    sqrt_price_b: Uint256,
+    liquidity: Uint256,
+    round_up: bool,
+) -> Result<Uint256, MathError> {
+    let (lower, upper) = sorted(sqrt_price_a, sqrt_price_b);
+    mul_div(
+        liquidity,
+        upper - lower,
+        Uint256::one() << RESOLUTION,
+        round_up,
+    )
+}
+
+/// Returns the square root price after adding `amount_in`, rounded against the trader
+fn next_sqrt_price_from_input(
+    sqrt_price: Uint256,
+    liquidity: Uint256,
+    amount_in: Uint256,
+    zero_for_one: bool,
+) -> Result<Uint256, MathError> {
+    if liquidity.is_zero() {
+        return Err(MathError::InsufficientLiquidity {});
+    }
+    if zero_for_one {
+        // L * sqrt_p / (L + amount * sqrt_p), rounded up
+        let numerator = Uint512::from(liquidity) << RESOLUTION;
+        let denominator =
+            numerator.checked_add(Uint512::from(amount_in).checked_mul(sqrt_price.into())?)?;
+        Ok(Uint256::try_from(mul_div_512(
+            numerator,
+            sqrt_price.into(),
+            denominator,
+            true,
+        )?)?)
+    } else {
+        // sqrt_p + amount / L, rounded down
+        let quotient =
+            Uint256::try_from((Uint512::from(amount_in) << RESOLUTION) / Uint512::from(liquidity))?;
+        Ok(sqrt_price.checked_add(quotient)?)
+    }
+}
+
+fn sorted(a: Uint256, b: Uint256) -> (Uint256, Uint256) {
+    if a > b {
+        (b, a)
+    } else {
+        (a, b)
+    }
+}
+
+fn mul_div(
+    a: Uint256,
+    b: Uint256,
+    denominator: Uint256,
+    round_up: bool,
+) -> Result<Uint256, MathError> {
+    Ok(Uint256::try_from(mul_div_512(
+        a.into(),
+        b.into(),
+        denominator.into(),
+        round_up,
+    )?)?)
+}
+
+fn mul_div_512(
+    a: Uint512,
+    b: Uint512,
+    denominator: Uint512,
+    round_up: bool,
+) -> Result<Uint512, MathError> {
+    div_512(a.checked_mul(b)?, denominator, round_up)
+}
+
+fn div_512(a: Uint512, denominator: Uint512, round_up: bool) -> Result<Uint512, MathError> {
+    let quotient = a.checked_div(denominator)?;
+    if round_up && !a.checked_rem(denominator)?.is_zero() {
+        return Ok(quotient.checked_add(Uint512::one())?);
+    }
+    Ok(quotient)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    /// Square root price, target, liquidity, amount remaining and fee rate, then the amount in,
+    /// fee amount, amount out and next square root price
+    type StepVector = (
+        &'static str,
+        &'static str,
+        u128,
+        &'static str,
+        &'static str,
+        [&'static str; 4],
+    );
+
+    fn snapshot(tick: i32, liquidity: u128) -> PoolSnapshot {
+        let ticks = [
+            (-600, 1_000_000_000_000),
+            (-60, 500_000_000_000),
+            (60, -500_000_000_000),
+            (600, -1_000_000_000_000),
+        ]
+        .into_iter()
+        .map(|(index, liquidity_net)| TickInfo {
+            index,
+            sqrt_price: sqrt_price_at_tick(index).unwrap(),
+            liquidity_net,
+        })
+        .collect();
+        PoolSnapshot {
+            sqrt_price: sqrt_price_at_tick(tick).unwrap(),
+            tick,
+            liquidity: Uint128::new(liquidity),
+            ticks,
+        }
+    }
+
+    #[test]
+    fn sqrt_price_at_tick_matches_uniswap() {
+        assert_eq!(sqrt_price_at_tick(0).unwrap(), Uint256::one() << 96);
+        assert_eq!(
+            sqrt_price_at_tick(MIN_TICK).unwrap(),
+            Uint256::from(4_295_128_739u128)
+        );
+        assert_eq!(
+            sqrt_price_at_tick(MAX_TICK).unwrap(),
+            Uint256::from_str("1461446703485210103287273052203988822378723970342").unwrap()
+        );
+        assert_eq!(
+            sqrt_price_at_tick(MAX_TICK + 1).unwrap_err(),
+            MathError::InvalidTick { tick: MAX_TICK + 1 }
+        );
+    }
+
+    // `computeSwapStep` cases of the Uniswap v3-core `SwapMath.spec.ts`, with their fees in
+    // pips converted to rates
+    #[test]
+    fn compute_swap_step_matches_uniswap_swap_math_spec() {
+        let vectors: [StepVector; 4] = [
+            // Exact amount in that gets capped at price target in one for zero
+            (
+                "79228162514264337593543950336",
+                "79623317895830914510639640423",
+                2_000_000_000_000_000_000,
+                "1000000000000000000",
+                "0.0006",
+                [
+                    "9975124224178055",
+                    "5988667735148",
+                    "9925619580021728",
+                    "79623317895830914510639640423",
+                ],
+            ),
+            // Exact amount in that is fully spent in one for zero
+            (
+                "79228162514264337593543950336",
+                "250541448375047931186413801569",
+                2_000_000_000_000_000_000,
+                "1000000000000000000",
+                "0.0006",
+                [
+                    "999400000000000000",
+                    "600000000000000",
+                    "666399946655997866",
+                    "118818475322642227089037862318",
+                ],
+            ),
+            // Entire input amount taken as fee
+            (
+                "2413",
+                "79887613182836312",
+                1_985_041_575_832_132_834_610_021_537_970,
+                "10",
+                "0.001872",
+                ["0", "10", "0", "2413"],
+            ),
+            // Target price of 1 uses partial input amount
+            (
+                "2",
+                "1",
+                1,
+                "3915081100057732413702495386755767",
+                "0.000001",
+                [
+                    "39614081257132168796771975168",
+                    "39614120871253040049813",
+                    "0",
+                    "1",
+                ],
+            ),
+        ];
+        for (sqrt_price, target, liquidity, amount_remaining, fee_rate, expected) in vectors {
+            let step = compute_swap_step(
+                Uint256::from_str(sqrt_price).unwrap(),
+                Uint256::from_str(target).unwrap(),
+                Uint128::new(liquidity),
+                Uint256::from_str(amount_remaining).unwrap(),
+                fee_rate.parse().unwrap(),
+            )
+            .unwrap();
+            let [amount_in, fee_amount, amount_out, sqrt_price_next] =
+                expected.map(|amount| Uint256::from_str(amount).unwrap());
+            assert_eq!(
+                step,
+                SwapStep {
+                    sqrt_price_next,
+                    amount_in,
+                    amount_out,
+                    fee_amount,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn compute_swap_within_a_range_is_a_single_step() {
+        let pool = snapshot(0, 1_500_000_000_000);
+        let fee_rate = Decimal::permille(3);
+        let swap = compute_swap(&pool, Uint128::new(1_000_000_000), true, fee_rate).unwrap();
+        let step = compute_swap_step(
+            pool.sqrt_price,
+            pool.ticks[1].sqrt_price,
+            pool.liquidity,
+            Uint256::from(1_000_000_000u128),
+            fee_rate,
+        )
+        .unwrap();
+        assert_eq!(
+            swap.return_amount,
+            Uint128::try_from(step.amount_out).unwrap()
+        );
+        assert_eq!(swap.fee_amount, Uint128::try_from(step.fee_amount).unwrap());
+        assert_eq!(swap.sqrt_price, step.sqrt_price_next);
+        assert_eq!(swap.liquidity, pool.liquidity);
+        assert_eq!(swap.ticks_crossed, 0);
+    }
+
+    #[test]
+    fn compute_swap_crossing_a_tick_continues_with_its_liquidity() {
+        let pool = snapshot(0, 1_500_000_000_000);
+        let fee_rate = Decimal::permille(3);
+        let offer_amount = 10_000_000_000u128;
+        let swap = compute_swap(&pool, Uint128::new(offer_amount), false, fee_rate).unwrap();
+
+        // Up to tick 60, then past it with the liquidity of the range it ends
+        let first = compute_swap_step(
+            pool.sqrt_price,
+            pool.ticks[2].sqrt_price,
+            pool.liquidity,
+            offer_amount.into(),
+            fee_rate,
+        )
+        .unwrap();
+        assert_eq!(first.sqrt_price_next, pool.ticks[2].sqrt_price);
+        let liquidity = Uint128::new(1_000_000_000_000);
+        let second = compute_swap_step(
+            first.sqrt_price_next,
+            pool.ticks[3].sqrt_price,
+            liquidity,
+            Uint256::from(offer_amount) - first.amount_in - first.fee_amount,
+            fee_rate,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Uint256::from(swap.return_amount),
+            first.amount_out + second.amount_out
+        );
+        assert_eq!(
+            Uint256::from(swap.fee_amount),
+            first.fee_amount + second.fee_amount
+        );
+        assert_eq!(swap.sqrt_price, second.sqrt_price_next);
+        assert_eq!(swap.liquidity, liquidity);
+        assert_eq!(swap.ticks_crossed, 1);
+        // Every range adds its fee weighted by its own liquidity
+        let resolution = Uint256::one() << FEE_GROWTH_RESOLUTION;
+        assert_eq!(
+            swap.fee_growth,
+            first.fee_amount * resolution / Uint256::from(pool.liquidity)
+                + second.fee_amount * resolution / Uint256::from(liquidity)
+        );
+    }
+
+    #[test]
+    fn compute_swap_fails_past_last_tick() {
+        let err = compute_swap(
+            &snapshot(0, 1_500_000_000_000),
+            Uint128::new(50_000_000_000),
+            true,
+            "0.003".parse().unwrap(),
+        )
+        .unwrap_err();
+        assert_eq!(err, MathError::InsufficientLiquidity {});
+    }
+}
@@ -0,0 +1,44 @@
+use cosmwasm_std::{
+    CheckedFromRatioError, CheckedMultiplyFractionError, ConversionOverflowError,
+    DivideByZeroError, OverflowError,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum MathError {
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("{0}")]
+    DivideByZero(#[from] DivideByZeroError),
+
+    #[error("{0}")]
+    ConversionOverflow(#[from] ConversionOverflowError),
+
+    #[error("{0}")]
+    CheckedFromRatio(#[from] CheckedFromRatioError),
+
+    #[error("{0}")]
+    CheckedMultiplyFraction(#[from] CheckedMultiplyFractionError),
+
+    #[error("Pool has no reserve of the offered or asked asset")]
+    EmptyPool {},
+
+    #[error("Asset index {index} out of range for a pool of {n_coins} assets")]
+    InvalidAssetIndex { index: usize, n_coins: usize },
+
+    #[error("Expected a precision for each of the {n_coins} assets, got {n_precisions}")]
+    InvalidPrecisions { n_precisions: usize, n_coins: usize },
+
+    #[error("Tick {tick} out of range")]
+    InvalidTick { tick: i32 },
+
+    #[error("Square root price must be positive")]
+    InvalidSqrtPrice {},
+
+    #[error("Not enough liquidity in the ticks of the snapshot")]
+    InsufficientLiquidity {},
+
+    #[error("Stableswap invariant did not converge")]
+    NoConvergence {},
+}
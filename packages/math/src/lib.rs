@@ -0,0 +1,4 @@
+pub mod concentrated;
+pub mod error;
+pub mod stableswap;
+pub mod xyk;
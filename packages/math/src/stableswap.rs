@@ -0,0 +1,345 @@
+use cosmwasm_std::{Decimal, Uint128, Uint256};
+
+use crate::error::MathError;
+
+/// Newton iterations before giving up on the invariant, as in the Curve contracts
+const MAX_ITERATIONS: usize = 255;
+
+/// Result of a stableswap swap, all amounts in the asked asset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StableSwap {
+    /// Amount sent to the trader, after fee
+    pub return_amount: Uint128,
+    /// Amount kept by the pool
+    pub fee_amount: Uint128,
+}
+
+/// Returns the swap of `offer_amount` of the asset at `offer_index` for the one at `ask_index`
+/// on a Curve-style pool holding `balances`, whose assets have the decimals in `precisions`.
+///
+/// Balances are scaled up to the greatest precision of the pool before solving the invariant,
+/// and the return is floored back to the precision of the asked asset, as Astroport stable
+/// pairs do. `amp` is the amplification as configured on the pool, which is multiplied by the
+/// number of assets to get `A * n^n`. The fee is floored.
+pub fn compute_swap(
+    amp: u64,
+    balances: &[Uint128],
+    precisions: &[u8],
+    offer_index: usize,
+    ask_index: usize,
+    offer_amount: Uint128,
+    fee_rate: Decimal,
+) -> Result<StableSwap, MathError> {
+    let n_coins = balances.len();
+    for index in [offer_index, ask_index] {
+        if index >= n_coins || offer_index == ask_index {
+            return Err(MathError::InvalidAssetIndex { index, n_coins });
+        }
+    }
+    if precisions.len() != n_coins {
+        return Err(MathError::InvalidPrecisions {
+            n_precisions: precisions.len(),
+            n_coins,
+        });
+    }
+    if balances[offer_index].is_zero() || balances[ask_index].is_zero() {
+        return Err(MathError::EmptyPool {});
+    }
+    let max_precision = precisions.iter().copied().max().unwrap_or_default();
+    let scales = precisions
+        .iter()
+        .map(|&precision| Uint256::from(10u8).checked_pow((max_precision - precision).into()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let xp = balances
+        .iter()
+        .zip(&scales)
+        .map(|(&balance, &scale)| Uint256::from(balance).checked_mul(scale))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let new_offer_balance = xp[offer_index]
+        .checked_add(Uint256::from(offer_amount).checked_mul(scales[offer_index])?)?;
+    let new_ask_balance = compute_y(amp, &xp, offer_index, ask_index, new_offer_balance)?;
+    let return_amount = xp[ask_index]
+        .checked_sub(new_ask_balance)?
+        .checked_div(scales[ask_index])?;
+    let return_amount = Uint128::try_from(return_amount)?;
+    let fee_amount = return_amount.checked_mul_floor(fee_rate)?;
+
+    Ok(StableSwap {
+        return_amount: return_amount.checked_sub(fee_amount)?,
+        fee_amount,
+    })
+}
+
+/// Returns the invariant `D` of a pool holding `xp`, solving
+/// `A * n^n * sum(x) + D = A * n^n * D + D^(n+1) / (n^n * prod(x))` by Newton's method.
+pub fn compute_d(amp: u64, xp: &[Uint256]) -> Result<Uint256, MathError> {
+    let n_coins = Uint256::from(xp.len() as u64);
+    let sum = xp
+        .iter()
+        .try_fold(Uint256::zero(), |sum, &x| sum.checked_add(x))?;
+    if sum.is_zero() {
+        return Ok(Uint256::zero());
+    }
+    let ann = Uint256::from(amp).checked_mul(n_coins)?;
+
+    let mut d = sum;
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_product = d;
+        for &x in xp {
+            d_product = d_product
+                .checked_mul(d)?
+                .checked_div(x.checked_mul(n_coins)?)?;
+        }
+        let d_prev = d;
+        let numerator = ann
+            .checked_mul(sum)?
+            .checked_add(d_product.checked_mul(n_coins)?)?
+            .checked_mul(d)?;
+        let denominator = ann
+            .checked_sub(Uint256::one())?
+            .checked_mul(d)?
+            .checked_add(
+                n_coins
+                    .checked_add(Uint256::one())?
+                    .checked_mul(d_product)?,
+            )?;
+        d = numerator.checked_div(denominator)?;
+        if d.abs_diff(d_prev) <= Uint256::one() {
+            return Ok(d);
+        }
+    }
+    Err(MathError::NoConvergence {})
+}
+
+/// Returns the balance of the asset at `ask_index` that keeps the invariant of a pool holding
+/// `xp` once the asset at `offer_index` reaches `new_offer_balance`.
+pub fn compute_y(
+    amp: u64,
+    xp: &[Uint256],
+    offer_index: usize,
+    ask_index: usize,
+    new_offer_balance: Uint256,
+) -> Result<Uint256, MathError> {
+    let n_coins = Uint256::from(xp.len() as u64);
+    let d = compute_d(amp, xp)?;
+    let ann = Uint256::from(amp).checked_mul(n_coins)?;
+
+    let mut c = d;
+    let mut sum = Uint256::zero();
+    for (index, &balance) in xp.iter().enumerate() {
+        let x = if index == offer_index {
+            new_offer_balance
+        } else if index != ask_index {
+            balance
+        } else {
+            continue;
+        };
+        sum = sum.checked_add(x)?;
+        c = c.checked_mul(d)?.checked_div(x.checked_mul(n_coins)?)?;
+    }
+    c = c.checked_mul(d)?.checked_div(ann.checked_mul(n_coins)?)?;
+    let b = sum.checked_add(d.checked_div(ann)?)?;
+
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        y = y.checked_mul(y)?.checked_add(c)?.checked_div(
+            Uint256::from(2u8)
+                .checked_mul(y)?
+                .checked_add(b)?
+                .checked_sub(d)?,
+        )?;
+        if y.abs_diff(y_prev) <= Uint256::one() {
+            return Ok(y);
+        }
+    }
+    Err(MathError::NoConvergence {})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xp(balances: &[u128]) -> Vec<Uint256> {
+        balances.iter().map(|&balance| balance.into()).collect()
+    }
+
+    fn swap(
+        amp: u64,
+        balances: &[u128],
+        precisions: &[u8],
+        offer_amount: u128,
+        fee_rate: &str,
+    ) -> StableSwap {
+        let balances: Vec<Uint128> = balances.iter().map(|&balance| balance.into()).collect();
+        compute_swap(
+            amp,
+            &balances,
+            precisions,
+            0,
+            1,
+            offer_amount.into(),
+            fee_rate.parse().unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn compute_d_of_balanced_pool_is_the_sum() {
+        for amp in [1, 100, 5_000] {
+            let d = compute_d(amp, &xp(&[1_000_000_000_000; 3])).unwrap();
+            assert_eq!(d, Uint256::from(3_000_000_000_000u128));
+        }
+    }
+
+    #[test]
+    fn compute_d_of_imbalanced_pool_is_below_the_sum() {
+        let balances = [1_000_000_000_000, 2_000_000_000_000];
+        let low_amp = compute_d(1, &xp(&balances)).unwrap();
+        let high_amp = compute_d(1_000, &xp(&balances)).unwrap();
+        // Between the constant product invariant 2 * sqrt(x * y) and the constant sum
+        assert!(low_amp > Uint256::from(2_828_427_124_746u128));
+        assert!(low_amp < high_amp);
+        assert!(high_amp < Uint256::from(3_000_000_000_000u128));
+    }
+
+    #[test]
+    fn compute_swap_on_balanced_pool_returns_close_to_one_for_one() {
+        let swap = swap(
+            1_000,
+            &[1_000_000_000_000, 1_000_000_000_000],
+            &[6, 6],
+            1_000_000,
+            "0",
+        );
+        assert!(swap.return_amount <= Uint128::new(1_000_000));
+        assert!(swap.return_amount >= Uint128::new(999_999));
+    }
+
+    #[test]
+    fn compute_swap_keeps_the_invariant_within_rounding() {
+        let balances = [1_000_000_000_000u128, 2_000_000_000_000];
+        let swap = swap(100, &balances, &[6, 6], 100_000_000_000, "0");
+        let before = compute_d(100, &xp(&balances)).unwrap();
+        let after = compute_d(
+            100,
+            &xp(&[
+                balances[0] + 100_000_000_000,
+                balances[1] - swap.return_amount.u128(),
+            ]),
+        )
+        .unwrap();
+        // Newton's method stops within one unit of the invariant
+        assert!(after.abs_diff(before) <= Uint256::one());
+    }
+
+    #[test]
+    fn compute_swap_normalizes_precisions() {
+        let same_precision = swap(
+            100,
+            &[1_000_000_000_000, 2_000_000_000_000],
+            &[6, 6],
+            100_000_000_000,
+            "0.0004",
+        );
+        // The same pool with the asked asset in 18 decimals
+        let mixed_precision = swap(
+            100,
+            &[1_000_000_000_000, 2_000_000_000_000_000_000_000_000],
+            &[6, 18],
+            100_000_000_000,
+            "0.0004",
+        );
+        let scale = 1_000_000_000_000;
+        let gross = same_precision.return_amount + same_precision.fee_amount;
+        let mixed_gross = mixed_precision.return_amount + mixed_precision.fee_amount;
+        // Up to the units Newton's method stops within, at 6 decimals
+        assert!(gross.u128().abs_diff(mixed_gross.u128() / scale) <= 2);
+
+        // Offering the 18 decimals asset is floored back to 6 decimals
+        let balances: Vec<Uint128> = [2_000_000_000_000_000_000_000_000u128, 1_000_000_000_000]
+            .map(Uint128::new)
+            .to_vec();
+        let swap = compute_swap(
+            100,
+            &balances,
+            &[18, 6],
+            0,
+            1,
+            Uint128::new(999_999_999_999),
+            Decimal::zero(),
+        )
+        .unwrap();
+        assert_eq!(swap.return_amount, Uint128::zero());
+    }
+
+    #[test]
+    fn compute_swap_floors_fee() {
+        let swap = swap(
+            1_000,
+            &[1_000_000_000_000, 1_000_000_000_000],
+            &[6, 6],
+            1_000_000,
+            "0.0005",
+        );
+        let gross = swap.return_amount + swap.fee_amount;
+        assert_eq!(swap.fee_amount, gross.mul_floor(Decimal::bps(5)));
+    }
+
+    #[test]
+    fn compute_swap_rejects_invalid_indexes() {
+        let balances = [Uint128::new(1_000), Uint128::new(1_000)];
+        let err = compute_swap(
+            100,
+            &balances,
+            &[6, 6],
+            0,
+            2,
+            Uint128::new(10),
+            Decimal::zero(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            MathError::InvalidAssetIndex {
+                index: 2,
+                n_coins: 2
+            }
+        );
+        let err = compute_swap(
+            100,
+            &balances,
+            &[6, 6],
+            1,
+            1,
+            Uint128::new(10),
+            Decimal::zero(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            MathError::InvalidAssetIndex {
+                index: 1,
+                n_coins: 2
+            }
+        );
+        let err = compute_swap(
+            100,
+            &balances,
+            &[6],
+            0,
+            1,
+            Uint128::new(10),
+            Decimal::zero(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            MathError::InvalidPrecisions {
+                n_precisions: 1,
+                n_coins: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use cosmwasm_std::{Decimal, Decimal256, Uint128, Uint256};
+
+use crate::error::MathError;
+
+/// Result of a constant product swap, all amounts in the asked asset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XykSwap {
+    /// Amount sent to the trader, after commission
+    pub return_amount: Uint128,
+    /// Difference between the spot price quote and the return before commission
+    pub spread_amount: Uint128,
+    /// Amount kept by the pool
+    pub commission_amount: Uint128,
+}
+
+/// Returns the swap of `offer_amount` on a pool holding `offer_pool` and `ask_pool`, with the
+/// same rounding as Astroport and OraiDEX pairs: the return before commission is floored after
+/// dividing the constant product with 18 decimals, then the commission is floored.
+pub fn compute_swap(
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    offer_amount: Uint128,
+    commission_rate: Decimal,
+) -> Result<XykSwap, MathError> {
+    if offer_pool.is_zero() || ask_pool.is_zero() {
+        return Err(MathError::EmptyPool {});
+    }
+    let offer_pool = Uint256::from(offer_pool);
+    let ask_pool = Uint256::from(ask_pool);
+    let offer_amount = Uint256::from(offer_amount);
+
+    // ask_amount = ask_pool - cp / (offer_pool + offer_amount)
+    let cp = offer_pool.checked_mul(ask_pool)?;
+    let return_amount = Decimal256::from_ratio(ask_pool, 1u8)
+        .checked_sub(Decimal256::checked_from_ratio(
+            cp,
+            offer_pool.checked_add(offer_amount)?,
+        )?)?
+        .to_uint_floor();
+    let spread_amount = offer_amount
+        .checked_mul_floor(Decimal256::checked_from_ratio(ask_pool, offer_pool)?)?
+        .saturating_sub(return_amount);
+    let commission_amount = return_amount.checked_mul_floor(Decimal256::from(commission_rate))?;
+
+    Ok(XykSwap {
+        return_amount: return_amount.checked_sub(commission_amount)?.try_into()?,
+        spread_amount: spread_amount.try_into()?,
+        commission_amount: commission_amount.try_into()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Simulation queries answered by the terraswap-pair 0.2.0 build in `tests/bin` (sha256
+    // 02518c45...0e64) over pools holding the listed reserves, as `[return_amount, spread_amount,
+    // commission_amount]`. That pair rounds its 0.3% commission up where Astroport floors it, so
+    // the return before commission and the spread are compared.
+    #[test]
+    fn compute_swap_matches_terraswap_pair_simulation() {
+        let vectors: [(u128, u128, u128, [u128; 3]); 7] = [
+            (1_000_000, 1_000_000, 1_000, [996, 1, 3]),
+            (
+                2_000_000_000,
+                3_000_000_000_000,
+                123_456_789,
+                [173_895_347_346, 10_766_580_344, 523_255_810],
+            ),
+            (
+                1_000_000_000_000,
+                7_000_000_000,
+                100_000_000_000,
+                [634_454_545, 63_636_364, 1_909_091],
+            ),
+            (500, 500, 1_000_000, [497, 999_501, 2]),
+            (
+                123_456_789_012,
+                987_654_321_098,
+                55_555_555,
+                [442_911_800, 199_910, 1_332_734],
+            ),
+            (7_777_777, 3_333_333_333, 2_222, [949_156, 272, 2_857]),
+            (
+                1_000_000_000_000_000,
+                2_500_000_000,
+                9_999_999_999_999,
+                [24_678_217, 247_524, 74_258],
+            ),
+        ];
+        for (
+            offer_pool,
+            ask_pool,
+            offer_amount,
+            [return_amount, spread_amount, commission_amount],
+        ) in vectors
+        {
+            let swap = compute_swap(
+                offer_pool.into(),
+                ask_pool.into(),
+                offer_amount.into(),
+                Decimal::permille(3),
+            )
+            .unwrap();
+            assert_eq!(
+                (swap.return_amount + swap.commission_amount).u128(),
+                return_amount + commission_amount
+            );
+            assert_eq!(swap.spread_amount.u128(), spread_amount);
+        }
+    }
+
+    #[test]
+    fn compute_swap_floors_commission() {
+        let swap = compute_swap(
+            Uint128::new(1_000_000),
+            Uint128::new(1_000_000),
+            Uint128::new(1_000),
+            Decimal::permille(3),
+        )
+        .unwrap();
+        // 0.3% of the 999 returned before commission
+        assert_eq!(swap.commission_amount, Uint128::new(2));
+        assert_eq!(swap.return_amount, Uint128::new(997));
+    }
+
+    #[test]
+    fn compute_swap_rejects_empty_pool() {
+        let err = compute_swap(
+            Uint128::zero(),
+            Uint128::new(1_000),
+            Uint128::new(10),
+            Decimal::zero(),
+        )
+        .unwrap_err();
+        assert_eq!(err, MathError::EmptyPool {});
+    }
+}
@@ -35,4 +35,21 @@ impl AssetInfo {
             }),
         }
     }
+
+    /// Returns a CW20 or bank burn message.
+    pub fn to_burn_msg(&self, amount: Uint128) -> CosmosMsg {
+        match self {
+            AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Burn { amount }).unwrap(),
+                funds: vec![],
+            }),
+            AssetInfo::NativeToken { denom } => CosmosMsg::Bank(BankMsg::Burn {
+                amount: vec![Coin {
+                    denom: denom.clone(),
+                    amount,
+                }],
+            }),
+        }
+    }
 }
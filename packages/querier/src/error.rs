@@ -0,0 +1,71 @@
+use std::fmt;
+
+use cosmwasm_std::StdError;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum QuerierError {
+    #[error("{query} query to {addr} failed: {source}")]
+    QueryFailed {
+        query: QueryKind,
+        addr: String,
+        source: StdError,
+    },
+
+    #[error("{query} query to {addr} returned nothing")]
+    EmptyResponse { query: QueryKind, addr: String },
+
+    #[error("{query} query to {addr} returned no amount for asset index {index}")]
+    MissingAssetIndex {
+        query: QueryKind,
+        addr: String,
+        index: u32,
+    },
+}
+
+/// The query that failed, so callers can tell a wrong interface from a chain-level error
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryKind {
+    Simulation,
+    Pool,
+    Config,
+    Precision,
+    HelixSimulation,
+    AstrovaultStableSimulation,
+    AstrovaultHybridSimulation,
+    NativeBalance { denom: String },
+    TokenBalance,
+    NftTokens,
+}
+
+impl fmt::Display for QueryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let query = match self {
+            QueryKind::Simulation => "Simulation",
+            QueryKind::Pool => "Pool",
+            QueryKind::Config => "Config",
+            QueryKind::Precision => "Precision",
+            QueryKind::HelixSimulation => "Helix simulation",
+            QueryKind::AstrovaultStableSimulation => "Astrovault stable simulation",
+            QueryKind::AstrovaultHybridSimulation => "Astrovault hybrid simulation",
+            QueryKind::NativeBalance { denom } => return write!(f, "Native balance of {denom}"),
+            QueryKind::TokenBalance => "Token balance",
+            QueryKind::NftTokens => "NFT tokens",
+        };
+        f.write_str(query)
+    }
+}
+
+impl QuerierError {
+    pub fn query_failed(
+        query: QueryKind,
+        addr: impl Into<String>,
+    ) -> impl FnOnce(StdError) -> Self {
+        let addr = addr.into();
+        move |source| QuerierError::QueryFailed {
+            query,
+            addr,
+            source,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use cosmwasm_std::Uint128;
+use cosmwasm_std::{Binary, Decimal, Uint128};
 use models::asset::Asset;
 use models::asset_info::AssetInfo;
 use schemars::JsonSchema;
@@ -10,13 +10,38 @@ use serde::{Deserialize, Serialize};
 #[serde(rename_all = "snake_case")]
 pub enum PairQueryMsg {
     Pair {},
+    Pool {},
     Simulation { offer_asset: Asset },
+    Config {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct PairInfo {
     pub asset_infos: Vec<AssetInfo>,
+    /// Reported by Astroport pairs only
+    pub pair_type: Option<AstroportPairType>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AstroportPairType {
+    Xyk {},
+    Stable {},
+    Custom(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PairConfigResponse {
+    /// Pair type specific parameters, e.g. `AstroportStableParams` for stable pairs
+    pub params: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AstroportStableParams {
+    pub amp: Decimal,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -25,6 +50,13 @@ pub struct PairSimulationResponse {
     pub return_amount: Uint128,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PoolResponse {
+    pub assets: Vec<Asset>,
+    pub total_share: Uint128,
+}
+
 // ********** CW20 ************* //
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -39,6 +71,24 @@ pub struct Cw20BalanceResponse {
     pub balance: Uint128,
 }
 
+// ********** CW721 ************* //
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw721QueryMsg {
+    Tokens {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Cw721TokensResponse {
+    pub tokens: Vec<String>,
+}
+
 // ********** Helix ************* //
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -1,15 +1,24 @@
-use cosmwasm_std::{Addr, ContractInfoResponse, QuerierWrapper, StdResult, Uint128};
+use cosmwasm_std::{
+    from_json, Addr, ContractInfoResponse, Decimal, QuerierWrapper, StdResult, Uint128,
+};
 use models::asset::Asset;
 use models::asset_info::AssetInfo;
 
+use crate::error::{QuerierError, QueryKind};
 use crate::msg::{
-    QueryAstrovault, QueryAstrovaultHybrid, QueryAstrovaultHybridSimulationResponse, QueryAstrovaultResponse, QueryAstrovaultStable, QueryAstrovaultStableSimulationResponse, QueryHelix, QueryOraiDexV2, QueryOraiDexV2PairResponse
+    QueryAstrovault, QueryAstrovaultHybrid, QueryAstrovaultHybridSimulationResponse,
+    QueryAstrovaultResponse, QueryAstrovaultStable, QueryAstrovaultStableSimulationResponse,
+    QueryHelix, QueryOraiDexV2, QueryOraiDexV2PairResponse,
 };
 
 use super::msg::{
-    Cw20BalanceResponse, Cw20QueryMsg, PairInfo, PairQueryMsg, PairSimulationResponse,
+    AstroportStableParams, Cw20BalanceResponse, Cw20QueryMsg, Cw721QueryMsg, Cw721TokensResponse,
+    PairConfigResponse, PairInfo, PairQueryMsg, PairSimulationResponse, PoolResponse,
 };
 
+/// Storage namespace of the asset precisions of Astroport stable pairs
+const ASTROPORT_PRECISIONS_NAMESPACE: &[u8] = b"precisions";
+
 pub fn query_pair_info(querier: &QuerierWrapper, addr: &Addr) -> StdResult<PairInfo> {
     querier.query_wasm_smart(addr, &PairQueryMsg::Pair {})
 }
@@ -47,12 +56,58 @@ pub fn query_simulation(
     querier: &QuerierWrapper,
     addr: &Addr,
     offer_asset: Asset,
-) -> StdResult<Uint128> {
-    Ok(querier
+) -> Result<Uint128, QuerierError> {
+    querier
         .query_wasm_smart(addr, &PairQueryMsg::Simulation { offer_asset })
-        .map_or(Uint128::zero(), |res: PairSimulationResponse| {
-            res.return_amount
-        }))
+        .map(|res: PairSimulationResponse| res.return_amount)
+        .map_err(QuerierError::query_failed(QueryKind::Simulation, addr))
+}
+
+pub fn query_pool(querier: &QuerierWrapper, addr: &Addr) -> Result<PoolResponse, QuerierError> {
+    querier
+        .query_wasm_smart(addr, &PairQueryMsg::Pool {})
+        .map_err(QuerierError::query_failed(QueryKind::Pool, addr))
+}
+
+/// Returns the current amplification of an Astroport stable pair, from its config parameters.
+pub fn query_astroport_stable_amp(
+    querier: &QuerierWrapper,
+    addr: &Addr,
+) -> Result<Decimal, QuerierError> {
+    let config: PairConfigResponse = querier
+        .query_wasm_smart(addr, &PairQueryMsg::Config {})
+        .map_err(QuerierError::query_failed(QueryKind::Config, addr))?;
+    let params = config.params.ok_or(QuerierError::EmptyResponse {
+        query: QueryKind::Config,
+        addr: addr.to_string(),
+    })?;
+    from_json(params)
+        .map(|params: AstroportStableParams| params.amp)
+        .map_err(QuerierError::query_failed(QueryKind::Config, addr))
+}
+
+/// Returns the decimals an Astroport stable pair scales `asset_info` with. The pair does not
+/// expose them through a smart query, so they are read from its `precisions` map.
+pub fn query_astroport_precision(
+    querier: &QuerierWrapper,
+    addr: &Addr,
+    asset_info: &AssetInfo,
+) -> Result<u8, QuerierError> {
+    let namespace_length = ASTROPORT_PRECISIONS_NAMESPACE.len() as u16;
+    let key = [
+        namespace_length.to_be_bytes().as_slice(),
+        ASTROPORT_PRECISIONS_NAMESPACE,
+        asset_info.id().as_bytes(),
+    ]
+    .concat();
+    let precision = querier
+        .query_wasm_raw(addr, key)
+        .map_err(QuerierError::query_failed(QueryKind::Precision, addr))?
+        .ok_or(QuerierError::EmptyResponse {
+            query: QueryKind::Precision,
+            addr: addr.to_string(),
+        })?;
+    from_json(precision).map_err(QuerierError::query_failed(QueryKind::Precision, addr))
 }
 
 pub fn query_helix_simulation(
@@ -60,8 +115,8 @@ pub fn query_helix_simulation(
     addr: &Addr,
     offer_asset: Asset,
     market_id: String,
-) -> StdResult<Uint128> {
-    Ok(querier
+) -> Result<Uint128, QuerierError> {
+    querier
         .query_wasm_smart(
             addr,
             &QueryHelix::Simulation {
@@ -69,9 +124,8 @@ pub fn query_helix_simulation(
                 market_id,
             },
         )
-        .map_or(Uint128::zero(), |res: PairSimulationResponse| {
-            res.return_amount
-        }))
+        .map(|res: PairSimulationResponse| res.return_amount)
+        .map_err(QuerierError::query_failed(QueryKind::HelixSimulation, addr))
 }
 
 pub fn query_astrovault_stable_simulation(
@@ -80,8 +134,8 @@ pub fn query_astrovault_stable_simulation(
     amount: Uint128,
     swap_from_asset_index: u32,
     swap_to_asset_index: u32,
-) -> StdResult<Uint128> {
-    Ok(querier
+) -> Result<Uint128, QuerierError> {
+    let res: QueryAstrovaultStableSimulationResponse = querier
         .query_wasm_smart(
             addr,
             &QueryAstrovaultStable::SwapSimulation {
@@ -90,16 +144,19 @@ pub fn query_astrovault_stable_simulation(
                 swap_to_asset_index,
             },
         )
-        .map_or(
-            Uint128::zero(),
-            |res: QueryAstrovaultStableSimulationResponse| {
-                let index = usize::try_from(swap_to_asset_index).unwrap();
-                res.swap_to_assets_amount
-                    .get(index)
-                    .unwrap_or(&Uint128::zero())
-                    .to_owned()
-            },
-        ))
+        .map_err(QuerierError::query_failed(
+            QueryKind::AstrovaultStableSimulation,
+            addr,
+        ))?;
+    let index = usize::try_from(swap_to_asset_index).unwrap();
+    res.swap_to_assets_amount
+        .get(index)
+        .copied()
+        .ok_or(QuerierError::MissingAssetIndex {
+            query: QueryKind::AstrovaultStableSimulation,
+            addr: addr.to_string(),
+            index: swap_to_asset_index,
+        })
 }
 
 pub fn query_astrovault_hybrid_simulation(
@@ -107,8 +164,8 @@ pub fn query_astrovault_hybrid_simulation(
     addr: &Addr,
     amount: Uint128,
     swap_from_asset_index: u32,
-) -> StdResult<Uint128> {
-    Ok(querier
+) -> Result<Uint128, QuerierError> {
+    querier
         .query_wasm_smart(
             addr,
             &QueryAstrovaultHybrid::SwapSimulation {
@@ -116,19 +173,27 @@ pub fn query_astrovault_hybrid_simulation(
                 swap_from_asset_index,
             },
         )
-        .map_or(Uint128::zero(), |res: QueryAstrovaultHybridSimulationResponse| {
-            res.to_amount_minus_fee
-        }))
+        .map(|res: QueryAstrovaultHybridSimulationResponse| res.to_amount_minus_fee)
+        .map_err(QuerierError::query_failed(
+            QueryKind::AstrovaultHybridSimulation,
+            addr,
+        ))
 }
 
 pub fn query_native_balance(
     querier: &QuerierWrapper,
     addr: &Addr,
     denom: &String,
-) -> StdResult<Uint128> {
-    Ok(querier
+) -> Result<Uint128, QuerierError> {
+    querier
         .query_balance(addr, denom)
-        .map_or(Uint128::zero(), |coin| coin.amount))
+        .map(|coin| coin.amount)
+        .map_err(QuerierError::query_failed(
+            QueryKind::NativeBalance {
+                denom: denom.clone(),
+            },
+            addr,
+        ))
 }
 
 pub fn query_contract_info(
@@ -142,22 +207,42 @@ pub fn query_token_balance(
     querier: &QuerierWrapper,
     addr: &Addr,
     token: &String,
-) -> StdResult<Uint128> {
-    Ok(querier
+) -> Result<Uint128, QuerierError> {
+    querier
         .query_wasm_smart(
             token,
             &Cw20QueryMsg::Balance {
                 address: addr.into(),
             },
         )
-        .map_or(Uint128::zero(), |res: Cw20BalanceResponse| res.balance))
+        .map(|res: Cw20BalanceResponse| res.balance)
+        .map_err(QuerierError::query_failed(QueryKind::TokenBalance, token))
+}
+
+/// Returns whether `owner` holds at least one token of the cw721 `collection`.
+pub fn query_nft_owner(
+    querier: &QuerierWrapper,
+    owner: &Addr,
+    collection: &Addr,
+) -> Result<bool, QuerierError> {
+    querier
+        .query_wasm_smart(
+            collection,
+            &Cw721QueryMsg::Tokens {
+                owner: owner.into(),
+                start_after: None,
+                limit: Some(1),
+            },
+        )
+        .map(|res: Cw721TokensResponse| !res.tokens.is_empty())
+        .map_err(QuerierError::query_failed(QueryKind::NftTokens, collection))
 }
 
 pub fn query_balance(
     querier: &QuerierWrapper,
     addr: &Addr,
     asset_info: &AssetInfo,
-) -> StdResult<Uint128> {
+) -> Result<Uint128, QuerierError> {
     match &asset_info {
         AssetInfo::NativeToken { denom } => query_native_balance(querier, addr, denom),
         AssetInfo::Token { contract_addr } => {